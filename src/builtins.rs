@@ -1,28 +1,216 @@
-use std::env::{set_current_dir, set_var, var, current_dir};
+use std::collections::BTreeMap;
+use std::env::{set_var, var};
 use std::error::Error;
-use std::io::{Write, stdout};
-use std::path::Path;
-use std::str::SplitWhitespace;
-use crate::execute::ExecutionResult;
-use crate::utils::parse_single_argument;
+use std::path::{Component, Path, PathBuf};
+use std::mem::MaybeUninit;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::config::config;
+use crate::diagnostics::define_function;
+use crate::directories::{set_working_directory, stack_entry, working_directory};
+use crate::execute::{ExecutionResult, execute};
+use crate::jump::record_directory;
+use crate::parse_command::KEYWORDS;
+use crate::signals::is_interactive;
+use crate::utils::{check_variable, last_exit_status, parse_single_argument};
+
+/// Names of built-in commands, including `time` keyword
+pub const BUILTINS: [&str; 16] = ["alias", "cd", "complete", "dirs", "exit", "fn", "history", "hook", "j", "jobs", "popd", "pushd", "set", "time", "trap", "z"];
+
+/// Builtin registered by the program that embeds the shell,
+/// called with the expanded arguments
+pub type CustomBuiltin = Arc<dyn Fn(&[String]) -> ExecutionResult + Send + Sync>;
+
+static CUSTOM_BUILTINS: Mutex<BTreeMap<String, CustomBuiltin>> = Mutex::new(BTreeMap::new());
+
+/// Registers the builtin, which replaces a custom
+/// builtin with the same name, but not a built-in one
+pub fn register_builtin(name: &str, builtin: CustomBuiltin) -> Result<(), Box<dyn Error>> {
+    if name.is_empty() || name.starts_with(['@', '$', '\'', '&']) || name.contains(char::is_whitespace) || KEYWORDS.contains(&name) {
+        return Err(Box::<dyn Error>::from(format!("{name}: invalid builtin name")));
+    }
+
+    if BUILTINS.contains(&name) {
+        return Err(Box::<dyn Error>::from(format!("{name}: builtin already exists")));
+    }
+
+    CUSTOM_BUILTINS.lock().unwrap().insert(name.to_string(), builtin);
+
+    Ok(())
+}
+
+pub fn custom_builtin(name: &str) -> Option<CustomBuiltin> {
+    CUSTOM_BUILTINS.lock().unwrap().get(name).cloned()
+}
+
+pub fn is_builtin(name: &str) -> bool {
+    BUILTINS.contains(&name) || CUSTOM_BUILTINS.lock().unwrap().contains_key(name)
+}
+
+/// Names of built-in and custom builtins
+pub fn builtin_names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTINS.iter().map(|name| name.to_string()).collect();
+    names.extend(CUSTOM_BUILTINS.lock().unwrap().keys().cloned());
+
+    names
+}
 
 /// Implementation of shell built-in `cd` function
-/// 
+///
 /// Changes current process directory
-/// 
+///
 /// If no argument provided, changes directory to $HOME.
-/// This also happens if argument is '~'
-pub fn change_directory(directory: Option<&str>) -> ExecutionResult {
-    let path = match directory {
-        None => var("HOME").expect("rsh: unexpected internal error"),
-        Some("~") => var("HOME").expect("rsh: unexpected internal error"),
-        Some(path) => String::from(path)
+/// This also happens if argument is '~'. Other arguments:
+///
+/// * `-` changes to the previous directory (`$OLDPWD`)
+/// * `~N` changes to `N`-th entry of the directory stack
+/// * relative path that doesn't start with `.` is searched
+///   in `:`-separated directories of `$CDPATH`
+///
+/// Options `-L` (default) and `-P` keep symbolic links in
+/// the path or resolve them, see `directories` module
+pub fn change_directory(args: &[String]) -> ExecutionResult {
+    let mut physical = false;
+    let mut args = args.iter().peekable();
+
+    while let Some(option) = args.next_if(|arg| arg.len() > 1 && arg.starts_with('-') && !arg[1..].starts_with(char::is_numeric)) {
+        match option.as_str() {
+            "-P" => physical = true,
+            "-L" => physical = false,
+            "--" => break,
+            option => return ExecutionResult::Error(Box::<dyn Error>::from(format!("cd: invalid option: {option}")))
+        }
+    }
+
+    let directory = args.next();
+
+    if args.next().is_some() {
+        return ExecutionResult::Error(Box::<dyn Error>::from("cd: too many arguments"));
+    }
+
+    // new directory is printed if it is not the typed one
+    let (path, print) = match directory.map(String::as_str) {
+        None => (PathBuf::from(var("HOME").expect("rsh: unexpected internal error")), false),
+        Some("-") => match var("OLDPWD") {
+            Ok(previous) => (PathBuf::from(previous), true),
+            Err(_) => return ExecutionResult::Error(Box::<dyn Error>::from("cd: OLDPWD not set"))
+        },
+        Some(word) => match stack_entry(word) {
+            Some(Ok(path)) => (path, false),
+            Some(Err(err)) => return ExecutionResult::Error(Box::<dyn Error>::from(format!("cd: {err}"))),
+            None => search_cdpath(word)
+        }
     };
 
-    return match set_current_dir(Path::new(&path)) {
-        Ok(_) => ExecutionResult::Success,
-        Err(err) => ExecutionResult::Error(Box::new(err))
+    if let Err(err) = set_working_directory(&path, physical) {
+        return ExecutionResult::Error(Box::<dyn Error>::from(format!("cd: {}: {err}", path.display())));
+    }
+
+    if print {
+        println!("{}", working_directory().display());
+    }
+
+    // scripts and embedding programs don't fill the database
+    if config().record_directories && is_interactive() {
+        record_directory(&working_directory());
     }
+
+    ExecutionResult::Success
+}
+
+/// Finds the directory in `$CDPATH`, returns the
+/// path and whether it was found there
+fn search_cdpath(directory: &str) -> (PathBuf, bool) {
+    let path = PathBuf::from(directory);
+    let explicit = path.is_absolute() || matches!(path.components().next(), Some(Component::CurDir | Component::ParentDir));
+
+    if explicit {
+        return (path, false);
+    }
+
+    let cdpath = var("CDPATH").unwrap_or_default();
+
+    for base in cdpath.split(':').filter(|_| !cdpath.is_empty()) {
+        // empty entry is the working directory
+        if base.is_empty() && path.is_dir() {
+            return (path, false);
+        }
+
+        let candidate = Path::new(base).join(&path);
+
+        if !base.is_empty() && candidate.is_dir() {
+            return (candidate, true);
+        }
+    }
+
+    (path, false)
+}
+
+/// Implementation of shell built-in `exit` function
+///
+/// Terminates the shell with the given status.
+/// If no status provided, status of the last command is used
+pub fn exit_shell(status: Option<&str>) -> ExecutionResult {
+    match status.map(str::parse::<i32>) {
+        None => ExecutionResult::Exit(last_exit_status()),
+        Some(Ok(code)) => ExecutionResult::Exit(code),
+        Some(Err(_)) => ExecutionResult::Error(Box::<dyn Error>::from("exit: numeric argument required"))
+    }
+}
+
+/// Implementation of shell built-in `time` keyword
+///
+/// Executes the pipeline and prints its real, user and system
+/// time to stderr. User and system time are taken with
+/// `getrusage(2)` for both the shell and its children, so
+/// builtins and functions are measured as well as processes
+///
+/// ```rsh
+/// time sleep 1 | cat
+/// ```
+pub fn time_pipeline(pipeline: &str) -> ExecutionResult {
+    let (user_before, system_before) = cpu_time();
+    let start = Instant::now();
+
+    let result = execute(pipeline);
+
+    let real = start.elapsed();
+    let (user_after, system_after) = cpu_time();
+
+    let format = |time: Duration| format!("{}m{:.3}s", time.as_secs() / 60, time.as_secs_f64() % 60.0);
+
+    eprintln!(
+        "\nreal\t{}\nuser\t{}\nsys\t{}",
+        format(real),
+        format(user_after.saturating_sub(user_before)),
+        format(system_after.saturating_sub(system_before))
+    );
+
+    result
+}
+
+/// User and system time used by the shell and its waited children
+fn cpu_time() -> (Duration, Duration) {
+    let mut user = Duration::ZERO;
+    let mut system = Duration::ZERO;
+
+    for who in [libc::RUSAGE_SELF, libc::RUSAGE_CHILDREN] {
+        let mut usage = MaybeUninit::<libc::rusage>::zeroed();
+
+        let usage = unsafe {
+            libc::getrusage(who, usage.as_mut_ptr());
+            usage.assume_init()
+        };
+
+        let to_duration = |time: libc::timeval| {
+            Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000)
+        };
+
+        user += to_duration(usage.ru_utime);
+        system += to_duration(usage.ru_stime);
+    }
+
+    (user, system)
 }
 
 /// Implementation of shell built-in `set` function
@@ -47,13 +235,19 @@ pub fn set_variable(expression: Option<&str>) -> ExecutionResult {
         None => return ExecutionResult::Error(Box::<dyn Error>::from("expression required"))
     };
 
-    let key = parse_single_argument(args.next().unwrap().trim());
+    let key = args.next().unwrap().trim();
 
     let value = match args.next() {
-        Some(value) => parse_single_argument(value.trim()),
+        Some(value) => value.trim(),
         None => return ExecutionResult::Error(Box::<dyn Error>::from("expression required"))
     };
 
+    if let Err(err) = check_variable(key).and_then(|_| check_variable(value)) {
+        return ExecutionResult::Error(err);
+    }
+
+    let (key, value) = (parse_single_argument(key), parse_single_argument(value));
+
     let (parsed_key, parsed_value) = match (key, value) {
         (Some(key), Some(value)) => (key, value),
         (None, _) | (_, None) => return ExecutionResult::Error(Box::<dyn Error>::from("expression required"))
@@ -95,57 +289,28 @@ pub fn set_alias(expression: Option<&str>) -> ExecutionResult {
     ExecutionResult::Success
 }
 
-pub fn function_declaration(args: SplitWhitespace) -> ExecutionResult {
-    let mut tokens = args;
+/// Implementation of shell built-in `fn` function
+///
+/// Declares a function with inline body:
+///
+/// ```rsh
+/// fn greet echo hello &name
+/// ```
+///
+/// Functions with block body (`fn name { ... }`) are
+/// declared by the parser
+pub fn function_declaration(args: &[String]) -> ExecutionResult {
+    let mut tokens = args.iter();
 
     let function_name = match tokens.next() {
         Some(name) => name,
         None => return ExecutionResult::Error(Box::<dyn Error>::from("function name required"))
     };
 
-    let mut function_body = String::new();
-
-    if tokens.clone().peekable().peek().unwrap() == &"{" {
-        tokens.next();
-        
-        let cwd_path = current_dir().unwrap_or_default();
-        let cwd = cwd_path.to_str().unwrap_or_default();
-
-        let prompt = " ".repeat(cwd.len() + 3) + "> ";
-
-        print!("{prompt}");
-
-        loop {
-            match stdout().flush() {
-                Ok(_) => {},
-                Err(err) => return ExecutionResult::Error(Box::from(err))
-            };
-
-            let mut line = String::new();
-
-            print!("{prompt}");
-
-            match std::io::stdin().read_line(&mut line) {
-                Ok(_) => {
-                    if line.trim() == "}" {
-                        match stdout().flush() {
-                            Ok(_) => {},
-                            Err(err) => return ExecutionResult::Error(Box::from(err))
-                        };
-                        
-                        break;
-                    };
-        
-                    function_body += &line;
-                },
-                Err(err) => return ExecutionResult::Error(Box::from(err))
-            };
-        }
-    } else {
-        function_body = tokens.collect::<Vec<&str>>().join(" ");
-    };
+    let function_body = tokens.map(String::as_str).collect::<Vec<&str>>().join(" ");
 
     set_var("__FN_".to_owned() + function_name, function_body);
+    define_function(function_name, None);
 
-    return ExecutionResult::Success;
+    ExecutionResult::Success
 }
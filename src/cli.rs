@@ -0,0 +1,246 @@
+//! Command-line interface of the `rsh` binary
+
+use std::env::set_var;
+use std::error::Error;
+
+pub const USAGE: &str = "\
+usage: rsh [options] [file [args...]]
+       rsh [options] -c command [name [args...]]
+       rsh [options] -s [args...]
+       rsh --check [--json] [--strict] [--check-env] [file...]
+       rsh fmt [--check] [file...]
+       rsh lsp";
+
+pub const HELP: &str = "
+options:
+  -c             read commands from the first argument
+  -s             read commands from the standard input
+  -i             run the shell in interactive mode
+  -l, --login    act as a login shell
+  -n, --check    check scripts for errors without executing them
+      --debug    run the script in the step debugger
+      --json     print problems found by --check as JSON
+      --strict   exit with status 1 if --check finds warnings
+      --check-env
+                 with --check, report variables that are set
+                 neither in the script nor in the environment
+      --norc     do not read ~/.rshrc
+      --noprofile
+                 do not read ~/.rsh_profile
+      --help     print this help and exit
+      --version  print version and exit
+
+commands:
+  fmt            format scripts in place, or the standard input
+                 to the standard output; with --check only print
+                 the files that are not formatted
+  lsp            run the language server over the standard
+                 input and output";
+
+/// Source of the commands the shell executes
+pub enum Input {
+    /// Command string passed with `-c`
+    Command(String),
+    /// Script file passed as the first operand
+    File(String),
+    /// Standard input, either a terminal or a piped script
+    Stdin
+}
+
+pub struct Options {
+    pub input: Input,
+    pub interactive: bool,
+    pub login: bool,
+    pub rc: bool,
+    pub profile: bool,
+    /// Run the script in the step debugger
+    pub debug: bool,
+    /// Value of the `$0` positional parameter
+    pub name: String,
+    /// Values of the `$1`, `$2`, ... positional parameters
+    pub args: Vec<String>
+}
+
+pub enum Action {
+    Run(Options),
+    /// Check the scripts without executing them, optionally
+    /// printing the problems as JSON
+    Check { inputs: Vec<Input>, json: bool, strict: bool, check_env: bool },
+    /// Format the scripts, or only check whether they are formatted
+    Format { files: Vec<String>, check: bool },
+    /// Run the language server
+    Lsp,
+    Help,
+    Version
+}
+
+/// Parses command-line arguments of the shell
+///
+/// Options are read until the first operand or `--`.
+/// Short options can be grouped (e.g. `rsh -lc 'echo hi'`).
+/// Remaining operands are the command string (with `-c`)
+/// or the script file, followed by positional parameters.
+///
+/// `rsh fmt` and `rsh lsp` are commands instead of scripts, so
+/// a script named `fmt` or `lsp` must be run as `rsh ./fmt`.
+///
+/// A login shell is requested either with `-l`/`--login`
+/// or with `-` prepended to `argv[0]`, as `login(1)` does.
+pub fn parse_options<I>(mut args: I) -> Result<Action, Box<dyn Error>>
+where I: Iterator<Item = String> {
+    let argv0 = args.next().unwrap_or_else(|| String::from("rsh"));
+    let mut args = args.peekable();
+
+    if args.next_if(|arg| arg == "fmt").is_some() {
+        return parse_format_options(args);
+    }
+
+    if args.next_if(|arg| arg == "lsp").is_some() {
+        // editors pass `--stdio`, which is the only transport
+        return match args.find(|arg| arg != "--stdio") {
+            Some(arg) => Err(Box::<dyn Error>::from(format!("lsp: {arg}: invalid option"))),
+            None => Ok(Action::Lsp)
+        };
+    }
+
+    let mut options = Options {
+        input: Input::Stdin,
+        interactive: false,
+        login: argv0.starts_with('-'),
+        rc: true,
+        profile: true,
+        debug: false,
+        name: argv0,
+        args: Vec::new()
+    };
+
+    let mut read_command = false;
+    let mut read_stdin = false;
+    let mut check = false;
+    let mut json = false;
+    let mut strict = false;
+    let mut check_env = false;
+    let mut operands: Vec<String> = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {
+                operands.extend(args.by_ref());
+                break;
+            },
+            "--help" => return Ok(Action::Help),
+            "--version" => return Ok(Action::Version),
+            "--login" => options.login = true,
+            "--norc" => options.rc = false,
+            "--noprofile" => options.profile = false,
+            "--check" => check = true,
+            "--json" => json = true,
+            "--strict" => strict = true,
+            "--check-env" => check_env = true,
+            "--debug" => options.debug = true,
+            option if option.starts_with("--") => {
+                return Err(Box::<dyn Error>::from(format!("{option}: invalid option")))
+            },
+            flags if flags.starts_with('-') && flags.len() > 1 => {
+                for flag in flags.chars().skip(1) {
+                    match flag {
+                        'c' => read_command = true,
+                        's' => read_stdin = true,
+                        'i' => options.interactive = true,
+                        'l' => options.login = true,
+                        'n' => check = true,
+                        _ => return Err(Box::<dyn Error>::from(format!("-{flag}: invalid option")))
+                    }
+                }
+            },
+            _ => {
+                operands.push(arg);
+                operands.extend(args.by_ref());
+                break;
+            }
+        }
+    }
+
+    let check_flags = [("--json", json), ("--strict", strict), ("--check-env", check_env)];
+
+    if let Some((flag, _)) = check_flags.iter().find(|(_, set)| *set && !check) {
+        return Err(Box::<dyn Error>::from(format!("{flag}: requires --check")));
+    }
+
+    let mut operands = operands.into_iter();
+
+    // all operands are scripts to check
+    if check {
+        let inputs = match operands.next() {
+            Some(command) if read_command => vec![Input::Command(command)],
+            Some(path) if !read_stdin => [path].into_iter().chain(operands).map(Input::File).collect(),
+            None if read_command => return Err(Box::<dyn Error>::from("-c: option requires an argument")),
+            _ => vec![Input::Stdin]
+        };
+
+        return Ok(Action::Check { inputs, json, strict, check_env });
+    }
+
+    if read_command {
+        let command = match operands.next() {
+            Some(command) => command,
+            None => return Err(Box::<dyn Error>::from("-c: option requires an argument"))
+        };
+
+        options.input = Input::Command(command);
+
+        if let Some(name) = operands.next() {
+            options.name = name;
+        }
+    } else if !read_stdin {
+        if let Some(path) = operands.next() {
+            options.name = path.clone();
+            options.input = Input::File(path);
+        }
+    }
+
+    options.args = operands.collect();
+
+    if options.debug && matches!(options.input, Input::Stdin) {
+        return Err(Box::<dyn Error>::from("--debug: requires a script file or -c"));
+    }
+
+    Ok(Action::Run(options))
+}
+
+/// Parses arguments of `rsh fmt`
+fn parse_format_options<I>(args: I) -> Result<Action, Box<dyn Error>>
+where I: Iterator<Item = String> {
+    let mut check = false;
+    let mut files = Vec::new();
+    let mut options = true;
+
+    for arg in args {
+        match arg.as_str() {
+            "--" if options => options = false,
+            "--check" if options => check = true,
+            "--help" if options => return Ok(Action::Help),
+            option if options && option.starts_with('-') && option.len() > 1 => {
+                return Err(Box::<dyn Error>::from(format!("fmt: {option}: invalid option")))
+            },
+            _ => files.push(arg)
+        }
+    }
+
+    Ok(Action::Format { files, check })
+}
+
+/// Exposes script name and arguments as positional parameters
+///
+/// `$0` is the name, `$1`, `$2`, ... are the arguments,
+/// `$#` is their count and `$@` is all of them separated with spaces
+pub fn set_positional_args(name: &str, args: &[String]) {
+    set_var("0", name);
+
+    for (index, arg) in args.iter().enumerate() {
+        set_var((index + 1).to_string(), arg);
+    }
+
+    set_var("#", args.len().to_string());
+    set_var("@", args.join(" "));
+}
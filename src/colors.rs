@@ -1,4 +1,4 @@
-/// ascii-escape colors
+//! ascii-escape colors
 
 pub const RED: &str  = "\x1b[31m";
 pub const GREEN: &str = "\x1b[32m";
@@ -19,6 +19,20 @@ pub fn red(text: &str) -> String {
     format!("{RED}{text}{RESET}")
 }
 
-pub fn green(text: &str) -> String {
-    format!("{GREEN}{text}{RESET}")
+/// Finds escape sequence by its name (e.g. `red` or `bold`)
+pub fn color_by_name(name: &str) -> Option<&'static str> {
+    match name {
+        "red" => Some(RED),
+        "green" => Some(GREEN),
+        "yellow" => Some(YELLOW),
+        "blue" => Some(BLUE),
+        "magenta" => Some(MAGENTA),
+        "cyan" => Some(CYAN),
+        "white" => Some(WHITE),
+        "bold" => Some(BOLD),
+        "dimmed" => Some(DIMMED),
+        "italic" => Some(ITALIC),
+        "underline" => Some(UNDERLINE),
+        _ => None
+    }
 }
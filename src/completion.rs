@@ -0,0 +1,661 @@
+//! Tab completion
+//!
+//! Line is split into words with the same lexer that is
+//! used for execution. Word under the cursor is completed
+//! depending on its position:
+//!
+//! * command position: builtins, aliases, functions,
+//!   instants and executables from `$PATH`
+//! * `$name`: variables
+//! * argument of a command with completion spec: words,
+//!   subcommands and options from the spec
+//! * `-flag` after a builtin or an instant: its flags
+//! * otherwise: paths, only directories for `cd`
+//!
+//! Replacements are escaped, so completed names with
+//! spaces and special characters are executed as is.
+//!
+//! Specs are registered with `complete` builtin or in
+//! `[completions]` section of the config:
+//!
+//! ```rsh
+//! complete git -w "add commit push remote"
+//! complete git -s remote -w "add remove"
+//! complete git -s commit -o "--amend --message"
+//! complete kubectl -F kube_complete
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env::{current_exe, var, vars};
+use std::error::Error;
+use std::fs::{self, read_dir};
+use std::path::{Path, MAIN_SEPARATOR};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use rustyline::completion::Pair;
+use crate::builtins::builtin_names;
+use crate::execute::ExecutionResult;
+use crate::instants::instant_names;
+use crate::lexer::{Token, TokenKind, escape, tokenize, unquote};
+use crate::parse_command::COMMAND_KEYWORDS;
+use crate::utils::is_executable;
+
+/// Flags of builtins and instants
+pub const FLAGS: [(&str, &[&str]); 11] = [
+    ("@exec", &["--noexit", "-n"]),
+    ("@format", &[
+        "--red", "--green", "--yellow", "--blue", "--magenta", "--cyan", "--white",
+        "--bold", "--dimmed", "--italic", "--underline"
+    ]),
+    ("cd", &["-L", "-P"]),
+    ("complete", &["-r", "-s", "-w", "-o", "-F"]),
+    ("dirs", &["-c", "-l", "-p", "-v"]),
+    ("history", &["-c", "-d", "-l", "-s"]),
+    ("hook", &["-d"]),
+    ("j", &["-i", "-l", "-x", "--prune"]),
+    ("set", &["-e", "-u", "-x", "-f", "-C", "-v", "-o", "+o"]),
+    ("trap", &["-l", "-p"]),
+    ("z", &["-i", "-l", "-x", "--prune"])
+];
+
+/// Flags of the builtin or instant
+pub fn command_flags(command: &str) -> &'static [&'static str] {
+    FLAGS.iter()
+        .find(|(name, _)| *name == command)
+        .map_or(&[], |(_, flags)| flags)
+}
+
+/// Completes the word that ends at `pos`
+///
+/// Returns start of the word and candidates to replace it
+pub fn complete(line: &str, pos: usize) -> (usize, Vec<Pair>) {
+    let tokens = tokenize(&line[..pos]);
+
+    let (word, previous) = match tokens.split_last() {
+        Some((last, rest)) if last.kind == TokenKind::Word && last.end() == pos => (Some(last), rest),
+        _ => (None, &tokens[..])
+    };
+
+    // comments are not completed
+    if tokens.last().is_some_and(|token| token.kind == TokenKind::Comment) {
+        return (pos, Vec::new());
+    }
+
+    let start = word.map_or(pos, |word| word.start);
+    let raw = word.map_or("", |word| word.text);
+
+    let position = Position::of(previous);
+
+    if let Position::Argument(index) = position {
+        if !raw.starts_with('$') {
+            if let Some(candidates) = complete_spec(&line[..pos], &previous[index..], raw) {
+                return (start, candidates);
+            }
+        }
+    }
+
+    let command = match position {
+        Position::Argument(index) => previous[index].text,
+        _ => ""
+    };
+
+    let candidates = match position {
+        Position::Command => complete_command(raw),
+        _ if raw.starts_with('$') => complete_variable(&raw[1..]),
+        Position::Argument(_) if raw.starts_with(['-', '+']) && !command_flags(command).is_empty() => {
+            complete_flags(raw, command_flags(command))
+        },
+        Position::Argument(_) if matches!(command, "cd" | "pushd") => complete_path(raw, |path| path.is_dir()),
+        _ => complete_path(raw, |_| true)
+    };
+
+    (start, candidates)
+}
+
+/// Position of the word being completed
+#[derive(Clone, Copy)]
+pub enum Position {
+    Command,
+    /// Argument of the command, index of the command token
+    Argument(usize),
+    /// File name after `>` or `>>`, index of the command token
+    Redirection(Option<usize>)
+}
+
+impl Position {
+    /// Position of the word after the tokens
+    pub fn of(previous: &[Token]) -> Position {
+        let mut position = Position::Command;
+
+        for (index, token) in previous.iter().enumerate() {
+            position = match (position, token.kind) {
+                (_, TokenKind::Comment) => continue,
+                (Position::Argument(command), TokenKind::Write | TokenKind::Append) => Position::Redirection(Some(command)),
+                (_, TokenKind::Write | TokenKind::Append) => Position::Redirection(None),
+                (_, kind) if kind != TokenKind::Word => Position::Command,
+                (Position::Command, _) if COMMAND_KEYWORDS.contains(&token.text) => Position::Command,
+                // body of the function starts with a command
+                (_, _) if token.text == "{" => Position::Command,
+                (Position::Command, _) => Position::Argument(index),
+                // file name ends the redirection, arguments could follow it
+                (Position::Redirection(Some(command)), _) => Position::Argument(command),
+                (Position::Redirection(None), _) => Position::Command,
+                (position, _) => position
+            };
+        }
+
+        position
+    }
+}
+
+/// Completion spec of a command or its subcommand
+#[derive(Default)]
+struct Spec {
+    words: Vec<String>,
+    options: Vec<String>,
+    /// rsh function that prints candidates, one per line
+    function: Option<String>,
+    subcommands: BTreeMap<String, Spec>
+}
+
+impl Spec {
+    /// Prints `complete` commands that define the spec
+    fn print(&self, command: &str, path: &mut Vec<String>) {
+        let mut definition = format!("complete {}", escape(command));
+
+        if !path.is_empty() {
+            definition += &format!(" -s {}", quote_list(path));
+        }
+
+        if !self.words.is_empty() {
+            definition += &format!(" -w {}", quote_list(&self.words));
+        }
+
+        if !self.options.is_empty() {
+            definition += &format!(" -o {}", quote_list(&self.options));
+        }
+
+        if let Some(function) = &self.function {
+            definition += &format!(" -F {}", escape(function));
+        }
+
+        // subcommand without candidates is printed to keep it in the tree
+        let has_candidates = !self.words.is_empty() || !self.options.is_empty() || self.function.is_some();
+
+        if has_candidates || (self.subcommands.is_empty() && !path.is_empty()) {
+            println!("{definition}");
+        }
+
+        for (name, subcommand) in &self.subcommands {
+            path.push(name.clone());
+            subcommand.print(command, path);
+            path.pop();
+        }
+    }
+}
+
+static SPECS: Mutex<BTreeMap<String, Spec>> = Mutex::new(BTreeMap::new());
+
+/// Implementation of shell built-in `complete` function
+///
+/// Registers completion spec of the command:
+///
+/// ```rsh
+/// complete git -w "add commit push remote"
+/// complete git -s remote -w "add remove"
+/// complete git -s commit -o "--amend --message"
+/// complete kubectl -F kube_complete
+/// ```
+///
+/// `-s` selects the subcommand the spec is applied to.
+/// Without arguments, all specs are printed, `-r command`
+/// removes the spec of the command
+pub fn define_completion(args: &[String]) -> ExecutionResult {
+    match define(args) {
+        Ok(_) => ExecutionResult::Success,
+        Err(err) => ExecutionResult::Error(Box::<dyn Error>::from(format!("complete: {err}")))
+    }
+}
+
+fn define(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut specs = SPECS.lock().unwrap();
+    let mut args = args.iter();
+
+    let command = match args.next().map(String::as_str) {
+        None => {
+            for (command, spec) in specs.iter() {
+                spec.print(command, &mut Vec::new());
+            }
+
+            return Ok(());
+        },
+        Some("-r") => {
+            for command in args {
+                if specs.remove(command).is_none() {
+                    return Err(Box::<dyn Error>::from(format!("no completion spec for `{command}`")));
+                }
+            }
+
+            return Ok(());
+        },
+        Some(command) if command.starts_with('-') => {
+            return Err(Box::<dyn Error>::from(format!("command expected, found `{command}`")));
+        },
+        Some(command) => command
+    };
+
+    if args.as_slice().is_empty() {
+        let spec = match specs.get(command) {
+            Some(spec) => spec,
+            None => return Err(Box::<dyn Error>::from(format!("no completion spec for `{command}`")))
+        };
+
+        spec.print(command, &mut Vec::new());
+        return Ok(());
+    }
+
+    let mut path = Vec::new();
+    let mut words = Vec::new();
+    let mut options = Vec::new();
+    let mut function = None;
+
+    while let Some(flag) = args.next() {
+        let value = match args.next() {
+            Some(value) => value,
+            None => return Err(Box::<dyn Error>::from(format!("`{flag}` requires an argument")))
+        };
+
+        let list = value.split_whitespace().map(String::from);
+
+        match flag.as_str() {
+            "-s" => path.extend(list),
+            "-w" => words.extend(list),
+            "-o" => options.extend(list),
+            "-F" => function = Some(value.clone()),
+            _ => return Err(Box::<dyn Error>::from(format!("unknown option `{flag}`")))
+        }
+    }
+
+    let mut spec = specs.entry(command.to_string()).or_default();
+
+    for subcommand in path {
+        spec = spec.subcommands.entry(subcommand).or_default();
+    }
+
+    spec.words.extend(words);
+    spec.options.extend(options);
+    spec.function = function.or(spec.function.take());
+
+    Ok(())
+}
+
+/// Completes the argument with the spec of the command
+///
+/// `stage` starts with the command token. Returns `None`
+/// if there is no spec, so the default completion is used
+fn complete_spec(line: &str, stage: &[Token], raw: &str) -> Option<Vec<Pair>> {
+    let specs = SPECS.lock().unwrap();
+    let mut spec = specs.get(&unquote(stage[0].text))?;
+
+    let mut previous = stage[1..].iter().peekable();
+
+    while let Some(token) = previous.next() {
+        if token.kind != TokenKind::Word {
+            // skip file name of the redirection
+            previous.next_if(|next| next.kind == TokenKind::Word);
+            continue;
+        }
+
+        if let Some(subcommand) = spec.subcommands.get(&unquote(token.text)) {
+            spec = subcommand;
+        }
+    }
+
+    let prefix = unquote(raw);
+
+    if prefix.starts_with('-') && !spec.options.is_empty() {
+        return Some(complete_flags(&prefix, &spec.options.iter().map(String::as_str).collect::<Vec<_>>()));
+    }
+
+    if spec.words.is_empty() && spec.subcommands.is_empty() && spec.function.is_none() {
+        return None;
+    }
+
+    let mut names: BTreeSet<String> = spec.words.iter().chain(spec.subcommands.keys()).cloned().collect();
+
+    if let Some(function) = &spec.function {
+        names.extend(function_candidates(function, line, &prefix));
+    }
+
+    Some(
+        names.into_iter()
+            .filter(|name| name.starts_with(&prefix))
+            .map(|name| Pair { replacement: quote(raw, &name, true), display: name })
+            .collect()
+    )
+}
+
+/// Runs the rsh function in a child shell and returns lines of its output
+///
+/// Functions are stored in variables, so the child shell inherits
+/// them. The function gets the current word as an argument, and
+/// the line before the cursor is available as `$COMP_LINE`
+fn function_candidates(function: &str, line: &str, prefix: &str) -> Vec<String> {
+    let argument = if prefix.is_empty() { String::from("\"\"") } else { escape(prefix) };
+
+    let output = current_exe().and_then(|shell| {
+        Command::new(shell)
+            .arg("-c")
+            .arg(format!("{} {argument}", escape(function)))
+            .env("COMP_LINE", line)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+    });
+
+    match output {
+        Ok(output) => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect(),
+        Err(_) => Vec::new()
+    }
+}
+
+/// Joins the list into a single quoted word
+fn quote_list(list: &[String]) -> String {
+    format!("\"{}\"", list.join(" ").replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn complete_command(raw: &str) -> Vec<Pair> {
+    let prefix = unquote(raw);
+
+    if prefix.starts_with('@') {
+        return plain_candidates(instant_names().into_iter().filter(|instant| instant.starts_with(&prefix)));
+    }
+
+    // relative and absolute paths to executables
+    if prefix.contains(MAIN_SEPARATOR) {
+        return complete_path(raw, |path| path.is_dir() || is_executable(path));
+    }
+
+    command_names(&prefix).into_iter()
+        .map(|name| Pair { replacement: quote(raw, &name, true), display: name })
+        .collect()
+}
+
+/// Names of builtins, aliases, functions and executables
+/// from `$PATH` that start with the prefix
+pub fn command_names(prefix: &str) -> BTreeSet<String> {
+    let mut names = BTreeSet::new();
+
+    names.extend(builtin_names());
+
+    for (key, _) in vars() {
+        if let Some(name) = key.strip_prefix("__ALIAS_").or_else(|| key.strip_prefix("__FN_")) {
+            names.insert(name.to_string());
+        }
+    }
+
+    for directory in var("PATH").unwrap_or_default().split(':') {
+        let Ok(entries) = read_dir(directory) else { continue };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+
+            if name.starts_with(prefix) && is_executable(&entry.path()) {
+                names.insert(name);
+            }
+        }
+    }
+
+    names.retain(|name| name.starts_with(prefix));
+
+    names
+}
+
+fn complete_variable(prefix: &str) -> Vec<Pair> {
+    let names: BTreeSet<String> = vars()
+        .map(|(key, _)| key)
+        // internal variables of aliases, functions and hooks
+        .filter(|key| key.starts_with(prefix) && !key.starts_with("__"))
+        .collect();
+
+    plain_candidates(names.into_iter().map(|name| format!("${name}")))
+}
+
+fn complete_flags(prefix: &str, flags: &[&str]) -> Vec<Pair> {
+    plain_candidates(flags.iter().filter(|flag| flag.starts_with(prefix)).map(|flag| flag.to_string()))
+}
+
+/// Completes file names, directories are always suggested
+/// so that nested paths can be completed
+fn complete_path(raw: &str, filter: fn(&Path) -> bool) -> Vec<Pair> {
+    let prefix = match raw.strip_prefix('\'') {
+        Some(literal) => literal.to_string(),
+        None => unquote(raw)
+    };
+
+    let (directory, name) = match prefix.rfind(MAIN_SEPARATOR) {
+        Some(index) => prefix.split_at(index + 1),
+        None => ("", prefix.as_str())
+    };
+
+    let search_directory = match directory.strip_prefix("~/") {
+        Some(rest) => format!("{}/{rest}", var("HOME").unwrap_or_default()),
+        None if directory.is_empty() => String::from("."),
+        None => directory.to_string()
+    };
+
+    let Ok(entries) = read_dir(&search_directory) else { return Vec::new() };
+
+    let mut candidates: Vec<Pair> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().to_string_lossy().into_owned();
+
+            // hidden files are suggested only when requested
+            if !file_name.starts_with(name) || (file_name.starts_with('.') && !name.starts_with('.')) {
+                return None;
+            }
+
+            // symlinks to directories are followed
+            let path = entry.path();
+            let is_dir = fs::metadata(&path).is_ok_and(|metadata| metadata.is_dir());
+
+            if !is_dir && !filter(&path) {
+                return None;
+            }
+
+            let display = if is_dir { format!("{file_name}{MAIN_SEPARATOR}") } else { file_name };
+            let replacement = quote(raw, &format!("{directory}{display}"), !is_dir);
+
+            Some(Pair { display, replacement })
+        })
+        .collect();
+
+    candidates.sort_by(|a, b| a.display.cmp(&b.display));
+    candidates
+}
+
+/// Quotes the completed text the same way as the original word
+///
+/// Words in double quotes are closed when `complete` is set,
+/// otherwise they are left open to continue the completion
+fn quote(raw: &str, text: &str, complete: bool) -> String {
+    if raw.starts_with('\'') {
+        return format!("'{text}");
+    }
+
+    if raw.starts_with('"') {
+        let escaped = text.replace('\\', "\\\\").replace('"', "\\\"");
+        return if complete { format!("\"{escaped}\"") } else { format!("\"{escaped}") };
+    }
+
+    // home directory is expanded only when `~` is not escaped
+    match text.strip_prefix("~/") {
+        Some(rest) => format!("~/{}", escape(rest)),
+        None => escape(text)
+    }
+}
+
+fn plain_candidates<I: Iterator<Item = String>>(names: I) -> Vec<Pair> {
+    names.map(|name| Pair { display: name.clone(), replacement: name }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Position of the word after the line
+    fn position(line: &str) -> Position {
+        Position::of(&tokenize(line))
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    /// Candidates of the spec for the last word of the line
+    fn spec_candidates(line: &str) -> Option<Vec<(String, String)>> {
+        let tokens = tokenize(line);
+        let (raw, previous) = match tokens.split_last() {
+            Some((last, rest)) if !line.ends_with(' ') => (last.text, rest),
+            _ => ("", &tokens[..])
+        };
+
+        let Position::Argument(index) = Position::of(previous) else { return None };
+
+        complete_spec(line, &previous[index..], raw)
+            .map(|pairs| pairs.into_iter().map(|pair| (pair.display, pair.replacement)).collect())
+    }
+
+    fn pair(display: &str, replacement: &str) -> (String, String) {
+        (display.to_string(), replacement.to_string())
+    }
+
+    #[test]
+    fn positions_of_commands() {
+        assert!(matches!(position(""), Position::Command));
+        assert!(matches!(position("ls | "), Position::Command));
+        assert!(matches!(position("ls; "), Position::Command));
+        assert!(matches!(position("true && "), Position::Command));
+        assert!(matches!(position("sleep 1 & "), Position::Command));
+        assert!(matches!(position("if "), Position::Command));
+        assert!(matches!(position("while true; do "), Position::Command));
+        assert!(matches!(position("fn f { "), Position::Command));
+    }
+
+    #[test]
+    fn positions_of_arguments() {
+        assert!(matches!(position("ls "), Position::Argument(0)));
+        assert!(matches!(position("ls -la "), Position::Argument(0)));
+        assert!(matches!(position("cat a | grep "), Position::Argument(3)));
+        assert!(matches!(position("if test "), Position::Argument(1)));
+        assert!(matches!(position("echo # comment\n"), Position::Command));
+    }
+
+    #[test]
+    fn positions_of_redirections() {
+        assert!(matches!(position("echo a > "), Position::Redirection(Some(0))));
+        assert!(matches!(position("echo a >> "), Position::Redirection(Some(0))));
+        assert!(matches!(position("> "), Position::Redirection(None)));
+        // file name ends the redirection
+        assert!(matches!(position("echo > out "), Position::Argument(0)));
+        assert!(matches!(position("> out "), Position::Command));
+    }
+
+    #[test]
+    fn completes_with_specs() {
+        define(&strings(&["rsh-test-vcs", "-w", "add commit push remote"])).unwrap();
+        define(&strings(&["rsh-test-vcs", "-s", "remote", "-w", "add remove"])).unwrap();
+        define(&strings(&["rsh-test-vcs", "-s", "commit", "-o", "--amend --message"])).unwrap();
+
+        assert_eq!(spec_candidates("rsh-test-vcs c"), Some(vec![pair("commit", "commit")]));
+        assert_eq!(spec_candidates("rsh-test-vcs "), Some(vec![
+            pair("add", "add"), pair("commit", "commit"), pair("push", "push"), pair("remote", "remote")
+        ]));
+
+        // subcommands are walked, redirections are skipped
+        assert_eq!(spec_candidates("rsh-test-vcs remote re"), Some(vec![pair("remove", "remove")]));
+        assert_eq!(spec_candidates("rsh-test-vcs > out remote a"), Some(vec![pair("add", "add")]));
+        assert_eq!(spec_candidates("rsh-test-vcs commit --a"), Some(vec![pair("--amend", "--amend")]));
+
+        // quoted words keep the quotes
+        assert_eq!(spec_candidates("rsh-test-vcs \"pu"), Some(vec![pair("push", "\"push\"")]));
+
+        // subcommand without words falls back to the default completion
+        assert_eq!(spec_candidates("rsh-test-vcs commit "), None);
+        assert_eq!(spec_candidates("rsh-test-unknown "), None);
+
+        assert!(define(&strings(&["rsh-test-vcs", "-w"])).is_err());
+        assert!(define(&strings(&["rsh-test-vcs", "-x", "y"])).is_err());
+        assert!(define(&strings(&["-r", "rsh-test-vcs"])).is_ok());
+        assert!(define(&strings(&["-r", "rsh-test-vcs"])).is_err());
+        assert_eq!(spec_candidates("rsh-test-vcs "), None);
+    }
+
+    #[test]
+    fn quotes_like_the_original_word() {
+        assert_eq!(quote("my", "my file.txt", true), r"my\ file.txt");
+        assert_eq!(quote("say", "say \"hi\"", true), r#"say\ \"hi\""#);
+        assert_eq!(quote("~/d", "~/dir name/", false), r"~/dir\ name/");
+        assert_eq!(quote("\"my", "my file.txt", true), "\"my file.txt\"");
+        assert_eq!(quote("\"my", "my dir/", false), "\"my dir/");
+        assert_eq!(quote("\"sa", "say \"hi\"", true), r#""say \"hi\"""#);
+        assert_eq!(quote("'my", "my file.txt", true), "'my file.txt");
+    }
+
+    #[test]
+    fn completes_variables() {
+        std::env::set_var("RSH_TEST_COMPLETION_VAR", "value");
+
+        let line = "echo $RSH_TEST_COMPLETION";
+        let (start, candidates) = complete(line, line.len());
+
+        assert_eq!(start, 5);
+        assert_eq!(candidates.iter().map(|pair| pair.replacement.as_str()).collect::<Vec<_>>(), ["$RSH_TEST_COMPLETION_VAR"]);
+
+        std::env::remove_var("RSH_TEST_COMPLETION_VAR");
+    }
+
+    #[test]
+    fn completes_paths_with_spaces() {
+        let directory = std::env::temp_dir().join(format!("rsh-completion-{}", std::process::id()));
+        fs::create_dir_all(directory.join("my dir")).unwrap();
+        fs::write(directory.join("my file.txt"), "").unwrap();
+        fs::write(directory.join(".hidden"), "").unwrap();
+
+        let path = directory.to_string_lossy();
+        let complete_line = |line: &str| -> Vec<(String, String)> {
+            let (_, candidates) = complete(line, line.len());
+            candidates.into_iter().map(|pair| (pair.display, pair.replacement)).collect()
+        };
+
+        assert_eq!(complete_line(&format!("cat {path}/my")), [
+            pair("my dir/", &format!("{path}/my\\ dir/")),
+            pair("my file.txt", &format!("{path}/my\\ file.txt"))
+        ]);
+        assert_eq!(complete_line(&format!("cat {path}/my\\ f")), [
+            pair("my file.txt", &format!("{path}/my\\ file.txt"))
+        ]);
+        assert_eq!(complete_line(&format!("cat \"{path}/my f")), [
+            pair("my file.txt", &format!("\"{path}/my file.txt\""))
+        ]);
+        // literal word lasts until the whitespace, so it is not escaped
+        assert_eq!(complete_line(&format!("cat '{path}/my")), [
+            pair("my dir/", &format!("'{path}/my dir/")),
+            pair("my file.txt", &format!("'{path}/my file.txt"))
+        ]);
+        assert_eq!(complete_line(&format!("cd {path}/my")), [
+            pair("my dir/", &format!("{path}/my\\ dir/"))
+        ]);
+        assert_eq!(complete_line(&format!("cat {path}/.h")), [
+            pair(".hidden", &format!("{path}/.hidden"))
+        ]);
+
+        let _ = fs::remove_dir_all(&directory);
+    }
+}
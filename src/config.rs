@@ -0,0 +1,333 @@
+//! Parser of the `~/.rshrc` configuration file
+//!
+//! Config uses a small subset of TOML: `[section]` headers,
+//! `key = value` pairs and `#` comments. Values are strings
+//! (`"text"` or `'text'`), integers or booleans. Unlike TOML,
+//! only `\"` and `\\` are escapes in `"text"`, other backslashes
+//! are kept, so prompt templates don't have to double them.
+//!
+//! ```toml
+//! [prompt]
+//! format = "\u@\h \w \$ "
+//!
+//! [history]
+//! size = 5000
+//!
+//! [aliases]
+//! ll = "ls -la"
+//!
+//! [completions]
+//! git = '-s remote -w "add remove"'
+//! ```
+
+use std::env::var;
+use std::error::Error;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::Duration;
+use rustyline::EditMode;
+use crate::builtins::set_alias;
+use crate::colors::*;
+use crate::completion::define_completion;
+use crate::execute::ExecutionResult;
+use crate::history::Dedup;
+use crate::lexer::{TokenKind, tokenize, unquote};
+use crate::options::set_option;
+use crate::utils::error_log;
+
+pub struct Config {
+    /// Template of the prompt, see `prompt` module
+    pub prompt: String,
+    /// Template of the prompt for continuation lines
+    pub continuation_prompt: String,
+    /// Template of the prompt displayed on the right side of the terminal
+    pub right_prompt: String,
+    /// Minimal duration of the command to be shown in the prompt
+    pub duration_threshold: Duration,
+    pub success_color: String,
+    pub failure_color: String,
+    pub history_path: String,
+    /// Maximum number of history entries
+    pub history_size: usize,
+    pub history_dedup: Dedup,
+    /// Commands that start with whitespace are not recorded
+    pub history_ignore_space: bool,
+    /// Patterns of commands that are not recorded
+    pub history_ignore: Vec<String>,
+    /// Database of visited directories, see `jump` module
+    pub directories_path: String,
+    /// Whether directories entered with `cd` in the interactive shell are recorded
+    pub record_directories: bool,
+    pub edit_mode: EditMode,
+    /// Time limit for computing git status in the prompt
+    pub git_timeout: Duration,
+    /// Whether status is taken from `git status` instead of `.git`
+    pub git_command: bool
+}
+
+impl Config {
+    pub fn new(home: &str) -> Config {
+        Config {
+            prompt: String::from("\\[bold]\\[status]*\\[reset] \\[bold]\\p\\[reset] $ "),
+            continuation_prompt: String::from("> "),
+            right_prompt: String::from("\\[yellow]\\D\\[reset]"),
+            duration_threshold: Duration::from_secs(2),
+            success_color: String::from(GREEN),
+            failure_color: String::from(RED),
+            history_path: format!("{home}/.rsh_history"),
+            history_size: 1000,
+            history_dedup: Dedup::Consecutive,
+            history_ignore_space: true,
+            history_ignore: Vec::new(),
+            directories_path: format!("{home}/.rsh_directories"),
+            record_directories: true,
+            edit_mode: EditMode::Emacs,
+            git_timeout: Duration::from_millis(200),
+            git_command: false
+        }
+    }
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Makes config available to the whole shell, can be called only once
+pub fn set_config(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+/// Config of the shell, or the default config if it wasn't loaded
+pub fn config() -> &'static Config {
+    CONFIG.get_or_init(|| Config::new(&var("HOME").unwrap_or_default()))
+}
+
+enum Value {
+    String(String),
+    Integer(i64),
+    Boolean(bool)
+}
+
+impl Value {
+    fn parse(value: &str) -> Result<Value, Box<dyn Error>> {
+        let mut chars = value.chars();
+
+        match chars.next() {
+            Some(quote @ ('"' | '\'')) => {
+                let mut string = String::new();
+
+                while let Some(char) = chars.next() {
+                    match char {
+                        '\\' if quote == '"' => match chars.next() {
+                            Some(escaped @ ('"' | '\\')) => string.push(escaped),
+                            // other escapes are kept for prompt templates
+                            Some(other) => {
+                                string.push('\\');
+                                string.push(other);
+                            },
+                            None => break
+                        },
+                        char if char == quote => {
+                            let rest = chars.as_str().trim_start();
+
+                            if !rest.is_empty() && !rest.starts_with('#') {
+                                return Err(Box::<dyn Error>::from(format!("unexpected `{rest}` after string")));
+                            }
+
+                            return Ok(Value::String(string));
+                        },
+                        char => string.push(char)
+                    }
+                }
+
+                Err(Box::<dyn Error>::from("unterminated string"))
+            },
+            _ => {
+                let value = value.split('#').next().unwrap_or_default().trim();
+
+                match value {
+                    "true" => Ok(Value::Boolean(true)),
+                    "false" => Ok(Value::Boolean(false)),
+                    "" => Err(Box::<dyn Error>::from("value required")),
+                    _ => match value.parse() {
+                        Ok(integer) => Ok(Value::Integer(integer)),
+                        Err(_) => Err(Box::<dyn Error>::from(format!("invalid value `{value}` (strings must be quoted)")))
+                    }
+                }
+            }
+        }
+    }
+
+    fn string(self) -> Result<String, Box<dyn Error>> {
+        match self {
+            Value::String(string) => Ok(string),
+            _ => Err(Box::<dyn Error>::from("expected string"))
+        }
+    }
+
+    fn integer(self) -> Result<i64, Box<dyn Error>> {
+        match self {
+            Value::Integer(integer) => Ok(integer),
+            _ => Err(Box::<dyn Error>::from("expected integer"))
+        }
+    }
+
+    fn boolean(self) -> Result<bool, Box<dyn Error>> {
+        match self {
+            Value::Boolean(boolean) => Ok(boolean),
+            _ => Err(Box::<dyn Error>::from("expected `true` or `false`"))
+        }
+    }
+
+    /// Converts space separated color names (e.g. `"bold red"`) to escape sequences
+    fn color(self) -> Result<String, Box<dyn Error>> {
+        let mut sequence = String::new();
+
+        for name in self.string()?.split_whitespace() {
+            match color_by_name(name) {
+                Some(color) => sequence += color,
+                None => return Err(Box::<dyn Error>::from(format!("unknown color `{name}`")))
+            }
+        }
+
+        Ok(sequence)
+    }
+}
+
+/// Loads config from the file
+///
+/// Missing file is not an error. Invalid lines are reported
+/// with the file name and the line number and then skipped,
+/// so one mistake doesn't discard the whole config
+pub fn load_config<P>(path: P, home: &str) -> Config
+where P: AsRef<Path> {
+    let mut config = Config::new(home);
+
+    let content = match read_to_string(&path) {
+        Ok(content) => content,
+        Err(_) => return config
+    };
+
+    let mut section = String::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let result = if let Some(header) = line.strip_prefix('[') {
+            match header.split('#').next().unwrap_or_default().trim_end().strip_suffix(']') {
+                Some(name) => {
+                    section = name.trim().to_string();
+
+                    match section.as_str() {
+                        "prompt" | "history" | "jump" | "editor" | "colors" | "git" | "options" | "aliases" | "completions" => Ok(()),
+                        _ => Err(Box::<dyn Error>::from(format!("unknown section [{section}]")))
+                    }
+                },
+                None => Err(Box::<dyn Error>::from("expected `]` after section name"))
+            }
+        } else {
+            match line.split_once('=') {
+                Some((key, value)) => Value::parse(value.trim())
+                    .and_then(|value| apply(&mut config, &section, key.trim(), value, home)),
+                None => Err(Box::<dyn Error>::from("expected `key = value`"))
+            }
+        };
+
+        if let Err(err) = result {
+            error_log(Box::<dyn Error>::from(format!("{}:{}: {err}", path.as_ref().display(), index + 1)));
+        }
+    }
+
+    config
+}
+
+fn apply(config: &mut Config, section: &str, key: &str, value: Value, home: &str) -> Result<(), Box<dyn Error>> {
+    match (section, key) {
+        ("prompt", "format") => config.prompt = value.string()?,
+        ("prompt", "continuation") => config.continuation_prompt = value.string()?,
+        ("prompt", "right") => config.right_prompt = value.string()?,
+        ("prompt", "duration_threshold") => config.duration_threshold = match u64::try_from(value.integer()?) {
+            Ok(milliseconds) => Duration::from_millis(milliseconds),
+            Err(_) => return Err(Box::<dyn Error>::from("threshold must not be negative"))
+        },
+
+        ("history", "path") => {
+            let path = value.string()?;
+
+            config.history_path = match path.strip_prefix("~/") {
+                Some(relative) => format!("{home}/{relative}"),
+                None => path
+            };
+        },
+        ("history", "size") => config.history_size = match usize::try_from(value.integer()?) {
+            Ok(size) => size,
+            Err(_) => return Err(Box::<dyn Error>::from("history size must not be negative"))
+        },
+
+        ("history", "dedup") => config.history_dedup = match value.string()?.as_str() {
+            "none" => Dedup::None,
+            "consecutive" => Dedup::Consecutive,
+            "all" => Dedup::All,
+            dedup => return Err(Box::<dyn Error>::from(format!("unknown dedup mode `{dedup}` (expected `none`, `consecutive` or `all`)")))
+        },
+        ("history", "ignore_space") => config.history_ignore_space = value.boolean()?,
+        ("history", "ignore") => config.history_ignore = value.string()?
+            .split(':')
+            .filter(|pattern| !pattern.is_empty())
+            .map(String::from)
+            .collect(),
+
+        ("jump", "path") => {
+            let path = value.string()?;
+
+            config.directories_path = match path.strip_prefix("~/") {
+                Some(relative) => format!("{home}/{relative}"),
+                None => path
+            };
+        },
+        ("jump", "record") => config.record_directories = value.boolean()?,
+
+        ("editor", "mode") => config.edit_mode = match value.string()?.as_str() {
+            "emacs" => EditMode::Emacs,
+            "vi" => EditMode::Vi,
+            mode => return Err(Box::<dyn Error>::from(format!("unknown editing mode `{mode}` (expected `emacs` or `vi`)")))
+        },
+
+        ("colors", "success") => config.success_color = value.color()?,
+        ("colors", "failure") => config.failure_color = value.color()?,
+
+        ("git", "timeout") => config.git_timeout = match u64::try_from(value.integer()?) {
+            Ok(milliseconds) => Duration::from_millis(milliseconds),
+            Err(_) => return Err(Box::<dyn Error>::from("timeout must not be negative"))
+        },
+        ("git", "command") => config.git_command = value.boolean()?,
+
+        ("options", option) => set_option(option, value.boolean()?)?,
+
+        ("aliases", name) => {
+            if let ExecutionResult::Error(err) = set_alias(Some(&format!("{name}={}", value.string()?))) {
+                return Err(err);
+            }
+        },
+
+        ("completions", command) => {
+            let spec = tokenize(&value.string()?)
+                .into_iter()
+                .filter(|token| token.kind == TokenKind::Word)
+                .map(|token| unquote(token.text))
+                .collect::<Vec<String>>();
+
+            if let ExecutionResult::Error(err) = define_completion(&[vec![command.to_string()], spec].concat()) {
+                return Err(err);
+            }
+        },
+
+        ("", _) => return Err(Box::<dyn Error>::from(format!("`{key}` must be inside a section"))),
+        _ => return Err(Box::<dyn Error>::from(format!("unknown key `{key}` in section [{section}]")))
+    }
+
+    Ok(())
+}
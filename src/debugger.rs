@@ -0,0 +1,524 @@
+//! Step debugger of scripts
+//!
+//! `rsh --debug script.rsh` stops before the first command of
+//! the script and reads commands of the debugger from the terminal:
+//!
+//! | command | effect |
+//! |:----|:----|
+//! | `step`, `s` | execute the command, stopping inside the functions it calls |
+//! | `next`, `n` | execute the command, stopping at the next command of the same function |
+//! | `finish`, `f` | run until the current function returns |
+//! | `continue`, `c` | run until a breakpoint |
+//! | `break LINE`, `break FILE:LINE`, `break NAME` | stop at the line, or when the function is called |
+//! | `delete [N]` | delete breakpoint `N`, or all breakpoints |
+//! | `breakpoints` | list breakpoints |
+//! | `list`, `l` | print the code around the current line |
+//! | `backtrace`, `bt` | print the call stack |
+//! | `print [NAME]`, `p` | print the variable, or all variables |
+//! | `functions [NAME]` | list functions, or print the body of the function |
+//! | `args` | print positional parameters |
+//! | `eval COMMAND`, `e` | execute the command in the paused script |
+//! | `quit`, `q` | exit the script with status 1 |
+//!
+//! Empty line repeats the last command. Only the commands of
+//! files are stopped at, not of traps or inline functions.
+
+use std::collections::BTreeMap;
+use std::env::{var, vars};
+use std::fs::{File, read_to_string};
+use std::io::{self, BufRead, BufReader};
+use std::path::Path;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::diagnostics::{Position, call_stack, current_position, current_snippet, function_origin};
+use crate::execute::{ExecutionResult, execute_code};
+use crate::utils::{last_exit_status, set_exit_status};
+
+const HELP: &str = "\
+step, s                 execute the command, stopping inside the functions it calls
+next, n                 execute the command, stopping at the next command of the same function
+finish, f               run until the current function returns
+continue, c             run until a breakpoint
+break LINE, b           stop at the line of the script
+break FILE:LINE         stop at the line of the file
+break NAME              stop when the function is called
+delete [N], d           delete breakpoint N, or all breakpoints
+breakpoints             list breakpoints
+list, l                 print the code around the current line
+backtrace, bt           print the call stack
+print [NAME], p         print the variable, or all variables
+functions [NAME]        list functions, or print the body of the function
+args                    print positional parameters
+eval COMMAND, e         execute the command in the paused script
+quit, q                 exit the script with status 1
+help, h                 print this help";
+
+/// Lines printed around the current line by `list`
+const LIST_CONTEXT: usize = 5;
+
+#[derive(Clone, Copy)]
+enum Mode {
+    /// Stop at the next command
+    Step,
+    /// Stop at the next command of the frame at the depth or outer frames
+    Next(usize),
+    /// Stop at the next command of the frames outer than the depth
+    Finish(usize),
+    /// Stop only at breakpoints
+    Continue
+}
+
+impl Mode {
+    /// Whether the command of the frame at the depth is stepped to
+    fn stops_at(self, depth: usize) -> bool {
+        match self {
+            Mode::Step => true,
+            Mode::Next(next) => depth <= next,
+            Mode::Finish(finish) => depth < finish,
+            Mode::Continue => false
+        }
+    }
+}
+
+enum Breakpoint {
+    /// Line of the file, or of the script if the file is not given
+    Line { file: Option<String>, line: usize },
+    Function(String)
+}
+
+/// What the debugger does after the command
+enum Action {
+    Resume(Mode),
+    Exit(i32),
+    Stay
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Debugger is reading commands, so the commands it executes are not stopped at
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+static MODE: Mutex<Mode> = Mutex::new(Mode::Step);
+
+/// Breakpoints with their numbers, which are kept when other breakpoints are deleted
+static BREAKPOINTS: Mutex<Vec<(usize, Breakpoint)>> = Mutex::new(Vec::new());
+
+static NEXT_NUMBER: AtomicUsize = AtomicUsize::new(1);
+
+/// Command that an empty line repeats
+static LAST_COMMAND: Mutex<String> = Mutex::new(String::new());
+
+/// File of the debugged script
+static SCRIPT: Mutex<String> = Mutex::new(String::new());
+
+/// File, line and depth of the last command, so that
+/// a line with several commands is stopped at once
+static LAST: Mutex<Option<(String, usize, usize)>> = Mutex::new(None);
+
+/// Breakpoint of the function that was called,
+/// its first command is stopped at
+static CALLED: Mutex<Option<String>> = Mutex::new(None);
+
+/// Terminal the commands are read from, or the standard input
+static INPUT: Mutex<Option<Box<dyn BufRead + Send>>> = Mutex::new(None);
+
+/// Enables the debugger for the script, which stops before its first command
+pub fn enable(script: &str) {
+    let input: Box<dyn BufRead + Send> = match File::open("/dev/tty") {
+        Ok(tty) => Box::new(BufReader::new(tty)),
+        Err(_) => Box::new(BufReader::new(io::stdin()))
+    };
+
+    *INPUT.lock().unwrap() = Some(input);
+    *SCRIPT.lock().unwrap() = script.to_string();
+    ENABLED.store(true, Ordering::SeqCst);
+
+    eprintln!("debugging {script}, type `help` for the list of commands");
+}
+
+/// Stops before the command if the debugger is enabled and the
+/// command is stepped to or has a breakpoint
+///
+/// Returns `Exit` if the script must exit
+pub fn before_command() -> ExecutionResult {
+    if !ENABLED.load(Ordering::SeqCst) || PAUSED.load(Ordering::SeqCst) {
+        return ExecutionResult::Success;
+    }
+
+    let Some(position) = current_position() else {
+        return ExecutionResult::Success;
+    };
+
+    let called = CALLED.lock().unwrap().take();
+    let current = (position.file.clone(), position.line, position.depth);
+    let same_line = LAST.lock().unwrap().replace(current.clone()) == Some(current);
+
+    let stepped = MODE.lock().unwrap().stops_at(position.depth);
+
+    let breakpoint = match called {
+        Some(called) => Some(called),
+        None if same_line => None,
+        None => line_breakpoint(&position)
+    };
+
+    if !stepped && breakpoint.is_none() {
+        return ExecutionResult::Success;
+    }
+
+    if let Some(breakpoint) = breakpoint {
+        eprintln!("breakpoint {breakpoint}");
+    }
+
+    pause(&position)
+}
+
+/// Stops at the first command of the function if it has a breakpoint
+pub fn function_called(name: &str) {
+    if !ENABLED.load(Ordering::SeqCst) || PAUSED.load(Ordering::SeqCst) || function_origin(name).is_none() {
+        return;
+    }
+
+    let breakpoint = BREAKPOINTS.lock().unwrap().iter()
+        .find(|(_, breakpoint)| matches!(breakpoint, Breakpoint::Function(function) if function == name))
+        .map(|(number, _)| format!("{number} at function `{name}`"));
+
+    if breakpoint.is_some() {
+        *CALLED.lock().unwrap() = breakpoint;
+    }
+}
+
+/// Number and location of the breakpoint at the line of the command
+fn line_breakpoint(position: &Position) -> Option<String> {
+    let script = SCRIPT.lock().unwrap().clone();
+
+    BREAKPOINTS.lock().unwrap().iter().find_map(|(number, breakpoint)| match breakpoint {
+        Breakpoint::Line { file, line } if *line == position.line => {
+            let file = file.as_deref().unwrap_or(&script);
+
+            Path::new(&position.file).ends_with(file)
+                .then(|| format!("{number} at {}:{line}", position.file))
+        },
+        _ => None
+    })
+}
+
+/// Reads and executes commands of the debugger until the script is resumed
+fn pause(position: &Position) -> ExecutionResult {
+    if let Some(snippet) = current_snippet() {
+        eprintln!("{snippet}");
+    }
+
+    PAUSED.store(true, Ordering::SeqCst);
+
+    let result = loop {
+        eprint!("(rsh-debug) ");
+
+        let mut line = String::new();
+
+        let read = match INPUT.lock().unwrap().as_mut() {
+            Some(input) => input.read_line(&mut line),
+            None => Ok(0)
+        };
+
+        // end of input quits like `quit`
+        if !matches!(read, Ok(read) if read > 0) {
+            eprintln!();
+            break ExecutionResult::Exit(1);
+        }
+
+        // empty line repeats the last command
+        let line = match line.trim() {
+            "" => LAST_COMMAND.lock().unwrap().clone(),
+            line => line.to_string()
+        };
+
+        *LAST_COMMAND.lock().unwrap() = line.clone();
+
+        match run_command(&line, position) {
+            Action::Resume(mode) => {
+                *MODE.lock().unwrap() = mode;
+                break ExecutionResult::Success;
+            },
+            Action::Exit(code) => break ExecutionResult::Exit(code),
+            Action::Stay => {}
+        }
+    };
+
+    PAUSED.store(false, Ordering::SeqCst);
+
+    result
+}
+
+fn run_command(line: &str, position: &Position) -> Action {
+    let (command, argument) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let argument = argument.trim();
+
+    match command {
+        "" => {},
+        "step" | "s" => return Action::Resume(Mode::Step),
+        "next" | "n" => return Action::Resume(Mode::Next(position.depth)),
+        "finish" | "f" => return Action::Resume(Mode::Finish(position.depth)),
+        "continue" | "c" => return Action::Resume(Mode::Continue),
+        "break" | "b" => set_breakpoint(argument),
+        "delete" | "d" => delete_breakpoints(argument),
+        "breakpoints" => list_breakpoints(),
+        "list" | "l" => list_code(position),
+        "backtrace" | "bt" => {
+            for (index, call) in call_stack().iter().enumerate() {
+                eprintln!("#{index} {call}");
+            }
+        },
+        "print" | "p" => print_variables(argument),
+        "functions" => print_functions(argument),
+        "args" => {
+            let count: usize = var("#").ok().and_then(|count| count.parse().ok()).unwrap_or(0);
+
+            for index in 0..=count {
+                eprintln!("${index} = {}", var(index.to_string()).unwrap_or_default());
+            }
+        },
+        "eval" | "e" => {
+            // `$?` of the script is kept
+            let status = last_exit_status();
+            let result = execute_code(argument);
+            set_exit_status(status);
+
+            if let ExecutionResult::Exit(code) = result {
+                return Action::Exit(code);
+            }
+        },
+        "quit" | "q" => return Action::Exit(1),
+        "help" | "h" => eprintln!("{HELP}"),
+        _ => eprintln!("{command}: unknown command, type `help` for the list of commands")
+    }
+
+    Action::Stay
+}
+
+/// Adds the breakpoint at `LINE`, `FILE:LINE` or function `NAME`
+fn set_breakpoint(argument: &str) {
+    if argument.is_empty() {
+        eprintln!("break: line or function name required");
+        return;
+    }
+
+    let breakpoint = match argument.rsplit_once(':') {
+        Some((file, line)) if line.parse::<usize>().is_ok() => {
+            Breakpoint::Line { file: Some(file.to_string()), line: line.parse().unwrap_or_default() }
+        },
+        _ => match argument.parse::<usize>() {
+            Ok(line) => Breakpoint::Line { file: None, line },
+            Err(_) => Breakpoint::Function(argument.to_string())
+        }
+    };
+
+    let number = NEXT_NUMBER.fetch_add(1, Ordering::SeqCst);
+
+    eprintln!("breakpoint {number} {}", describe_breakpoint(&breakpoint));
+    BREAKPOINTS.lock().unwrap().push((number, breakpoint));
+}
+
+fn describe_breakpoint(breakpoint: &Breakpoint) -> String {
+    match breakpoint {
+        Breakpoint::Line { file: Some(file), line } => format!("at {file}:{line}"),
+        Breakpoint::Line { file: None, line } => format!("at {}:{line}", SCRIPT.lock().unwrap()),
+        Breakpoint::Function(name) => format!("at function `{name}`")
+    }
+}
+
+fn delete_breakpoints(argument: &str) {
+    let mut breakpoints = BREAKPOINTS.lock().unwrap();
+
+    if argument.is_empty() {
+        breakpoints.clear();
+        return;
+    }
+
+    let index = argument.parse::<usize>().ok()
+        .and_then(|number| breakpoints.iter().position(|(existing, _)| *existing == number));
+
+    match index {
+        Some(index) => {
+            breakpoints.remove(index);
+        },
+        None => eprintln!("delete: {argument}: no such breakpoint")
+    }
+}
+
+fn list_breakpoints() {
+    let breakpoints = BREAKPOINTS.lock().unwrap();
+
+    if breakpoints.is_empty() {
+        eprintln!("no breakpoints");
+    }
+
+    for (number, breakpoint) in breakpoints.iter() {
+        eprintln!("{number} {}", describe_breakpoint(breakpoint));
+    }
+}
+
+/// Prints lines around the current line, marking it with `>`
+fn list_code(position: &Position) {
+    let source = match read_to_string(&position.file) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("list: {}: {err}", position.file);
+            return;
+        }
+    };
+
+    let first = position.line.saturating_sub(LIST_CONTEXT).max(1);
+    let last = position.line + LIST_CONTEXT;
+    let width = last.to_string().len();
+
+    for (index, text) in source.lines().enumerate().skip(first - 1).take(last - first + 1) {
+        let number = index + 1;
+        let marker = if number == position.line { '>' } else { ' ' };
+
+        eprintln!("{marker} {number:>width$} | {text}");
+    }
+}
+
+/// Prints the variable, or all variables except internal
+/// variables of aliases, functions and hooks
+fn print_variables(name: &str) {
+    let name = name.strip_prefix('$').unwrap_or(name);
+
+    if !name.is_empty() {
+        match var(name) {
+            Ok(value) => eprintln!("{name} = {value}"),
+            Err(_) => eprintln!("{name} is not set")
+        }
+
+        return;
+    }
+
+    let variables: BTreeMap<String, String> = vars()
+        .filter(|(name, _)| !name.starts_with("__"))
+        .collect();
+
+    for (name, value) in variables {
+        eprintln!("{name} = {value}");
+    }
+}
+
+/// Prints the body of the function, or the names of all functions
+/// with the locations where they are defined
+fn print_functions(name: &str) {
+    if !name.is_empty() {
+        match var("__FN_".to_owned() + name) {
+            // bodies of inline functions are not in braces
+            Ok(body) if body.starts_with(char::is_whitespace) => eprintln!("fn {name} {{{body}}}"),
+            Ok(body) => eprintln!("fn {name} {body}"),
+            Err(_) => eprintln!("{name}: function not found")
+        }
+
+        return;
+    }
+
+    let functions: BTreeMap<String, String> = vars()
+        .filter_map(|(name, _)| name.strip_prefix("__FN_").map(str::to_string))
+        .map(|name| {
+            let origin = function_origin(&name)
+                .map(|origin| format!(" ({}:{})", origin.file, origin.line))
+                .unwrap_or_default();
+
+            (name, origin)
+        })
+        .collect();
+
+    for (name, origin) in functions {
+        eprintln!("{name}{origin}");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(file: &str, line: usize, depth: usize) -> Position {
+        Position { file: file.to_string(), line, depth }
+    }
+
+    /// Numbers and descriptions of the breakpoints
+    fn breakpoints() -> Vec<(usize, String)> {
+        BREAKPOINTS.lock().unwrap().iter()
+            .map(|(number, breakpoint)| (*number, describe_breakpoint(breakpoint)))
+            .collect()
+    }
+
+    #[test]
+    fn steps_into_over_and_out_of_functions() {
+        assert!(Mode::Step.stops_at(1));
+        assert!(Mode::Step.stops_at(3));
+
+        // `next` doesn't stop inside the called functions
+        assert!(Mode::Next(2).stops_at(1));
+        assert!(Mode::Next(2).stops_at(2));
+        assert!(!Mode::Next(2).stops_at(3));
+
+        // `finish` stops only after the function returns
+        assert!(Mode::Finish(2).stops_at(1));
+        assert!(!Mode::Finish(2).stops_at(2));
+        assert!(!Mode::Finish(2).stops_at(3));
+
+        assert!(!Mode::Continue.stops_at(1));
+    }
+
+    #[test]
+    fn resumes_with_the_depth_of_the_command() {
+        let current = position("deploy.rsh", 4, 2);
+
+        assert!(matches!(run_command("step", &current), Action::Resume(Mode::Step)));
+        assert!(matches!(run_command("s", &current), Action::Resume(Mode::Step)));
+        assert!(matches!(run_command("next", &current), Action::Resume(Mode::Next(2))));
+        assert!(matches!(run_command("n", &current), Action::Resume(Mode::Next(2))));
+        assert!(matches!(run_command("finish", &current), Action::Resume(Mode::Finish(2))));
+        assert!(matches!(run_command("c", &current), Action::Resume(Mode::Continue)));
+        assert!(matches!(run_command("quit", &current), Action::Exit(1)));
+        assert!(matches!(run_command("nope", &current), Action::Stay));
+        assert!(matches!(run_command("", &current), Action::Stay));
+    }
+
+    /// Breakpoints are global, so a single test changes them
+    #[test]
+    fn sets_matches_and_deletes_breakpoints() {
+        *SCRIPT.lock().unwrap() = String::from("deploy.rsh");
+        let first = NEXT_NUMBER.load(Ordering::SeqCst);
+
+        run_command("break 12", &position("deploy.rsh", 1, 1));
+        run_command("b lib/util.rsh:3", &position("deploy.rsh", 1, 1));
+        run_command("break upload", &position("deploy.rsh", 1, 1));
+        run_command("break", &position("deploy.rsh", 1, 1));
+
+        assert_eq!(breakpoints(), [
+            (first, String::from("at deploy.rsh:12")),
+            (first + 1, String::from("at lib/util.rsh:3")),
+            (first + 2, String::from("at function `upload`"))
+        ]);
+
+        // line without a file is a line of the script, files match by their ends
+        assert_eq!(line_breakpoint(&position("deploy.rsh", 12, 1)), Some(format!("{first} at deploy.rsh:12")));
+        assert_eq!(line_breakpoint(&position("/src/deploy.rsh", 12, 3)), Some(format!("{first} at /src/deploy.rsh:12")));
+        assert_eq!(line_breakpoint(&position("/src/lib/util.rsh", 3, 2)), Some(format!("{} at /src/lib/util.rsh:3", first + 1)));
+        assert_eq!(line_breakpoint(&position("deploy.rsh", 11, 1)), None);
+        assert_eq!(line_breakpoint(&position("other.rsh", 12, 1)), None);
+        assert_eq!(line_breakpoint(&position("util.rsh", 3, 1)), None);
+
+        // numbers are kept when other breakpoints are deleted
+        run_command("delete 1000", &position("deploy.rsh", 1, 1));
+        run_command("delete x", &position("deploy.rsh", 1, 1));
+        run_command(&format!("delete {first}"), &position("deploy.rsh", 1, 1));
+        run_command("break 5", &position("deploy.rsh", 1, 1));
+
+        assert_eq!(breakpoints(), [
+            (first + 1, String::from("at lib/util.rsh:3")),
+            (first + 2, String::from("at function `upload`")),
+            (first + 3, String::from("at deploy.rsh:5"))
+        ]);
+        assert_eq!(line_breakpoint(&position("deploy.rsh", 12, 1)), None);
+
+        run_command("d", &position("deploy.rsh", 1, 1));
+        assert!(breakpoints().is_empty());
+    }
+}
@@ -0,0 +1,403 @@
+//! Locations of errors in scripts
+//!
+//! Each execution of code (script, function body, file executed
+//! with `@exec`) pushes a frame with the code, where it comes from
+//! and the command being executed. Errors logged while the code
+//! of a file runs point to the command:
+//!
+//! ```text
+//! rsh: deploy.rsh:12:5: command not found: rsnyc
+//!    |
+//! 12 |     rsnyc -a build/ &host
+//!    |     ^^^^^^^^^^^^^^^^^^^^^
+//!    = in function `upload`, called at deploy.rsh:20:1
+//! ```
+//!
+//! Code typed in the interactive shell, traps and inline functions
+//! (`fn name body`) have no file, so their errors have no location.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use crate::colors::red;
+use crate::parse_command::Span;
+
+/// File and line where the code starts
+#[derive(Clone)]
+pub struct Origin {
+    pub file: String,
+    /// Line number, counted from 1
+    pub line: usize,
+    /// Text of the line before the code, e.g. `fn name {`
+    /// before the function body
+    pub prefix: String
+}
+
+impl Origin {
+    pub fn file(file: &str) -> Origin {
+        Origin { file: file.to_string(), line: 1, prefix: String::new() }
+    }
+}
+
+/// Part of the code that was replaced before execution,
+/// e.g. argument placeholder of the function
+pub struct Substitution {
+    /// Replacement in the executed code
+    pub executed: Span,
+    /// Replaced part of the source code
+    pub source: Span
+}
+
+pub struct Frame {
+    /// What is executed, e.g. "function `greet`", shown in the call stack
+    call: Option<String>,
+    origin: Option<Origin>,
+    /// Code as it is written in the file
+    source: String,
+    substitutions: Vec<Substitution>,
+    /// Command being executed, offsets in the executed code
+    current: Option<Span>
+}
+
+impl Frame {
+    pub fn new(call: Option<String>, origin: Option<Origin>, source: &str, substitutions: Vec<Substitution>) -> Frame {
+        Frame { call, origin, source: source.to_string(), substitutions, current: None }
+    }
+
+    /// Offset in the source that corresponds to the
+    /// offset in the executed code
+    fn source_offset(&self, offset: usize) -> usize {
+        let mut shift = 0isize;
+
+        for substitution in &self.substitutions {
+            if offset < substitution.executed.start {
+                break;
+            }
+
+            if offset < substitution.executed.end {
+                return substitution.source.start;
+            }
+
+            shift = substitution.source.end as isize - substitution.executed.end as isize;
+        }
+
+        offset.saturating_add_signed(shift).min(self.source.len())
+    }
+
+    /// Text of the line in the file before the offset in the source
+    fn line_before(&self, origin: &Origin, offset: usize) -> String {
+        let before = &self.source[..offset];
+
+        match before.rfind('\n') {
+            Some(start) => before[start + 1..].to_string(),
+            None => origin.prefix.clone() + before
+        }
+    }
+
+    /// Line and column of the offset in the source
+    fn position(&self, origin: &Origin, offset: usize) -> (usize, usize) {
+        let line = origin.line + self.source[..offset].matches('\n').count();
+
+        (line, self.line_before(origin, offset).chars().count() + 1)
+    }
+
+    /// Origin of the code that starts at the offset in
+    /// the executed code, e.g. of the function body
+    fn origin_at(&self, offset: usize) -> Option<Origin> {
+        let origin = self.origin.as_ref()?;
+        let offset = self.source_offset(offset);
+        let (line, _) = self.position(origin, offset);
+
+        Some(Origin { file: origin.file.clone(), line, prefix: self.line_before(origin, offset) })
+    }
+
+    /// Location of the current command, e.g. `script.rsh:3:5`
+    fn location(&self) -> Option<String> {
+        let origin = self.origin.as_ref()?;
+        let (line, column) = self.position(origin, self.source_offset(self.current?.start));
+
+        Some(format!("{}:{line}:{column}", origin.file))
+    }
+
+    /// Line of the current command with carets under it
+    fn snippet(&self) -> Option<String> {
+        let origin = self.origin.as_ref()?;
+        let current = self.current?;
+
+        let start = self.source_offset(current.start);
+        let end = self.source_offset(current.end).max(start);
+
+        let before = self.line_before(origin, start);
+        let line_end = self.source[start..].find('\n').map_or(self.source.len(), |index| start + index);
+        let text = before.clone() + &self.source[start..line_end];
+
+        // tabs are kept so that carets are aligned in the terminal
+        let padding: String = before.chars()
+            .map(|char| if char == '\t' { '\t' } else { ' ' })
+            .collect();
+
+        let width = self.source[start..end.min(line_end)].chars().count().max(1);
+
+        let (line, _) = self.position(origin, start);
+        let gutter = " ".repeat(line.to_string().len());
+
+        Some(format!("{gutter} |\n{line} | {text}\n{gutter} | {padding}{}", red(&"^".repeat(width))))
+    }
+}
+
+/// Frames of the running code, the innermost is the last
+static FRAMES: Mutex<Vec<Frame>> = Mutex::new(Vec::new());
+
+/// Where bodies of the functions start
+static FUNCTIONS: Mutex<BTreeMap<String, Origin>> = Mutex::new(BTreeMap::new());
+
+pub fn push_frame(frame: Frame) {
+    FRAMES.lock().unwrap().push(frame);
+}
+
+pub fn pop_frame() {
+    FRAMES.lock().unwrap().pop();
+}
+
+/// Sets the command being executed in the innermost frame
+pub fn set_current(span: Span) {
+    if let Some(frame) = FRAMES.lock().unwrap().last_mut() {
+        frame.current = Some(span);
+    }
+}
+
+/// Whether the code of the innermost frame comes from a file
+pub fn has_origin() -> bool {
+    FRAMES.lock().unwrap().last().is_some_and(|frame| frame.origin.is_some())
+}
+
+/// Remembers where the body of the function starts, by its
+/// offset in the executed code of the innermost frame
+pub fn define_function(name: &str, body_start: Option<usize>) {
+    let frames = FRAMES.lock().unwrap();
+
+    let origin = body_start.zip(frames.last()).and_then(|(offset, frame)| frame.origin_at(offset));
+
+    match origin {
+        Some(origin) => FUNCTIONS.lock().unwrap().insert(name.to_string(), origin),
+        None => FUNCTIONS.lock().unwrap().remove(name)
+    };
+}
+
+pub fn function_origin(name: &str) -> Option<Origin> {
+    FUNCTIONS.lock().unwrap().get(name).cloned()
+}
+
+/// Position of the command being executed
+pub struct Position {
+    pub file: String,
+    pub line: usize,
+    /// Number of frames, the outermost frame is 1
+    pub depth: usize
+}
+
+/// Position of the current command of the innermost
+/// frame, if its code comes from a file
+pub fn current_position() -> Option<Position> {
+    let frames = FRAMES.lock().unwrap();
+    let frame = frames.last()?;
+    let origin = frame.origin.as_ref()?;
+    let (line, _) = frame.position(origin, frame.source_offset(frame.current?.start));
+
+    Some(Position { file: origin.file.clone(), line, depth: frames.len() })
+}
+
+/// Location of the current command followed by its snippet
+pub fn current_snippet() -> Option<String> {
+    let frames = FRAMES.lock().unwrap();
+    let frame = frames.last()?;
+
+    Some(format!("{}\n{}", frame.location()?, frame.snippet()?))
+}
+
+/// Calls of the frames with locations of their
+/// current commands, from the innermost frame
+pub fn call_stack() -> Vec<String> {
+    FRAMES.lock().unwrap().iter().rev()
+        .map(|frame| {
+            let call = frame.call.as_deref().unwrap_or("code");
+
+            match frame.location() {
+                Some(location) => format!("{call} at {location}"),
+                None => call.to_string()
+            }
+        })
+        .collect()
+}
+
+/// Formats the error message with the location of the current
+/// command, its snippet and the call stack, if the code comes
+/// from a file
+pub fn describe(message: &str) -> String {
+    let frames = FRAMES.lock().unwrap();
+
+    let Some(frame) = frames.last() else {
+        return message.to_string();
+    };
+
+    let (Some(location), Some(snippet)) = (frame.location(), frame.snippet()) else {
+        return message.to_string();
+    };
+
+    let mut description = format!("{location}: {message}\n{snippet}");
+    let gutter = snippet.find('|').unwrap_or(1) - 1;
+
+    // callers are not shown for the outermost frame
+    for (index, called) in frames.iter().enumerate().skip(1).rev() {
+        let Some(call) = &called.call else { continue };
+
+        description += &format!("\n{} = in {call}", " ".repeat(gutter));
+
+        if let Some(location) = frames[index - 1].location() {
+            description += &format!(", called at {location}");
+        }
+    }
+
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_command::{NodeKind, parse_command};
+
+    fn span(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /// Frame of the file with the current command at the span
+    fn script(source: &str, current: Span) -> Frame {
+        let mut frame = Frame::new(None, Some(Origin::file("script.rsh")), source, Vec::new());
+        frame.current = Some(current);
+        frame
+    }
+
+    /// Carets as they are rendered
+    fn carets(width: usize) -> String {
+        red(&"^".repeat(width))
+    }
+
+    #[test]
+    fn maps_offsets_through_substitutions() {
+        // `echo &who and &who` executed as `echo you and you`
+        let substitutions = vec![
+            Substitution { executed: span(5, 8), source: span(5, 9) },
+            Substitution { executed: span(13, 16), source: span(14, 18) }
+        ];
+        let frame = Frame::new(None, None, "echo &who and &who", substitutions);
+
+        assert_eq!(frame.source_offset(0), 0);
+        assert_eq!(frame.source_offset(5), 5);
+        // inside of the replacement points to the placeholder
+        assert_eq!(frame.source_offset(7), 5);
+        assert_eq!(frame.source_offset(8), 9);
+        assert_eq!(frame.source_offset(9), 10);
+        assert_eq!(frame.source_offset(14), 14);
+        assert_eq!(frame.source_offset(16), 18);
+        // offsets after the end are limited to the source
+        assert_eq!(frame.source_offset(40), 18);
+    }
+
+    #[test]
+    fn longer_replacements_shift_back() {
+        // `&x y` executed as `something y`
+        let frame = Frame::new(None, None, "&x y", vec![Substitution { executed: span(0, 9), source: span(0, 2) }]);
+
+        assert_eq!(frame.source_offset(9), 2);
+        assert_eq!(frame.source_offset(10), 3);
+    }
+
+    #[test]
+    fn locates_lines_and_columns() {
+        let source = "true\n  false x\nls\n";
+
+        assert_eq!(script(source, span(0, 4)).location().as_deref(), Some("script.rsh:1:1"));
+        assert_eq!(script(source, span(7, 14)).location().as_deref(), Some("script.rsh:2:3"));
+        assert_eq!(script(source, span(15, 17)).location().as_deref(), Some("script.rsh:3:1"));
+
+        // code without a file has no location
+        let mut frame = Frame::new(None, None, source, Vec::new());
+        frame.current = Some(span(0, 4));
+        assert_eq!(frame.location(), None);
+        assert_eq!(frame.snippet(), None);
+    }
+
+    #[test]
+    fn renders_carets_under_the_command() {
+        let frame = script("true\n  false x\n", span(7, 14));
+
+        assert_eq!(frame.snippet().unwrap(), format!("  |\n2 |   false x\n  |   {}", carets(7)));
+
+        // commands that span lines are underlined to the end of the line
+        let frame = script("if true; then\nfalse\nfi", span(0, 22));
+        assert_eq!(frame.snippet().unwrap(), format!("  |\n1 | if true; then\n  | {}", carets(13)));
+
+        // gutter is as wide as the line number
+        let source = "\n".repeat(11) + "ls";
+        assert_eq!(script(&source, span(11, 13)).snippet().unwrap(), format!("   |\n12 | ls\n   | {}", carets(2)));
+    }
+
+    #[test]
+    fn keeps_tabs_in_padding() {
+        let frame = script("\t\tfalse", span(2, 7));
+
+        assert_eq!(frame.location().as_deref(), Some("script.rsh:1:3"));
+        assert_eq!(frame.snippet().unwrap(), format!("  |\n1 | \t\tfalse\n  | \t\t{}", carets(5)));
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        // `é` and `→` take 2 and 3 bytes
+        let frame = script("echo é → x; rsnyc", span(15, 20));
+
+        assert_eq!(frame.location().as_deref(), Some("script.rsh:1:13"));
+        assert_eq!(frame.snippet().unwrap(), format!("  |\n1 | echo é → x; rsnyc\n  | {}{}", " ".repeat(12), carets(5)));
+
+        let frame = script("echo é", span(0, 7));
+        assert_eq!(frame.snippet().unwrap(), format!("  |\n1 | echo é\n  | {}", carets(6)));
+    }
+
+    #[test]
+    fn rebases_function_bodies() {
+        let source = "echo start\nfn upload {\n    rsnyc -a build/ &host\n}\nfn quick { ls &dir\n}\n";
+        let file = script(source, span(0, 10));
+
+        let bodies: Vec<Span> = parse_command(source).unwrap().into_iter()
+            .filter_map(|node| match node.kind {
+                NodeKind::Function { body, .. } => Some(body),
+                _ => None
+            })
+            .collect();
+
+        // body on its own lines
+        let origin = file.origin_at(bodies[0].start).unwrap();
+        let body = &source[bodies[0].start..bodies[0].end];
+        let placeholder = body.find("&host").unwrap();
+        let executed = body.replace("&host", "example.com");
+        let command = executed.find("rsnyc").unwrap();
+
+        let mut function = Frame::new(Some(String::from("function `upload`")), Some(origin), body, vec![Substitution {
+            executed: span(placeholder, placeholder + "example.com".len()),
+            source: span(placeholder, placeholder + "&host".len())
+        }]);
+        function.current = Some(span(command, executed.trim_end().len()));
+
+        assert_eq!(function.location().as_deref(), Some("script.rsh:3:5"));
+        assert_eq!(function.snippet().unwrap(), format!("  |\n3 |     rsnyc -a build/ &host\n  |     {}", carets(21)));
+
+        // body on the line of the definition keeps its prefix
+        let origin = file.origin_at(bodies[1].start).unwrap();
+        let body = &source[bodies[1].start..bodies[1].end];
+        let command = body.find("ls").unwrap();
+
+        let mut function = Frame::new(None, Some(origin), body, Vec::new());
+        function.current = Some(span(command, command + 7));
+
+        assert_eq!(function.location().as_deref(), Some("script.rsh:5:12"));
+        assert_eq!(function.snippet().unwrap(), format!("  |\n5 | fn quick {{ ls &dir\n  |            {}", carets(7)));
+    }
+}
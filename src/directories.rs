@@ -0,0 +1,331 @@
+//! Working directory and directory stack
+//!
+//! Logical path of the working directory is kept in `PWD`,
+//! the way it was typed, so `cd ..` after following a symbolic
+//! link returns to the directory that contains the link.
+//! Previous working directory is kept in `OLDPWD`.
+//!
+//! Directory stack is changed with `pushd` and `popd`. Its first
+//! entry is always the working directory, so `~0` refers to the
+//! working directory, `~1` to the next entry, and `~-0` to the
+//! last one.
+
+use std::env::{current_dir, set_current_dir, set_var, var};
+use std::error::Error;
+use std::io;
+use std::os::unix::fs::MetadataExt;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+use crate::execute::ExecutionResult;
+use crate::hooks::run_hooks;
+use crate::prompt::abbreviate_home;
+
+/// Entries of the stack after the working directory
+static STACK: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Sets `PWD` when the shell starts
+///
+/// Inherited `PWD` is kept if it refers to the working
+/// directory, since it may contain symbolic links
+pub fn init_working_directory() {
+    set_var("PWD", working_directory());
+}
+
+/// Logical path of the working directory
+pub fn working_directory() -> PathBuf {
+    let physical = current_dir().unwrap_or_default();
+
+    let logical = match var("PWD") {
+        Ok(logical) if Path::new(&logical).is_absolute() => PathBuf::from(logical),
+        _ => return physical
+    };
+
+    match (logical.metadata(), physical.metadata()) {
+        (Ok(logical_meta), Ok(physical_meta))
+            if logical_meta.dev() == physical_meta.dev() && logical_meta.ino() == physical_meta.ino() => logical,
+        _ => physical
+    }
+}
+
+/// Changes the working directory and updates `PWD` and `OLDPWD`
+///
+/// With `physical`, symbolic links are resolved. Otherwise `..`
+/// removes the previous component of the logical path, and the
+/// physical path is used only if the logical one doesn't exist
+pub fn set_working_directory(path: &Path, physical: bool) -> io::Result<()> {
+    let previous = working_directory();
+    let logical = normalize(&previous.join(path));
+
+    if !physical && set_current_dir(&logical).is_ok() {
+        set_var("PWD", logical);
+    } else {
+        set_current_dir(path)?;
+        set_var("PWD", current_dir()?);
+    }
+
+    set_var("OLDPWD", &previous);
+
+    let current = working_directory();
+
+    if current != previous {
+        run_hooks("chpwd", &[current.display().to_string()]);
+    }
+
+    Ok(())
+}
+
+/// Removes `.` and `..` components from the absolute path
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {},
+            Component::ParentDir => {
+                normalized.pop();
+            },
+            component => normalized.push(component)
+        }
+    }
+
+    normalized
+}
+
+/// Index in the list of `dirs` referenced as `+N` or `-N`, where `N`
+/// counts from the first or from the last entry. Number without the
+/// sign counts from the first entry
+///
+/// Returns `None` if the reference is not a number
+fn stack_index(reference: &str, length: usize) -> Option<Result<usize, Box<dyn Error>>> {
+    let (from_end, number) = match reference.strip_prefix('-') {
+        Some(number) => (true, number),
+        None => (false, reference.strip_prefix('+').unwrap_or(reference))
+    };
+
+    let number = number.parse::<usize>().ok()?;
+
+    if number >= length {
+        return Some(Err(Box::<dyn Error>::from("directory stack index out of range")));
+    }
+
+    Some(Ok(if from_end { length - 1 - number } else { number }))
+}
+
+/// Entry of the stack referenced as `~N`, `~+N` or `~-N`
+///
+/// Returns `None` if the word is not a reference to the stack
+pub fn stack_entry(word: &str) -> Option<Result<PathBuf, Box<dyn Error>>> {
+    let reference = word.strip_prefix('~')?;
+    let list = directory_list();
+
+    let entry = stack_index(reference, list.len())?
+        .map(|index| list[index].clone())
+        .map_err(|err| Box::<dyn Error>::from(format!("{word}: {err}")));
+
+    Some(entry)
+}
+
+/// Working directory followed by the stack
+fn directory_list() -> Vec<PathBuf> {
+    let mut list = vec![working_directory()];
+    list.extend(STACK.lock().unwrap().iter().cloned());
+
+    list
+}
+
+fn print_list() {
+    let list: Vec<String> = directory_list().iter()
+        .map(|directory| abbreviate_home(directory.display().to_string()))
+        .collect();
+
+    println!("{}", list.join(" "));
+}
+
+/// Implementation of shell built-in `pushd` function
+///
+/// `pushd dir` changes the directory and pushes the previous one
+/// to the stack, `pushd` swaps the first two entries, and
+/// `pushd +N` rotates the stack, so that `N`-th entry is the first
+pub fn push_directory(args: &[String]) -> ExecutionResult {
+    let mut stack = STACK.lock().unwrap();
+    let current = working_directory();
+
+    let mut list = match args.first().map(String::as_str) {
+        None if stack.is_empty() => return ExecutionResult::Error(Box::<dyn Error>::from("pushd: no other directory")),
+        None => {
+            let mut list = vec![stack[0].clone(), current];
+            list.extend(stack[1..].iter().cloned());
+            list
+        },
+        Some(reference) => match stack_index(reference, stack.len() + 1) {
+            Some(Ok(index)) => {
+                let mut list = vec![current];
+                list.extend(stack.iter().cloned());
+                list.rotate_left(index);
+                list
+            },
+            Some(Err(err)) => return ExecutionResult::Error(Box::<dyn Error>::from(format!("pushd: {reference}: {err}"))),
+            None => {
+                let mut list = vec![PathBuf::from(reference), current];
+                list.extend(stack.iter().cloned());
+                list
+            }
+        }
+    };
+
+    if let Err(err) = set_working_directory(&list[0], false) {
+        return ExecutionResult::Error(Box::<dyn Error>::from(format!("pushd: {}: {err}", list[0].display())));
+    }
+
+    list.remove(0);
+    *stack = list;
+    drop(stack);
+
+    print_list();
+
+    ExecutionResult::Success
+}
+
+/// Implementation of shell built-in `popd` function
+///
+/// `popd` removes the first entry and changes the directory to the
+/// next one, `popd +N` removes `N`-th entry without changing directory
+pub fn pop_directory(args: &[String]) -> ExecutionResult {
+    let mut stack = STACK.lock().unwrap();
+
+    if stack.is_empty() {
+        return ExecutionResult::Error(Box::<dyn Error>::from("popd: directory stack empty"));
+    }
+
+    let index = match args.first().map(|reference| stack_index(reference, stack.len() + 1)) {
+        None => 0,
+        Some(Some(Ok(index))) => index,
+        Some(Some(Err(err))) => return ExecutionResult::Error(Box::<dyn Error>::from(format!("popd: {}: {err}", args[0]))),
+        Some(None) => return ExecutionResult::Error(Box::<dyn Error>::from(format!("popd: {}: invalid argument", args[0])))
+    };
+
+    if index > 0 {
+        stack.remove(index - 1);
+    } else {
+        if let Err(err) = set_working_directory(&stack[0], false) {
+            return ExecutionResult::Error(Box::<dyn Error>::from(format!("popd: {}: {err}", stack[0].display())));
+        }
+
+        stack.remove(0);
+    }
+
+    drop(stack);
+
+    print_list();
+
+    ExecutionResult::Success
+}
+
+/// Implementation of shell built-in `dirs` function
+///
+/// Prints the directory stack. Options:
+///
+/// * `-v` prints one entry per line with its index
+/// * `-p` prints one entry per line
+/// * `-l` doesn't abbreviate home directory with `~`
+/// * `-c` clears the stack
+pub fn list_directories(args: &[String]) -> ExecutionResult {
+    let mut numbered = false;
+    let mut lines = false;
+    let mut full = false;
+
+    for arg in args {
+        match arg.as_str() {
+            "-v" => numbered = true,
+            "-p" => lines = true,
+            "-l" => full = true,
+            "-c" => {
+                STACK.lock().unwrap().clear();
+                return ExecutionResult::Success;
+            },
+            option => return ExecutionResult::Error(Box::<dyn Error>::from(format!("dirs: invalid option: {option}")))
+        }
+    }
+
+    let list: Vec<String> = directory_list().iter()
+        .map(|directory| directory.display().to_string())
+        .map(|directory| if full { directory } else { abbreviate_home(directory) })
+        .collect();
+
+    if numbered {
+        for (index, directory) in list.iter().enumerate() {
+            println!("{index:2}  {directory}");
+        }
+    } else if lines {
+        for directory in list {
+            println!("{directory}");
+        }
+    } else {
+        println!("{}", list.join(" "));
+    }
+
+    ExecutionResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Index of the reference, `Err` as its message
+    fn index(reference: &str, length: usize) -> Option<Result<usize, String>> {
+        stack_index(reference, length).map(|index| index.map_err(|err| err.to_string()))
+    }
+
+    #[test]
+    fn counts_from_the_first_entry() {
+        assert_eq!(index("0", 3), Some(Ok(0)));
+        assert_eq!(index("2", 3), Some(Ok(2)));
+        assert_eq!(index("+0", 3), Some(Ok(0)));
+        assert_eq!(index("+1", 3), Some(Ok(1)));
+    }
+
+    #[test]
+    fn counts_from_the_last_entry() {
+        assert_eq!(index("-0", 3), Some(Ok(2)));
+        assert_eq!(index("-2", 3), Some(Ok(0)));
+        assert_eq!(index("-0", 1), Some(Ok(0)));
+    }
+
+    #[test]
+    fn rejects_indices_out_of_range() {
+        let error = Some(Err(String::from("directory stack index out of range")));
+
+        assert_eq!(index("3", 3), error);
+        assert_eq!(index("+3", 3), error);
+        assert_eq!(index("-3", 3), error);
+        assert_eq!(index("0", 0), error);
+        assert_eq!(index("-0", 0), error);
+    }
+
+    #[test]
+    fn ignores_words_that_are_not_numbers() {
+        assert_eq!(index("", 3), None);
+        assert_eq!(index("+", 3), None);
+        assert_eq!(index("-", 3), None);
+        assert_eq!(index("+-1", 3), None);
+        assert_eq!(index("--1", 3), None);
+        assert_eq!(index("1a", 3), None);
+        assert_eq!(index("/tmp", 3), None);
+    }
+
+    #[test]
+    fn references_the_working_directory() {
+        assert_eq!(stack_entry("~0").unwrap().unwrap(), working_directory());
+        assert_eq!(stack_entry("~+0").unwrap().unwrap(), working_directory());
+        assert_eq!(stack_entry("~99").unwrap().unwrap_err().to_string(), "~99: directory stack index out of range");
+        assert!(stack_entry("~/src").is_none());
+        assert!(stack_entry("0").is_none());
+    }
+
+    #[test]
+    fn normalizes_logical_paths() {
+        assert_eq!(normalize(Path::new("/a/./b/../c")), PathBuf::from("/a/c"));
+        assert_eq!(normalize(Path::new("/a/b/../..")), PathBuf::from("/"));
+        assert_eq!(normalize(Path::new("/..")), PathBuf::from("/"));
+    }
+}
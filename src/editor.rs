@@ -0,0 +1,163 @@
+//! Integration of the shell with the line editor
+
+use std::borrow::Cow;
+use std::env::current_dir;
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+use std::sync::{Arc, Mutex};
+use rustyline::completion::{Completer, Pair};
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::{ValidationContext, ValidationResult, Validator};
+use rustyline::{Cmd, ConditionalEventHandler, Context, Event, EventContext, Helper, RepeatCount, Result};
+use crate::colors::*;
+use crate::completion::complete;
+use crate::highlight::highlight;
+use crate::history::with_entries;
+use crate::parse_command::parse_command;
+use crate::prompt::visible_width;
+
+#[derive(Default)]
+pub struct RshHelper {
+    /// Expanded right prompt, displayed on the first input line
+    pub right_prompt: String
+}
+
+impl Helper for RshHelper {}
+
+impl Completer for RshHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Result<(usize, Vec<Pair>)> {
+        Ok(complete(line, pos))
+    }
+}
+
+impl Hinter for RshHelper {
+    type Hint = String;
+
+    /// Suggests the rest of the most recent history entry that
+    /// starts with the line, like fish does. Entries that were
+    /// executed in the current directory and succeeded are preferred
+    fn hint(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> Option<String> {
+        if line.is_empty() || pos < line.len() {
+            return None;
+        }
+
+        let directory = current_dir().unwrap_or_default().display().to_string();
+
+        with_entries(|entries| {
+            let mut matched = entries.iter()
+                .rev()
+                .filter(|entry| entry.command.len() > line.len() && entry.command.starts_with(line) && !entry.command.contains('\n'));
+
+            let fallback = matched.clone().next();
+
+            matched
+                .find(|entry| entry.status == 0 && entry.directory == directory)
+                .or(fallback)
+                .map(|entry| entry.command[pos..].to_string())
+        })
+    }
+}
+
+/// Accepts the hint with `End`, the same way as `Right` does
+pub struct AcceptHint;
+
+impl ConditionalEventHandler for AcceptHint {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        (ctx.has_hint() && ctx.pos() == ctx.line().len()).then_some(Cmd::CompleteHint)
+    }
+}
+
+/// Opens history picker with `Ctrl-R`
+///
+/// Editor can't draw over the whole screen, so the line is saved
+/// and the editor is interrupted. The shell opens the picker
+/// when it finds the saved line
+pub struct OpenPicker(pub Arc<Mutex<Option<String>>>);
+
+impl ConditionalEventHandler for OpenPicker {
+    fn handle(&self, _evt: &Event, _n: RepeatCount, _positive: bool, ctx: &EventContext) -> Option<Cmd> {
+        *self.0.lock().unwrap() = Some(ctx.line().to_string());
+        Some(Cmd::Interrupt)
+    }
+}
+
+impl Validator for RshHelper {
+    /// Continues the input on the next line if the command is
+    /// incomplete, e.g. `fn f {` or `echo a |`. Other syntax
+    /// errors are reported when the command is executed
+    fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult> {
+        match parse_command(ctx.input()) {
+            Err(err) if err.incomplete => Ok(ValidationResult::Incomplete),
+            _ => Ok(ValidationResult::Valid(None))
+        }
+    }
+}
+
+impl Highlighter for RshHelper {
+    fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
+        Cow::Owned(highlight(line))
+    }
+
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        Cow::Owned(format!("{DIMMED}{hint}{RESET}"))
+    }
+
+    /// Line is highlighted again on every change, since
+    /// color of the command depends on the whole word
+    fn highlight_char(&self, _line: &str, _pos: usize) -> bool {
+        true
+    }
+
+    /// Appends the right prompt to the prompt
+    ///
+    /// Editor computes width of the prompt from the original
+    /// string, so the right prompt doesn't move the cursor
+    fn highlight_prompt<'b, 's: 'b, 'p: 'b>(&'s self, prompt: &'p str, _default: bool) -> Cow<'b, str> {
+        let width = visible_width(&self.right_prompt);
+
+        match terminal_width() {
+            Some(columns) if width > 0 && width < columns => {
+                // save cursor, move to the column, print the prompt and restore cursor
+                Cow::Owned(format!("{prompt}\x1b7\x1b[{}G{}\x1b8", columns - width + 1, self.right_prompt))
+            },
+            _ => Cow::Borrowed(prompt)
+        }
+    }
+}
+
+/// Erases the prompt and the input line that was left
+/// on the screen when the editor was interrupted
+pub fn erase_input(prompt: &str, line: &str) {
+    let columns = terminal_width().unwrap_or(80).max(1);
+
+    let rows: usize = format!("{prompt}{line}")
+        .split('\n')
+        .map(|part| visible_width(part).div_ceil(columns).max(1))
+        .sum();
+
+    print!("\x1b[{rows}A\r\x1b[J");
+    let _ = io::stdout().flush();
+}
+
+fn terminal_width() -> Option<usize> {
+    terminal_size().map(|(columns, _)| columns)
+}
+
+/// Columns and rows of the terminal
+pub fn terminal_size() -> Option<(usize, usize)> {
+    let mut size = MaybeUninit::<libc::winsize>::zeroed();
+
+    unsafe {
+        if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, size.as_mut_ptr()) != 0 {
+            return None;
+        }
+
+        let size = size.assume_init();
+
+        // size of some pseudo terminals is unknown
+        (size.ws_col > 0 && size.ws_row > 0).then_some((size.ws_col as usize, size.ws_row as usize))
+    }
+}
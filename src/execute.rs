@@ -1,63 +1,215 @@
-use core::str;
+use std::env::{set_var, var};
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
 use std::path::Path;
-use std::io::Read;
+use std::io::{self, Read, Write};
 use std::os::unix::process::CommandExt;
-use std::process::{Child, Command, Stdio, exit};
+use std::process::{Child, Command, Stdio};
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use crate::builtins;
+use crate::completion::define_completion;
+use crate::debugger;
+use crate::diagnostics::{Frame, Origin, define_function, has_origin, pop_frame, push_frame, set_current};
+use crate::directories::{list_directories, pop_directory, push_directory};
+use crate::history::history_builtin;
+use crate::hooks::hook_builtin;
 use crate::instants::*;
-use crate::parse_command::parse_command;
+use crate::jobs::{add_job, list_jobs};
+use crate::jump::jump;
+use crate::lexer::{Token, TokenKind, escape, tokenize};
+use crate::not_found::command_not_found;
+use crate::options;
+use crate::parse_command::{Node, NodeKind, Span, parse_command};
+use crate::signals::{check_signals, child_dispositions, interrupted, run_err_trap, trap_builtin};
 use crate::utils::*;
 
+/// Number of `if` and `while` conditions being executed
+static CONDITIONS: AtomicUsize = AtomicUsize::new(0);
+
 pub enum ExecutionResult {
     Success,
+    /// Command finished with non-zero exit status
+    Failure(i32),
     Error(Box<dyn Error>),
-    Exit
+    /// Shell must exit with the given status
+    Exit(i32)
 }
 
-pub fn execute(command_with_pipes: &str) -> ExecutionResult {
-    if command_with_pipes.len() == 0 || command_with_pipes.starts_with("#") {
-        return ExecutionResult::Success;
+impl ExecutionResult {
+    /// Exit status that corresponds to the result
+    pub fn status(&self) -> i32 {
+        match self {
+            ExecutionResult::Success => 0,
+            ExecutionResult::Failure(code) => *code,
+            ExecutionResult::Error(_) => 1,
+            ExecutionResult::Exit(code) => *code
+        }
+    }
+}
+
+/// What the command word resolves to
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum CommandKind {
+    Alias,
+    Builtin,
+    Function,
+    Instant,
+    Executable
+}
+
+/// Resolves the command word in the same order as `execute`
+///
+/// Returns `None` if the command is not found
+pub fn command_kind(word: &str) -> Option<CommandKind> {
+    // literal strings bypass aliases
+    if !word.starts_with('\'') && get_alias(word) != word {
+        return Some(CommandKind::Alias);
+    }
+
+    let command = parse_single_argument(word)?;
+
+    if builtins::is_builtin(&command) {
+        Some(CommandKind::Builtin)
+    } else if is_function(&command) {
+        Some(CommandKind::Function)
+    } else if is_instant(&command) {
+        Some(CommandKind::Instant)
+    } else if find_executable(&command).is_some() {
+        Some(CommandKind::Executable)
+    } else {
+        None
     }
+}
+
+/// Command of the pipeline with its output redirections
+struct Stage {
+    words: Vec<String>,
+    /// Files the output is written to, `true` for `>>`
+    redirections: Vec<(String, bool)>
+}
 
-    let mut binding = command_with_pipes
-        .replace(">>", "| &a")
-        .replace(">", "| &w");
-    
-    for token in binding.clone().replace('=', " ").split_whitespace() {
-        if token.starts_with('\'') {
-            continue
+/// Splits tokens of the pipeline by `|` into stages
+fn split_pipeline(tokens: &[Token]) -> Result<Vec<Stage>, Box<dyn Error>> {
+    let mut stages = Vec::new();
+    let mut stage = Stage { words: Vec::new(), redirections: Vec::new() };
+    let mut tokens = tokens.iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        match token.kind {
+            TokenKind::Word => stage.words.push(token.text.to_string()),
+            TokenKind::Write | TokenKind::Append => match tokens.next_if(|next| next.kind == TokenKind::Word) {
+                Some(filename) => stage.redirections.push((filename.text.to_string(), token.kind == TokenKind::Append)),
+                None => return Err(Box::<dyn Error>::from(format!("expected file name after `{}`", token.text)))
+            },
+            // pipeline continues on the next line
+            TokenKind::Separator if token.text == "\n" && stage.words.is_empty() && !stages.is_empty() => {},
+            TokenKind::Pipe if !stage.words.is_empty() && tokens.peek().is_some() => {
+                stages.push(stage);
+                stage = Stage { words: Vec::new(), redirections: Vec::new() };
+            },
+            _ => return Err(Box::<dyn Error>::from(format!("unexpected `{}`", token.text)))
         }
+    }
+
+    if stage.words.is_empty() {
+        return Err(Box::<dyn Error>::from("command expected"));
+    }
+
+    stages.push(stage);
+
+    Ok(stages)
+}
+
+/// Executes a pipeline, e.g. `cat file.txt | grep text > found.txt`
+pub fn execute(command_with_pipes: &str) -> ExecutionResult {
+    let expanded = expand_aliases(command_with_pipes);
 
-        binding = binding.replace(token, &get_alias(token));
+    // escaped new line between words only continues the line
+    let mut tokens: Vec<Token> = tokenize(&expanded)
+        .into_iter()
+        .filter(|token| token.kind != TokenKind::Comment && token.text != "\\\n")
+        .collect();
+
+    let first = match tokens.first() {
+        Some(first) => *first,
+        None => return ExecutionResult::Success
+    };
+
+    if first.kind == TokenKind::Word && first.text == "time" {
+        return builtins::time_pipeline(&expanded[first.end()..]);
+    }
+
+    // pipeline that ends with `&` is executed in background
+    let background = tokens.last().is_some_and(|token| token.kind == TokenKind::Background);
+
+    if background {
+        tokens.pop();
     }
-    
-    let mut commands = binding.split(" | ").peekable();
 
+    let stages = match split_pipeline(&tokens) {
+        Ok(stages) => stages,
+        Err(err) => return ExecutionResult::Error(err)
+    };
 
-    let mut previous_command = None;
+    let mut words = stages.iter().flat_map(|stage| stage.words.iter().chain(stage.redirections.iter().map(|(filename, _)| filename)));
 
-    while let Some(cmd) = commands.next() {
-        let mut tokens = cmd.split_whitespace();
+    if let Err(err) = words.try_for_each(|word| check_variable(word)) {
+        return ExecutionResult::Error(err);
+    }
 
-        let temp_command = get_alias(tokens.next().unwrap());
+    if options::is_set("xtrace") {
+        trace_pipeline(&stages, background);
+    }
+
+    let stages_count = stages.len();
+
+    let mut previous_command: Option<Child> = None;
+    let mut children: Vec<Child> = Vec::new();
+
+    for (index, stage) in stages.into_iter().enumerate() {
+        let temp_command = parse_single_argument(&stage.words[0]).unwrap_or_default();
         let command = temp_command.as_str();
-        
-        let mut args = tokens;
+
+        let args = &stage.words[1..];
+        let is_last = index + 1 == stages_count;
 
         match command {
-            "cd" => return builtins::change_directory(args.next()),
+            "cd" => return builtins::change_directory(&parse_args(args)),
 
-            "exit" => return ExecutionResult::Exit,
+            "pushd" => return push_directory(&parse_args(args)),
 
-            "set" => return builtins::set_variable(args.next()),
+            "popd" => return pop_directory(&parse_args(args)),
 
-            "alias" => return builtins::set_alias(Some(&args.collect::<Vec<&str>>().join(" "))),
+            "dirs" => return list_directories(&parse_args(args)),
+
+            "j" | "z" => return jump(command, &parse_args(args)),
+
+            "hook" => return hook_builtin(&parse_args(args)),
+
+            "trap" => return trap_builtin(&parse_args(args)),
+
+            "exit" => return builtins::exit_shell(parse_args(args).first().map(String::as_str)),
+
+            "set" if args.first().is_some_and(|arg| arg.starts_with(['-', '+'])) => return options::option_builtin(&parse_args(args)),
+
+            "set" => return builtins::set_variable(args.first().map(String::as_str)),
+
+            "alias" => return builtins::set_alias(Some(&args.join(" "))),
 
             "fn" => return builtins::function_declaration(args),
 
+            "jobs" => return list_jobs(),
+
+            "complete" => return define_completion(&parse_args(args)),
+
+            "history" => return history_builtin(&parse_args(args)),
+
             _ => {
+                if let Some(builtin) = builtins::custom_builtin(command) {
+                    return builtin(&parse_args(args));
+                }
+
                 if is_function(command) {
                     return exec_function(command, args);
                 }
@@ -66,137 +218,405 @@ pub fn execute(command_with_pipes: &str) -> ExecutionResult {
                     return match command {
                         "@exec" => instant_exec(previous_command, parse_args(args)),
                         "@format" => instant_format(previous_command, parse_args(args)),
-                        _ => return ExecutionResult::Success
+                        _ => instant_custom(command, previous_command, parse_args(args)).unwrap_or(ExecutionResult::Success)
                     }
                 }
 
+                let stdin = match previous_command.as_mut() {
+                    Some(output) => Stdio::from(output.stdout.take().unwrap()),
+                    // background jobs must not read from the terminal
+                    None if background => Stdio::null(),
+                    None => Stdio::inherit()
+                };
+
+                // previous commands of the pipeline are waited
+                // after the last one finishes
+                children.extend(previous_command.take());
 
-                let stdin = previous_command
-                        .map_or(
-                            Stdio::inherit(),
-                            |output: Child| Stdio::from(output.stdout.unwrap())
-                        );
+                let mut redirections = Vec::new();
+
+                for (filename, append) in &stage.redirections {
+                    match parse_single_argument(filename) {
+                        Some(filename) => redirections.push((filename, *append)),
+                        None => return ExecutionResult::Error(Box::<dyn Error>::from("expected file name"))
+                    }
+                }
 
-                let (stdout, write_to_file) = match generate_stdout(commands.peek()) {
-                    Ok((stdio, write)) => (stdio, write),
-                    Err(execution_error) => return execution_error
+                let stdout = match redirections.first() {
+                    Some((filename, append)) => match open_output(filename, *append) {
+                        Ok(file) => Stdio::from(file),
+                        Err(err) => return ExecutionResult::Error(Box::new(err))
+                    },
+                    None if is_last => Stdio::inherit(),
+                    None => Stdio::piped()
                 };
 
-                let output = if write_to_file {
-                    // write to the file for the first time
-                    match Command::new(command)
+                // directory name without arguments changes the directory
+                if stages_count == 1 && args.is_empty() && options::is_set("autocd")
+                    && find_executable(command).is_none() && Path::new(command).is_dir() {
+                    return builtins::change_directory(&[String::from("--"), command.to_string()]);
+                }
+
+                let dispositions = child_dispositions(background);
+
+                let output = unsafe {
+                    Command::new(command)
+                        .pre_exec(move || {
+                            for (signal, disposition) in &dispositions {
+                                libc::signal(*signal, *disposition);
+                            }
+
+                            Ok(())
+                        })
                         .args(parse_args(args))
                         .stdin(stdin)
                         .stdout(stdout)
-                        .spawn() {
-                            Ok(_) => {},
-                            Err(err) => return ExecutionResult::Error(Box::new(err))
-                        }
-
-                    let mut previous_filename = commands.next().unwrap().replace("&a ", "").replace("&w ", "");
-                    
-                    while commands.peek().is_some() && commands.peek().unwrap().starts_with("&") {
-                        let append = commands.peek().unwrap().starts_with("&a");
-                        
-                        let filename = commands.peek().unwrap().replace("&a ", "").replace("&w ", "");
-
-                        let file = match File::options()
-                            .create(!append)
-                            .append(append)
-                            .write(!append)
-                            .truncate(!append)
-                            .open(&filename) {
-                                Ok(file) => file,
-                                Err(err) => return ExecutionResult::Error(Box::new(err))
-                            };
-
-                        let file_stream = Stdio::from(file);
-                        
-                        // wait until data is written to the file (from previous file)
-                        // for the first iteration it will be the file we wrote in
-                        // before the cycle
-                        match Command::new("cat")
-                            .arg(previous_filename)
-                            .stdout(file_stream)
-                            .spawn()
-                            .unwrap()
-                            .wait() {
-                                Ok(_) => {},
-                                Err(err) => return ExecutionResult::Error(Box::new(err))
-                            }
+                        .spawn()
+                };
 
-                        previous_filename = filename;
-                        commands.next();
-                    }
+                let output = match output {
+                    Ok(output) => output,
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => return command_not_found(command, &parse_args(args)),
+                    Err(err) => return ExecutionResult::Error(Box::<dyn Error>::from(format!("{command}: {err}")))
+                };
+
+                if redirections.is_empty() {
+                    previous_command = Some(output);
+                    continue;
+                }
+
+                children.push(output);
+
+                // wait until the output is written to the file
+                if let Err(err) = children.last_mut().unwrap().wait() {
+                    return ExecutionResult::Error(Box::new(err));
+                }
+
+                // unlike many other shells, each next file gets
+                // the whole content of the previous one
+                for window in redirections.windows(2) {
+                    let [(previous_filename, _), (filename, append)] = window else { continue };
 
-                    // after the cycle is finished, we have to output
-                    // file to pipe if there are some other pipes
-                    // and we have to output it to /dev/null
-                    // if there are not
-                    let stdout = if commands.peek().is_some() {Stdio::piped()} else {Stdio::null()};
-                    
-                    Command::new("cat")
-                            .arg(previous_filename)
-                            .stdout(stdout)
-                            .spawn()
-                } else {
-                    unsafe {
-                        Command::new(command)
-                            .pre_exec(|| {
-                                libc::signal(libc::SIGINT, libc::SIG_DFL);
-                                libc::signal(libc::SIGQUIT, libc::SIG_ERR);
-                                Ok(())
-                            })
-                            .args(parse_args(args))
-                            .stdin(stdin)
-                            .stdout(stdout)
-                            .spawn()
+                    let copied = fs::read(previous_filename)
+                        .and_then(|content| open_output(filename, *append)?.write_all(&content));
+
+                    if let Err(err) = copied {
+                        return ExecutionResult::Error(Box::new(err));
                     }
-                };
+                }
 
-                match output {
-                    Ok(output) => {
-                        previous_command = Some(output);
-                    },
-                    Err(err) => return ExecutionResult::Error(Box::new(err))
+                // content of the last file is passed
+                // to the next command of the pipeline
+                if !is_last {
+                    let (filename, _) = redirections.last().unwrap();
+
+                    match Command::new("cat").arg(filename).stdout(Stdio::piped()).spawn() {
+                        Ok(output) => previous_command = Some(output),
+                        Err(err) => return ExecutionResult::Error(Box::new(err))
+                    }
                 }
             }
         }
     }
 
-    if previous_command.is_none() {
+    if previous_command.is_none() && children.is_empty() {
         return ExecutionResult::Success;
     }
 
-    match previous_command.unwrap().wait() {
-        Ok(_) => ExecutionResult::Success,
-        Err(err) => ExecutionResult::Error(Box::new(err))
+    if background {
+        children.extend(previous_command);
+        add_job(&expanded[..tokens.last().map_or(0, Token::end)], children);
+        return ExecutionResult::Success;
+    }
+
+    // output of the last command could be written to files
+    let mut last_command = match previous_command.or_else(|| children.pop()) {
+        Some(command) => command,
+        None => return ExecutionResult::Success
+    };
+
+    let result = match last_command.wait() {
+        Ok(status) => exit_status_result(status),
+        Err(err) => return ExecutionResult::Error(Box::new(err))
+    };
+
+    let mut statuses = Vec::new();
+
+    for mut child in children {
+        match child.wait() {
+            Ok(status) => statuses.push(exit_status_result(status).status()),
+            Err(err) => return ExecutionResult::Error(Box::new(err))
+        }
+    }
+
+    if !options::is_set("pipefail") || result.status() != 0 {
+        return result;
+    }
+
+    match statuses.into_iter().rev().find(|&status| status != 0) {
+        Some(status) => ExecutionResult::Failure(status),
+        None => result
     }
 }
 
+/// Prints the expanded pipeline to stderr, prefixed with `PS4`
+fn trace_pipeline(stages: &[Stage], background: bool) {
+    let commands: Vec<String> = stages.iter()
+        .map(|stage| {
+            let mut words: Vec<String> = parse_single_argument(&stage.words[0]).into_iter()
+                .chain(parse_args(&stage.words[1..]))
+                .map(|word| escape(&word))
+                .collect();
+
+            for (filename, append) in &stage.redirections {
+                words.push(if *append { String::from(">>") } else { String::from(">") });
+                words.extend(parse_single_argument(filename).map(|filename| escape(&filename)));
+            }
+
+            words.join(" ")
+        })
+        .collect();
+
+    let prefix = var("PS4").unwrap_or_else(|_| String::from("+ "));
+
+    eprintln!("{prefix}{}{}", commands.join(" | "), if background { " &" } else { "" });
+}
+
+/// Opens file for the output redirection (`>` or `>>`)
+///
+/// With `noclobber` option, `>` doesn't overwrite existing
+/// regular files, but can write to devices, e.g. `/dev/null`
+fn open_output(filename: &str, append: bool) -> io::Result<File> {
+    let exists = fs::metadata(filename).is_ok_and(|metadata| metadata.is_file());
+
+    if !append && exists && options::is_set("noclobber") {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{filename}: cannot overwrite existing file")));
+    }
+
+    File::options()
+        .create(true)
+        .append(append)
+        .write(!append)
+        .truncate(!append)
+        .open(filename)
+}
+
+/// Parses and executes the code
+///
+/// Errors are logged as they occur. Returns the result
+/// of the last executed command, and its exit status is
+/// stored in the `$?` variable. Syntax errors prevent
+/// the whole code from execution and set status to 2.
+/// With `verbose` option, each command is printed to
+/// stderr before it is executed
 pub fn execute_code(code: &str) -> ExecutionResult {
-    let parsed_code = code.replace("{\n", "")
-                                .replace('}', "")
-                                .replace("\n", "; ");
-                                
-    for line in parsed_code.split("\n") {
-        let separate_commands = parse_command(line);
-        for separate_command in separate_commands {
-            for command_with_pipes in separate_command{
-                match execute(command_with_pipes) {
-                    ExecutionResult::Success => continue,
-                    ExecutionResult::Error(err) => {
-                        error_log(err);
+    execute_in_frame(Frame::new(None, None, code, Vec::new()), code)
+}
+
+/// Executes the code of a script, so that its errors
+/// point to the line of the file
+pub fn execute_script(code: &str, file: &str) -> ExecutionResult {
+    let frame = Frame::new(Some(format!("file `{file}`")), Some(Origin::file(file)), code, Vec::new());
+
+    execute_in_frame(frame, code)
+}
+
+/// Executes the code like `execute_code`, with the
+/// frame that describes where it comes from
+pub fn execute_in_frame(frame: Frame, code: &str) -> ExecutionResult {
+    push_frame(frame);
+    let result = execute_frame_code(code);
+    pop_frame();
+
+    result
+}
+
+fn execute_frame_code(code: &str) -> ExecutionResult {
+    match parse_command(code) {
+        Ok(nodes) => {
+            let mut result = ExecutionResult::Success;
+
+            for node in &nodes {
+                set_current(node.span);
+
+                if options::is_set("verbose") {
+                    eprintln!("{}", &code[node.span.start..node.span.end]);
+                }
+
+                result = execute_nodes(code, slice::from_ref(node));
+
+                if let ExecutionResult::Exit(code) = result {
+                    return ExecutionResult::Exit(code);
+                }
+            }
+
+            result
+        },
+        Err(err) => {
+            if has_origin() {
+                set_current(Span { start: err.position, end: err.position });
+                error_log(Box::new(err));
+            } else {
+                let line = code[..err.position].matches('\n').count() + 1;
+                error_log(Box::<dyn Error>::from(format!("line {line}: {err}")));
+            }
+
+            set_exit_status(2);
+            ExecutionResult::Failure(2)
+        }
+    }
+}
+
+/// Executes commands of the tree
+fn execute_nodes(code: &str, nodes: &[Node]) -> ExecutionResult {
+    let mut result = ExecutionResult::Success;
+
+    for node in nodes {
+        if let stopped @ (ExecutionResult::Failure(_) | ExecutionResult::Exit(_)) = check_signals() {
+            set_exit_status(stopped.status());
+            return stopped;
+        }
+
+        result = match &node.kind {
+            NodeKind::Chain(pipelines) => execute_chain(code, pipelines),
+            NodeKind::Function { name, body } => {
+                set_var("__FN_".to_owned() + name, &code[body.start..body.end]);
+                define_function(name, Some(body.start));
+                set_exit_status(0);
+                ExecutionResult::Success
+            },
+            NodeKind::If { branches, otherwise } => execute_if(code, branches, otherwise.as_deref()),
+            NodeKind::While { condition: loop_condition, body } => {
+                let mut result = ExecutionResult::Success;
+
+                loop {
+                    match execute_condition(code, loop_condition) {
+                        ExecutionResult::Exit(code) => return ExecutionResult::Exit(code),
+                        checked if checked.status() != 0 => break,
+                        _ => {}
+                    }
+
+                    result = execute_nodes(code, body);
+
+                    if let ExecutionResult::Exit(code) = result {
+                        return ExecutionResult::Exit(code);
+                    }
+                }
+
+                set_exit_status(result.status());
+                result
+            },
+            NodeKind::For { variable, words, body } => {
+                let mut result = ExecutionResult::Success;
+
+                if let Err(err) = words.iter().try_for_each(|word| check_variable(word)) {
+                    error_log(err);
+                    set_exit_status(1);
+                    return ExecutionResult::Failure(1);
+                }
+
+                for value in parse_args(words) {
+                    if interrupted() {
                         break;
-                    },
-                    ExecutionResult::Exit => return ExecutionResult::Exit
-                };
+                    }
+
+                    set_var(variable, value);
+                    result = execute_nodes(code, body);
+
+                    if let ExecutionResult::Exit(code) = result {
+                        return ExecutionResult::Exit(code);
+                    }
+                }
+
+                set_exit_status(result.status());
+                result
             }
-            
+        };
+
+        if let ExecutionResult::Exit(code) = result {
+            return ExecutionResult::Exit(code);
         }
     }
 
-    ExecutionResult::Success
+    result
+}
+
+/// Executes the condition of `if` or `while`
+///
+/// Failed commands inside conditions, including the
+/// functions they call, don't trigger errexit
+fn execute_condition(code: &str, nodes: &[Node]) -> ExecutionResult {
+    CONDITIONS.fetch_add(1, Ordering::SeqCst);
+    let result = execute_nodes(code, nodes);
+    CONDITIONS.fetch_sub(1, Ordering::SeqCst);
+
+    result
+}
+
+/// Executes pipelines joined with `&&` until one of them fails
+fn execute_chain(code: &str, pipelines: &[Span]) -> ExecutionResult {
+    let mut result = ExecutionResult::Success;
+    let last = pipelines.len().saturating_sub(1);
+
+    for (index, span) in pipelines.iter().enumerate() {
+        if let stopped @ (ExecutionResult::Failure(_) | ExecutionResult::Exit(_)) = check_signals() {
+            set_exit_status(stopped.status());
+            return stopped;
+        }
+
+        set_current(*span);
+
+        if let ExecutionResult::Exit(code) = debugger::before_command() {
+            return ExecutionResult::Exit(code);
+        }
+
+        result = match execute(&code[span.start..span.end]) {
+            ExecutionResult::Error(err) => {
+                error_log(err);
+                ExecutionResult::Failure(1)
+            },
+            ExecutionResult::Exit(code) => return ExecutionResult::Exit(code),
+            result => result
+        };
+
+        set_exit_status(result.status());
+
+        if result.status() != 0 {
+            // only the last command of `&&` chain triggers ERR trap and errexit
+            if index == last && CONDITIONS.load(Ordering::SeqCst) == 0 {
+                if let ExecutionResult::Exit(code) = run_err_trap() {
+                    return ExecutionResult::Exit(code);
+                }
+
+                if options::is_set("errexit") {
+                    return ExecutionResult::Exit(result.status());
+                }
+            }
+
+            break;
+        }
+    }
+
+    result
+}
+
+/// Executes the first branch whose condition succeeds
+fn execute_if(code: &str, branches: &[(Vec<Node>, Vec<Node>)], otherwise: Option<&[Node]>) -> ExecutionResult {
+    for (branch_condition, body) in branches {
+        match execute_condition(code, branch_condition) {
+            ExecutionResult::Exit(code) => return ExecutionResult::Exit(code),
+            checked if checked.status() == 0 => return execute_nodes(code, body),
+            _ => {}
+        }
+    }
+
+    match otherwise {
+        Some(body) => execute_nodes(code, body),
+        None => {
+            set_exit_status(0);
+            ExecutionResult::Success
+        }
+    }
 }
 
 pub fn execute_file<P>(path: P) -> ExecutionResult
@@ -204,21 +624,15 @@ where P: AsRef<Path> {
     let mut code = String::new();
     let mut file = match File::options()
             .read(true)
-            .open(path) {
+            .open(&path) {
             Ok(file) => file,
-            Err(err) => {
-                error_log(Box::new(err));
-                return ExecutionResult::Exit;
-            }
+            Err(err) => return ExecutionResult::Error(Box::new(err))
         };
 
     match file.read_to_string(&mut code) {
         Ok(_) => {},
-        Err(err) => {
-            error_log(Box::new(err));
-            exit(5)
-        }
+        Err(err) => return ExecutionResult::Error(Box::new(err))
     }
 
-    execute_code(&code)
+    execute_script(&code, &path.as_ref().display().to_string())
 }
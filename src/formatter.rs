@@ -0,0 +1,384 @@
+//! Formatter of scripts
+//!
+//! `rsh fmt file.rsh` rewrites scripts in the canonical style:
+//!
+//! * bodies of functions, conditions and loops are indented with 4 spaces
+//!   (the language server uses the indentation of the editor instead)
+//! * `then` and `do` are on the line of the condition, e.g. `if test -f a; then`
+//! * `elif`, `else`, `fi`, `done` and `}` are on their own lines
+//! * words are separated with a single space, `|`, `&&` and
+//!   redirections have spaces around them, `;` is followed by a space
+//! * lines continued after `|`, `&&` or `\` are indented
+//! * comments and single blank lines between commands are kept
+//!
+//! Words are kept as they are written, so formatting doesn't change
+//! what the script does. Scripts with syntax errors are not formatted.
+
+use std::error::Error;
+use std::fs::{read_to_string, write};
+use std::io::{self, Read};
+use crate::lexer::{Token, TokenKind, tokenize};
+use crate::lint::line_column;
+use crate::parse_command::{ParseError, parse_command};
+use crate::utils::error_log;
+
+const INDENT: &str = "    ";
+
+/// Formats the script in the canonical style
+pub fn format_script(source: &str) -> Result<String, ParseError> {
+    format_script_with_indent(source, INDENT)
+}
+
+/// Formats the script in the canonical style, with `unit`
+/// as one level of indentation, e.g. a tab
+pub fn format_script_with_indent(source: &str, unit: &str) -> Result<String, ParseError> {
+    parse_command(source)?;
+
+    let tokens = tokenize(source);
+
+    let mut formatter = Formatter {
+        output: String::new(),
+        unit,
+        indent: 0,
+        newlines: 0,
+        semicolon: false,
+        command: true,
+        opened: false,
+        continued: false,
+        operator: false,
+        comment: false,
+        empty_line: true
+    };
+
+    let mut index = 0;
+
+    while index < tokens.len() {
+        index += formatter.token(&tokens[index..]);
+    }
+
+    if !formatter.output.is_empty() {
+        formatter.output.push('\n');
+    }
+
+    Ok(formatter.output)
+}
+
+struct Formatter<'a> {
+    output: String,
+    /// One level of indentation
+    unit: &'a str,
+    /// Depth of the blocks
+    indent: usize,
+    /// New lines since the last token
+    newlines: usize,
+    /// `;` since the last token
+    semicolon: bool,
+    /// Next word is in command position, where keywords are recognized
+    command: bool,
+    /// Block has just started, so its first command is on a new line
+    opened: bool,
+    /// Command continues on the next line after `|`, `&&` or `\`
+    continued: bool,
+    /// Last token is `|` or `&&`, so a new line continues the command
+    operator: bool,
+    /// Current line ends with a comment
+    comment: bool,
+    /// Nothing is written on the current line after the indentation
+    empty_line: bool
+}
+
+impl Formatter<'_> {
+    /// Writes the first of the tokens, returns the number of tokens consumed
+    fn token(&mut self, tokens: &[Token]) -> usize {
+        let token = tokens[0];
+
+        match token.kind {
+            TokenKind::Separator if token.text == "\n" => {
+                self.newlines += 1;
+
+                if self.operator {
+                    self.continued = true;
+                } else {
+                    self.command = true;
+                    self.continued = false;
+                }
+
+                return 1;
+            },
+            TokenKind::Separator => {
+                self.semicolon = true;
+                self.command = true;
+                self.continued = false;
+                return 1;
+            },
+            TokenKind::Comment => {
+                // `;` before the comment is not needed
+                self.semicolon = false;
+
+                if self.newlines > 0 || self.output.is_empty() {
+                    self.start_line(self.indent + self.continued as usize, self.newlines > 1 && !self.opened);
+                } else if !self.empty_line {
+                    self.output.push(' ');
+                }
+
+                self.write(token.text);
+                self.comment = true;
+                return 1;
+            },
+            _ => {}
+        }
+
+        let keyword = match token.kind {
+            TokenKind::Word if self.command => token.text,
+            _ => ""
+        };
+
+        let mut consumed = 1;
+
+        match keyword {
+            "then" | "do" => {
+                // `then` can't follow the comment on the same line
+                if self.comment {
+                    self.start_line(self.indent, false);
+                } else {
+                    self.output += "; ";
+                }
+
+                self.write(token.text);
+                self.open_block();
+                return 1;
+            },
+            "else" => {
+                self.close_block();
+                self.write(token.text);
+                self.open_block();
+                return 1;
+            },
+            "elif" | "fi" | "done" | "}" => {
+                self.close_block();
+                self.write(token.text);
+                self.command = keyword == "elif";
+                return 1;
+            },
+            _ => {}
+        }
+
+        self.place();
+
+        // escaped new line continues the command on the next line
+        let text = match token.text.strip_prefix("\\\n") {
+            Some(word) if token.kind == TokenKind::Word && !word.is_empty() => {
+                self.write("\\");
+                self.newlines = 1;
+                self.continued = true;
+                self.place();
+                word
+            },
+            _ => token.text
+        };
+
+        match text.strip_suffix('\n') {
+            Some(escaped) if token.kind == TokenKind::Word && escaped.ends_with('\\') => {
+                self.write(escaped);
+                self.newlines = 1;
+                self.continued = true;
+            },
+            _ => self.write(text)
+        }
+
+        self.command = match token.kind {
+            TokenKind::Word => matches!(keyword, "if" | "while"),
+            TokenKind::Background => true,
+            _ => false
+        };
+
+        self.operator = matches!(token.kind, TokenKind::Pipe | TokenKind::And) || self.continued && self.newlines > 0;
+
+        if token.kind == TokenKind::Background {
+            self.continued = false;
+        }
+
+        // `fn name {` starts the body of the function
+        let function = keyword == "fn"
+            && tokens.get(1).is_some_and(|name| name.kind == TokenKind::Word)
+            && tokens.get(2).is_some_and(|brace| brace.kind == TokenKind::Word && brace.text == "{");
+
+        if function {
+            self.output += " ";
+            self.output += tokens[1].text;
+            self.output += " {";
+            self.open_block();
+            consumed = 3;
+        }
+
+        consumed
+    }
+
+    /// Starts a new line with the indentation, optionally after a blank line
+    fn start_line(&mut self, indent: usize, blank: bool) {
+        if !self.output.is_empty() {
+            self.output.push('\n');
+
+            if blank {
+                self.output.push('\n');
+            }
+        }
+
+        self.output += &self.unit.repeat(indent);
+        self.newlines = 0;
+        self.comment = false;
+        self.empty_line = true;
+    }
+
+    /// Separates the next token from the previous one
+    fn place(&mut self) {
+        if self.opened {
+            self.start_line(self.indent, false);
+        } else if self.newlines > 0 || self.output.is_empty() {
+            self.start_line(self.indent + self.continued as usize, self.newlines > 1);
+        } else if self.semicolon {
+            self.output += "; ";
+        } else if !self.empty_line {
+            self.output.push(' ');
+        }
+    }
+
+    fn write(&mut self, text: &str) {
+        self.output += text;
+        self.newlines = 0;
+        self.semicolon = false;
+        self.opened = false;
+        self.empty_line = false;
+    }
+
+    fn open_block(&mut self) {
+        self.indent += 1;
+        self.opened = true;
+        self.command = true;
+        self.continued = false;
+        self.operator = false;
+    }
+
+    /// Starts the line of the keyword that closes the block
+    fn close_block(&mut self) {
+        self.indent = self.indent.saturating_sub(1);
+        self.start_line(self.indent, false);
+        self.continued = false;
+        self.operator = false;
+    }
+}
+
+/// Formats the files in place, or the standard input to
+/// the standard output if there are no files
+///
+/// With `check`, files are not changed, and the names of the files
+/// that are not formatted are printed. Returns the exit status: 0 if
+/// all files are formatted, 1 if some are not (with `check`), and
+/// 2 if a file can't be read or has syntax errors
+pub fn format_files(files: Vec<String>, check: bool) -> i32 {
+    let stdin = files.is_empty();
+    let files = if stdin { vec![String::from("stdin")] } else { files };
+    let mut status = 0;
+
+    for file in files {
+        let source = match stdin {
+            true => {
+                let mut source = String::new();
+                io::stdin().read_to_string(&mut source).map(|_| source)
+            },
+            false => read_to_string(&file)
+        };
+
+        let source = match source {
+            Ok(source) => source,
+            Err(err) => {
+                error_log(Box::<dyn Error>::from(format!("{file}: {err}")));
+                status = 2;
+                continue;
+            }
+        };
+
+        let formatted = match format_script(&source) {
+            Ok(formatted) => formatted,
+            Err(err) => {
+                let (line, column) = line_column(&source, err.position);
+                error_log(Box::<dyn Error>::from(format!("{file}:{line}:{column}: {err}")));
+                status = 2;
+                continue;
+            }
+        };
+
+        if check {
+            if formatted != source {
+                println!("{file}");
+                status = status.max(1);
+            }
+        } else if stdin {
+            print!("{formatted}");
+        } else if formatted != source {
+            if let Err(err) = write(&file, formatted) {
+                error_log(Box::<dyn Error>::from(format!("{file}: {err}")));
+                status = 2;
+            }
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSY: &str = "# build the project\nfn build {\ncd &dir;make   all|tee log&&echo done\n}\n\n\n\nif test -f a   # file exists\nthen\necho a\nelif test -f b; then echo b\nelse\nfor x in 1 2;do echo $x\ndone\nfi\nls>out &\ncat a |\ngrep b\necho a \\\nb\n";
+
+    const FORMATTED: &str = "# build the project\nfn build {\n    cd &dir; make all | tee log && echo done\n}\n\nif test -f a # file exists\nthen\n    echo a\nelif test -f b; then\n    echo b\nelse\n    for x in 1 2; do\n        echo $x\n    done\nfi\nls > out &\ncat a |\n    grep b\necho a \\\n    b\n";
+
+    #[test]
+    fn canonical_style() {
+        assert_eq!(format_script(MESSY).unwrap(), FORMATTED);
+    }
+
+    #[test]
+    fn idempotent() {
+        assert_eq!(format_script(FORMATTED).unwrap(), FORMATTED);
+
+        for source in ["", "echo a\n", "fn f {\necho &x\n}\nf 1\n", "while true; do\n    # comment\n    break\ndone\n"] {
+            let formatted = format_script(source).unwrap();
+            assert_eq!(format_script(&formatted).unwrap(), formatted, "{source:?}");
+        }
+    }
+
+    #[test]
+    fn custom_indentation() {
+        let formatted = format_script_with_indent(MESSY, "\t").unwrap();
+
+        assert_eq!(formatted, FORMATTED.replace("    ", "\t"));
+        assert_eq!(format_script_with_indent(&formatted, "\t").unwrap(), formatted);
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let source = "#!/usr/bin/env rsh\n# first\necho a # trailing\nif true; then\n# inside\necho b\nfi\n# last\n";
+        let formatted = format_script(source).unwrap();
+
+        for comment in ["#!/usr/bin/env rsh", "# first", "# trailing", "# inside", "# last"] {
+            assert!(formatted.contains(comment), "{comment} is lost in {formatted:?}");
+        }
+
+        assert!(formatted.contains("\n    # inside\n"));
+    }
+
+    #[test]
+    fn keeps_words() {
+        let source = "echo \"a   $b\" e\\ f 'c\n";
+
+        assert_eq!(format_script(source).unwrap(), source);
+    }
+
+    #[test]
+    fn rejects_syntax_errors() {
+        assert!(format_script("if true; then echo a").is_err());
+        assert!(format_script("echo \"unterminated").is_err());
+    }
+}
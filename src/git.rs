@@ -0,0 +1,1447 @@
+//! Git status segment of the prompt
+//!
+//! Status is read from the `.git` directory directly:
+//!
+//! * modified files: the index is compared with the working tree
+//! * staged changes: the index is compared with the tree of `HEAD`
+//! * untracked files: the working tree is walked, skipping files
+//!   of the index and files ignored by `.gitignore`,
+//!   `.git/info/exclude` and `core.excludesFile`
+//! * ahead/behind: commits of the branch and of its upstream
+//!   from `.git/config` are walked until their common history
+//!
+//! With `command` option of the config, status is taken from
+//! `git status` instead. Both ways are limited by the timeout
+//! from the config, so huge repositories don't stall the prompt.
+
+use std::collections::{BinaryHeap, BTreeMap, HashMap, HashSet};
+use std::collections::hash_map::Entry;
+use std::env::{current_dir, var};
+use std::fs::{self, File};
+use std::io::{Read, BufReader};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::config::config;
+use crate::git_objects::{Commit, Repository, parse_hex};
+
+#[derive(Default)]
+struct Status {
+    staged: bool,
+    modified: bool,
+    untracked: bool,
+    ahead: usize,
+    behind: usize,
+    /// Status wasn't fully computed before the timeout
+    incomplete: bool
+}
+
+/// Generates segment like `main|rebase +!? ⇡1⇣2`
+///
+/// Markers are `+` for staged changes, `!` for modified files
+/// and `?` for untracked files. `…` means that status wasn't
+/// computed in time. Returns empty string outside of repositories
+pub fn git_segment() -> String {
+    let deadline = Instant::now() + config().git_timeout;
+
+    let (work_tree, git_dir) = match find_repository() {
+        Some(repository) => repository,
+        None => return String::new()
+    };
+
+    let mut segment = branch(&git_dir);
+
+    if let Some(operation) = operation(&git_dir) {
+        segment = format!("{segment}|{operation}");
+    }
+
+    let status = if config().git_command {
+        command_status(&work_tree, deadline)
+            .unwrap_or_else(|| index_status(&work_tree, &git_dir, deadline))
+    } else {
+        index_status(&work_tree, &git_dir, deadline)
+    };
+
+    let mut markers = String::new();
+
+    if status.staged {
+        markers.push('+');
+    }
+    if status.modified {
+        markers.push('!');
+    }
+    if status.untracked {
+        markers.push('?');
+    }
+    if status.incomplete {
+        markers.push('…');
+    }
+
+    if !markers.is_empty() {
+        segment = format!("{segment} {markers}");
+    }
+
+    match (status.ahead, status.behind) {
+        (0, 0) => segment,
+        (ahead, 0) => format!("{segment} ⇡{ahead}"),
+        (0, behind) => format!("{segment} ⇣{behind}"),
+        (ahead, behind) => format!("{segment} ⇡{ahead}⇣{behind}")
+    }
+}
+
+/// Finds the working tree and the git directory of the repository
+/// that contains the current directory
+fn find_repository() -> Option<(PathBuf, PathBuf)> {
+    let mut directory = current_dir().ok()?;
+
+    loop {
+        let dot_git = directory.join(".git");
+
+        if dot_git.is_dir() {
+            return Some((directory, dot_git));
+        }
+
+        // worktrees and submodules have `.git` file with `gitdir: <path>`
+        if dot_git.is_file() {
+            let content = fs::read_to_string(&dot_git).ok()?;
+            let git_dir = directory.join(content.strip_prefix("gitdir:")?.trim());
+
+            return Some((directory, git_dir));
+        }
+
+        if !directory.pop() {
+            return None;
+        }
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|content| content.trim().to_string())
+}
+
+/// Current branch, or abbreviated commit hash if `HEAD` is detached
+fn branch(git_dir: &Path) -> String {
+    // during rebase `HEAD` is detached, but the branch is stored separately
+    let head = read_trimmed(&git_dir.join("rebase-merge/head-name"))
+        .or_else(|| read_trimmed(&git_dir.join("rebase-apply/head-name")))
+        .map(|name| format!("ref: {name}"))
+        .or_else(|| read_trimmed(&git_dir.join("HEAD")))
+        .unwrap_or_default();
+
+    match head.strip_prefix("ref: ") {
+        Some(reference) => reference.strip_prefix("refs/heads/").unwrap_or(reference).to_string(),
+        None => head.chars().take(7).collect()
+    }
+}
+
+/// Operation that is in progress, e.g. rebase or merge
+fn operation(git_dir: &Path) -> Option<&'static str> {
+    let operations = [
+        ("rebase-merge", "rebase"),
+        ("rebase-apply/rebasing", "rebase"),
+        ("rebase-apply/applying", "am"),
+        ("rebase-apply", "rebase"),
+        ("MERGE_HEAD", "merge"),
+        ("CHERRY_PICK_HEAD", "cherry-pick"),
+        ("REVERT_HEAD", "revert"),
+        ("BISECT_LOG", "bisect")
+    ];
+
+    operations.into_iter()
+        .find(|(file, _)| git_dir.join(file).exists())
+        .map(|(_, operation)| operation)
+}
+
+/// Reads status from `git status --porcelain=v2 --branch`
+///
+/// Returns `None` if `git` can't be executed. If it doesn't
+/// finish before the deadline, it is killed and the status
+/// is marked as incomplete
+fn command_status(work_tree: &Path, deadline: Instant) -> Option<Status> {
+    let mut child = Command::new("git")
+        .args(["--no-optional-locks", "status", "--porcelain=v2", "--branch", "--untracked-files=normal"])
+        .current_dir(work_tree)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+
+    let mut stdout = child.stdout.take()?;
+
+    // output is read in a separate thread, so `git`
+    // doesn't block on a full pipe while it is waited
+    let reader = thread::spawn(move || {
+        let mut output = String::new();
+        let _ = stdout.read_to_string(&mut output);
+        output
+    });
+
+    loop {
+        match child.try_wait() {
+            Ok(Some(status)) if status.success() => break,
+            Ok(Some(_)) | Err(_) => return None,
+            Ok(None) if Instant::now() >= deadline => {
+                let _ = child.kill();
+                let _ = child.wait();
+
+                return Some(Status { incomplete: true, ..Status::default() });
+            },
+            Ok(None) => thread::sleep(Duration::from_millis(5))
+        }
+    }
+
+    let output = reader.join().ok()?;
+    let mut status = Status::default();
+
+    for line in output.lines() {
+        let mut fields = line.split(' ');
+
+        match fields.next() {
+            Some("#") if fields.next() == Some("branch.ab") => {
+                status.ahead = fields.next()?.trim_start_matches('+').parse().unwrap_or(0);
+                status.behind = fields.next()?.trim_start_matches('-').parse().unwrap_or(0);
+            },
+            Some("1" | "2" | "u") => {
+                let xy = fields.next()?.as_bytes();
+
+                status.staged |= xy.first().is_some_and(|x| *x != b'.');
+                status.modified |= xy.get(1).is_some_and(|y| *y != b'.');
+            },
+            Some("?") => status.untracked = true,
+            _ => {}
+        }
+    }
+
+    Some(status)
+}
+
+/// Computes the status from the `.git` directory
+///
+/// Each part of the status is skipped once the deadline
+/// passes, and the status is marked as incomplete
+fn index_status(work_tree: &Path, git_dir: &Path, deadline: Instant) -> Status {
+    let repository = Repository::open(git_dir);
+    let index = read_index(&git_dir.join("index")).unwrap_or_default();
+    let config = read_config(repository.common_dir());
+
+    let mut status = Status::default();
+
+    match modified_files(work_tree, &index, deadline) {
+        Some(modified) => status.modified = modified,
+        None => status.incomplete = true
+    }
+
+    match staged_changes(&repository, &index, deadline) {
+        Some(staged) => status.staged = staged,
+        None => status.incomplete = true
+    }
+
+    match ahead_behind(&repository, git_dir, &config, deadline) {
+        Some((ahead, behind)) => (status.ahead, status.behind) = (ahead, behind),
+        None => status.incomplete = true
+    }
+
+    match untracked_files(work_tree, &repository, &index, &config, deadline) {
+        Some(untracked) => status.untracked = untracked,
+        None => status.incomplete = true
+    }
+
+    status
+}
+
+/// Finds modified files by comparing the index with the working tree
+///
+/// Files with the same size and modification time as recorded in
+/// the index are considered unchanged, like `git` does. Otherwise,
+/// the hash of the file is compared with the hash in the index.
+/// Returns `None` if the deadline passes
+fn modified_files(work_tree: &Path, index: &Index, deadline: Instant) -> Option<bool> {
+    for entry in &index.entries {
+        if Instant::now() >= deadline {
+            return None;
+        }
+
+        // gitlinks (submodules) are not files, files outside of the sparse
+        // checkout and files added with `git add -N` are not compared
+        if entry.mode & 0o170000 == 0o160000 || entry.skip_worktree || entry.intent_to_add {
+            continue;
+        }
+
+        let path = work_tree.join(&entry.path);
+
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return Some(true);
+        };
+
+        if metadata.size() as u32 == entry.size
+            && metadata.mtime() as u32 == entry.mtime
+            && metadata.mtime_nsec() as u32 == entry.mtime_nsec {
+            continue;
+        }
+
+        if blob_hash(&path, metadata.file_type().is_symlink()) != Some(entry.hash) {
+            return Some(true);
+        }
+    }
+
+    Some(false)
+}
+
+/// Finds staged changes by comparing the index with the tree of `HEAD`
+///
+/// Directories whose tree in the cache of the index is the same
+/// as in `HEAD` are skipped without reading their trees. Returns
+/// `None` if objects can't be read or the deadline passes
+fn staged_changes(repository: &Repository, index: &Index, deadline: Instant) -> Option<bool> {
+    // merge conflicts are staged and modified at the same time
+    if index.entries.iter().any(|entry| entry.stage != 0) {
+        return Some(true);
+    }
+
+    let entries: Vec<&IndexEntry> = index.entries.iter().filter(|entry| !entry.intent_to_add).collect();
+
+    // everything in the index is staged before the first commit
+    let Some(head) = repository.resolve("HEAD") else {
+        return Some(!entries.is_empty());
+    };
+
+    let mut files = BTreeMap::new();
+    let mut unchanged = Vec::new();
+
+    head_files(repository, &repository.commit(&head)?.tree, "", index, &mut files, &mut unchanged, deadline)?;
+
+    let staged: BTreeMap<&str, (u32, [u8; 20])> = entries.iter()
+        .filter(|entry| !unchanged.iter().any(|directory| entry.path.starts_with(directory)))
+        .map(|entry| (entry.path.as_str(), (entry.mode, entry.hash)))
+        .collect();
+
+    Some(staged.len() != files.len() || staged.iter().zip(&files).any(|(staged, file)| staged.0 != file.0 || staged.1 != file.1))
+}
+
+/// Collects files of the tree with their modes and hashes, except
+/// for directories that are unchanged according to the index
+fn head_files(
+    repository: &Repository,
+    tree: &[u8; 20],
+    prefix: &str,
+    index: &Index,
+    files: &mut BTreeMap<String, (u32, [u8; 20])>,
+    unchanged: &mut Vec<String>,
+    deadline: Instant
+) -> Option<()> {
+    if Instant::now() >= deadline {
+        return None;
+    }
+
+    if index.cache_tree.get(prefix) == Some(tree) {
+        unchanged.push(prefix.to_string());
+        return Some(());
+    }
+
+    for entry in repository.tree(tree)? {
+        let path = format!("{prefix}{}", entry.name);
+
+        if entry.mode == 0o40000 {
+            head_files(repository, &entry.hash, &format!("{path}/"), index, files, unchanged, deadline)?;
+        } else {
+            files.insert(path, (entry.mode, entry.hash));
+        }
+    }
+
+    Some(())
+}
+
+/// Counts commits of the current branch and its upstream that are
+/// not in the other one. Branches without upstream, missing upstream
+/// and detached `HEAD` have no counts
+fn ahead_behind(repository: &Repository, git_dir: &Path, config: &GitConfig, deadline: Instant) -> Option<(usize, usize)> {
+    let head = read_trimmed(&git_dir.join("HEAD")).unwrap_or_default();
+
+    let Some(branch) = head.strip_prefix("ref: refs/heads/") else {
+        return Some((0, 0));
+    };
+
+    let (Some(remote), Some(merge)) = (config.value(&format!("branch.{branch}.remote")), config.value(&format!("branch.{branch}.merge"))) else {
+        return Some((0, 0));
+    };
+
+    // `.` is the local repository, otherwise the fetch refspec
+    // maps the branch of the remote to its remote-tracking branch
+    let upstream = match remote {
+        "." => Some(merge.to_string()),
+        _ => config.values(&format!("remote.{remote}.fetch"))
+            .find_map(|refspec| {
+                let (source, destination) = refspec.trim_start_matches('+').split_once(':')?;
+
+                match (source.split_once('*'), destination.split_once('*')) {
+                    (Some((prefix, suffix)), Some((destination_prefix, destination_suffix))) => {
+                        let name = merge.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                        Some(format!("{destination_prefix}{name}{destination_suffix}"))
+                    },
+                    (None, None) if source == merge => Some(destination.to_string()),
+                    _ => None
+                }
+            })
+    };
+
+    let (Some(local), Some(upstream)) = (repository.resolve(&format!("refs/heads/{branch}")), upstream.and_then(|upstream| repository.resolve(&upstream))) else {
+        return Some((0, 0));
+    };
+
+    count_commits(repository, local, upstream, deadline)
+}
+
+/// Counts commits reachable from only one of the two commits
+///
+/// Commits are walked from the newest, marking which of the two
+/// they are reachable from, until only commits reachable from
+/// both are left. Returns `None` if the deadline passes
+fn count_commits(repository: &Repository, local: [u8; 20], upstream: [u8; 20], deadline: Instant) -> Option<(usize, usize)> {
+    const LOCAL: u8 = 1;
+    const UPSTREAM: u8 = 2;
+    const BOTH: u8 = LOCAL | UPSTREAM;
+
+    // parents of commits of shallow clones are missing
+    let shallow: HashSet<[u8; 20]> = fs::read_to_string(repository.common_dir().join("shallow"))
+        .unwrap_or_default()
+        .lines()
+        .filter_map(parse_hex)
+        .collect();
+
+    let mut flags: HashMap<[u8; 20], u8> = HashMap::new();
+    let mut commits: HashMap<[u8; 20], Commit> = HashMap::new();
+    let mut queue: BinaryHeap<(i64, [u8; 20])> = BinaryHeap::new();
+    let mut marked = vec![(local, LOCAL), (upstream, UPSTREAM)];
+
+    loop {
+        // commits are queued again when they get new flags, so
+        // commits with wrong timestamps are still counted right
+        for (hash, flag) in marked.drain(..) {
+            let flags = flags.entry(hash).or_insert(0);
+
+            if *flags & flag == flag {
+                continue;
+            }
+
+            *flags |= flag;
+
+            let time = match commits.entry(hash) {
+                Entry::Occupied(commit) => commit.get().time,
+                Entry::Vacant(entry) => entry.insert(repository.commit(&hash)?).time
+            };
+
+            queue.push((time, hash));
+        }
+
+        // the rest of the history is reachable from both
+        if queue.iter().all(|(_, hash)| flags[hash] == BOTH) {
+            break;
+        }
+
+        if Instant::now() >= deadline {
+            return None;
+        }
+
+        let (_, hash) = queue.pop()?;
+
+        if !shallow.contains(&hash) {
+            marked.extend(commits[&hash].parents.iter().map(|parent| (*parent, flags[&hash])));
+        }
+    }
+
+    let count = |flag: u8| flags.values().filter(|flags| **flags == flag).count();
+
+    Some((count(LOCAL), count(UPSTREAM)))
+}
+
+/// Finds files of the working tree that are neither in the index nor ignored
+///
+/// Ignored directories are not entered, and directories without
+/// files, as well as `.git` directories, are skipped like `git`
+/// does. Returns `None` if the deadline passes
+fn untracked_files(work_tree: &Path, repository: &Repository, index: &Index, config: &GitConfig, deadline: Instant) -> Option<bool> {
+    let tracked: HashSet<&str> = index.entries.iter().map(|entry| entry.path.as_str()).collect();
+
+    // directories that contain tracked files, with the trailing `/`
+    let tracked_directories: HashSet<&str> = index.entries.iter()
+        .flat_map(|entry| entry.path.match_indices('/').map(|(slash, _)| &entry.path[..=slash]))
+        .collect();
+
+    let home = var("HOME").unwrap_or_default();
+
+    // default global file is `$XDG_CONFIG_HOME/git/ignore`
+    let excludes_file = match config.value("core.excludesfile") {
+        Some(path) => match path.strip_prefix("~/") {
+            Some(rest) => format!("{home}/{rest}"),
+            None => path.to_string()
+        },
+        None => match var("XDG_CONFIG_HOME") {
+            Ok(config_home) if !config_home.is_empty() => format!("{config_home}/git/ignore"),
+            _ => format!("{home}/.config/git/ignore")
+        }
+    };
+
+    // the last matching rule wins, so rules with lower priority go first
+    let mut rules = Vec::new();
+
+    for path in [PathBuf::from(excludes_file), repository.common_dir().join("info/exclude")] {
+        rules.extend(ignore_rules(&fs::read_to_string(path).unwrap_or_default(), ""));
+    }
+
+    let walk = Walk { tracked, tracked_directories, deadline };
+
+    walk.find_untracked(work_tree, "", &mut rules)
+}
+
+struct Walk<'a> {
+    tracked: HashSet<&'a str>,
+    tracked_directories: HashSet<&'a str>,
+    deadline: Instant
+}
+
+impl Walk<'_> {
+    /// Whether the directory has an untracked file, `directory`
+    /// is relative to the working tree and ends with `/`
+    fn find_untracked(&self, path: &Path, directory: &str, rules: &mut Vec<IgnoreRule>) -> Option<bool> {
+        if Instant::now() >= self.deadline {
+            return None;
+        }
+
+        let Ok(entries) = fs::read_dir(path) else {
+            return Some(false);
+        };
+
+        // rules of `.gitignore` apply to the directory and its subdirectories
+        let outer_rules = rules.len();
+        rules.extend(ignore_rules(&fs::read_to_string(path.join(".gitignore")).unwrap_or_default(), directory));
+
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let relative = format!("{directory}{name}");
+
+            if name == ".git" || self.tracked.contains(relative.as_str()) {
+                continue;
+            }
+
+            // symbolic links to directories are files for git
+            let is_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir());
+
+            if is_ignored(rules, &relative, is_dir) {
+                continue;
+            }
+
+            if !is_dir {
+                return Some(true);
+            }
+
+            let subdirectory = format!("{relative}/");
+
+            // nested repositories are untracked as a whole
+            if !self.tracked_directories.contains(subdirectory.as_str()) && entry.path().join(".git").exists() {
+                return Some(true);
+            }
+
+            if self.find_untracked(&entry.path(), &subdirectory, rules)? {
+                return Some(true);
+            }
+        }
+
+        rules.truncate(outer_rules);
+
+        Some(false)
+    }
+}
+
+/// Pattern of `.gitignore`
+struct IgnoreRule {
+    pattern: String,
+    /// Directory of the `.gitignore` file, ends with `/`
+    base: String,
+    /// `!pattern` includes files that previous patterns ignore
+    negated: bool,
+    /// `pattern/` matches only directories
+    directory_only: bool,
+    /// Pattern with `/` matches the path relative to the base,
+    /// otherwise the name of the file at any level
+    anchored: bool
+}
+
+/// Parses lines of `.gitignore` in the directory
+///
+/// See `gitignore(5)`
+fn ignore_rules(text: &str, base: &str) -> Vec<IgnoreRule> {
+    text.lines()
+        .filter_map(|line| {
+            // trailing spaces are ignored unless escaped
+            let mut line = line.trim_end_matches('\r');
+
+            while line.ends_with(' ') && !line.ends_with("\\ ") {
+                line = &line[..line.len() - 1];
+            }
+
+            if line.is_empty() || line.starts_with('#') {
+                return None;
+            }
+
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(rest) => (true, rest),
+                None => (false, line)
+            };
+
+            let (directory_only, line) = match line.strip_suffix('/') {
+                Some(rest) => (true, rest),
+                None => (false, line)
+            };
+
+            let anchored = line.contains('/');
+
+            Some(IgnoreRule {
+                pattern: line.strip_prefix('/').unwrap_or(line).to_string(),
+                base: base.to_string(),
+                negated,
+                directory_only,
+                anchored
+            })
+        })
+        .collect()
+}
+
+/// Whether the last rule that matches the path ignores it
+fn is_ignored(rules: &[IgnoreRule], path: &str, is_dir: bool) -> bool {
+    rules.iter().rev()
+        .find(|rule| {
+            let Some(relative) = path.strip_prefix(&rule.base) else {
+                return false;
+            };
+
+            let text = match rule.anchored {
+                true => relative,
+                false => relative.rsplit('/').next().unwrap_or(relative)
+            };
+
+            (is_dir || !rule.directory_only) && wildmatch(rule.pattern.as_bytes(), text.as_bytes())
+        })
+        .is_some_and(|rule| !rule.negated)
+}
+
+/// Matches the path with the pattern of `.gitignore`
+///
+/// `*` and `?` don't match `/`, `[...]` matches a character of the
+/// set, `\` escapes the next character, and `**` between slashes
+/// matches any number of directories
+fn wildmatch(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some(b'*') if pattern.get(1) == Some(&b'*') => {
+            let rest = &pattern[2..];
+
+            match rest.first() {
+                // trailing `**` matches everything inside
+                None => true,
+                // `**/` matches zero or more directories
+                Some(b'/') => {
+                    wildmatch(&rest[1..], text)
+                        || text.iter().enumerate().any(|(index, byte)| *byte == b'/' && wildmatch(&rest[1..], &text[index + 1..]))
+                },
+                _ => wildmatch(&pattern[1..], text)
+            }
+        },
+        Some(b'*') => {
+            let rest = &pattern[1..];
+
+            (0..=text.len())
+                .take_while(|index| *index == 0 || text[index - 1] != b'/')
+                .any(|index| wildmatch(rest, &text[index..]))
+        },
+        Some(b'?') => text.first().is_some_and(|byte| *byte != b'/') && wildmatch(&pattern[1..], &text[1..]),
+        Some(b'[') => match (class_match(&pattern[1..], text.first().copied()), text.first()) {
+            (Some((matched, length)), Some(_)) => matched && wildmatch(&pattern[1 + length..], &text[1..]),
+            // unterminated `[` is a literal character
+            (None, Some(b'[')) => wildmatch(&pattern[1..], &text[1..]),
+            _ => false
+        },
+        Some(b'\\') if pattern.len() > 1 => text.first() == Some(&pattern[1]) && wildmatch(&pattern[2..], &text[1..]),
+        Some(byte) => text.first() == Some(byte) && wildmatch(&pattern[1..], &text[1..])
+    }
+}
+
+/// Matches the character with the set after `[`, e.g. `a-z]` or `!0-9]`
+///
+/// Returns whether it matched and the length of the set with `]`,
+/// or `None` if the set is not terminated
+fn class_match(set: &[u8], byte: Option<u8>) -> Option<(bool, usize)> {
+    let (negated, start) = match set.first() {
+        Some(b'!' | b'^') => (true, 1),
+        _ => (false, 0)
+    };
+
+    let mut matched = false;
+    let mut index = start;
+
+    loop {
+        let first = *set.get(index)?;
+
+        // `]` right after `[` is a member of the set
+        if first == b']' && index > start {
+            break;
+        }
+
+        let (first, length) = match first {
+            b'\\' => (*set.get(index + 1)?, 2),
+            _ => (first, 1)
+        };
+
+        index += length;
+
+        let last = match (set.get(index), set.get(index + 1)) {
+            (Some(b'-'), Some(last)) if *last != b']' => {
+                index += 2;
+                *last
+            },
+            _ => first
+        };
+
+        matched |= byte.is_some_and(|byte| (first..=last).contains(&byte) && byte != b'/');
+    }
+
+    Some((matched != negated && byte != Some(b'/'), index + 1))
+}
+
+/// Variables of git config, from the global files and the repository
+struct GitConfig(Vec<(String, String)>);
+
+impl GitConfig {
+    /// Last value of the variable, e.g. `branch.main.remote`
+    fn value(&self, name: &str) -> Option<&str> {
+        self.values(name).last()
+    }
+
+    fn values<'a>(&'a self, name: &str) -> impl Iterator<Item = &'a str> + 'a {
+        let name = config_name(name);
+
+        self.0.iter().filter(move |(key, _)| *key == name).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Name of the variable with case-insensitive section and
+/// key, while subsection (e.g. branch name) keeps its case
+fn config_name(name: &str) -> String {
+    match (name.split_once('.'), name.rsplit_once('.')) {
+        (Some((section, _)), Some((rest, key))) if rest.len() > section.len() => {
+            format!("{}{}.{}", section.to_lowercase(), &rest[section.len()..], key.to_lowercase())
+        },
+        _ => name.to_lowercase()
+    }
+}
+
+fn read_config(common_dir: &Path) -> GitConfig {
+    let home = var("HOME").unwrap_or_default();
+
+    let global = match var("XDG_CONFIG_HOME") {
+        Ok(config_home) if !config_home.is_empty() => format!("{config_home}/git/config"),
+        _ => format!("{home}/.config/git/config")
+    };
+
+    let mut variables = Vec::new();
+
+    for path in [PathBuf::from(global), Path::new(&home).join(".gitconfig"), common_dir.join("config")] {
+        variables.extend(parse_config(&fs::read_to_string(path).unwrap_or_default()));
+    }
+
+    GitConfig(variables)
+}
+
+/// Parses variables of git config as `section.subsection.key`
+///
+/// See `git-config(1)`. Includes are not followed
+fn parse_config(text: &str) -> Vec<(String, String)> {
+    let mut variables = Vec::new();
+    let mut section = String::new();
+
+    for line in text.lines() {
+        let line = line.trim();
+
+        if line.starts_with(['#', ';']) || line.is_empty() {
+            continue;
+        }
+
+        // `[section "subsection"]` or `[section.subsection]`
+        if let Some(header) = line.strip_prefix('[').and_then(|line| line.split_once(']')).map(|(header, _)| header) {
+            section = match header.split_once(' ') {
+                Some((name, subsection)) => {
+                    let subsection = subsection.trim().trim_matches('"').replace("\\\"", "\"").replace("\\\\", "\\");
+                    format!("{}.{subsection}", name.to_lowercase())
+                },
+                None => header.to_lowercase()
+            };
+
+            continue;
+        }
+
+        // key without value is a true boolean
+        let (key, value) = line.split_once('=').unwrap_or((line, "true"));
+
+        variables.push((format!("{section}.{}", key.trim().to_lowercase()), config_value(value)));
+    }
+
+    variables
+}
+
+/// Value without quotes, escapes and the comment
+fn config_value(text: &str) -> String {
+    let mut value = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim().chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '"' => quoted = !quoted,
+            '\\' => match chars.next() {
+                Some('n') => value.push('\n'),
+                Some('t') => value.push('\t'),
+                Some(char) => value.push(char),
+                None => {}
+            },
+            '#' | ';' if !quoted => break,
+            char => value.push(char)
+        }
+    }
+
+    value.trim_end().to_string()
+}
+
+#[derive(Default)]
+struct Index {
+    entries: Vec<IndexEntry>,
+    /// Trees of directories that are unchanged since they were
+    /// written, by the path of the directory ending with `/`
+    cache_tree: HashMap<String, [u8; 20]>
+}
+
+struct IndexEntry {
+    mtime: u32,
+    mtime_nsec: u32,
+    mode: u32,
+    size: u32,
+    hash: [u8; 20],
+    path: String,
+    /// Stage of the merge conflict, `0` if there is no conflict
+    stage: u16,
+    /// File is outside of the sparse checkout
+    skip_worktree: bool,
+    /// File is added with `git add -N`
+    intent_to_add: bool
+}
+
+/// Parses `.git/index` of version 2, 3 or 4
+///
+/// See `Documentation/gitformat-index.txt` in the git repository
+fn read_index(path: &Path) -> Option<Index> {
+    let data = fs::read(path).ok()?;
+
+    let u32_at = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+    };
+
+    if data.get(0..4)? != b"DIRC" {
+        return None;
+    }
+
+    let version = u32_at(4)?;
+    let count = u32_at(8)?;
+
+    if !(2..=4).contains(&version) {
+        return None;
+    }
+
+    let mut entries = Vec::with_capacity(count as usize);
+    let mut offset = 12;
+    let mut previous_path = String::new();
+
+    for _ in 0..count {
+        let start = offset;
+
+        let mtime = u32_at(offset + 8)?;
+        let mtime_nsec = u32_at(offset + 12)?;
+        let mode = u32_at(offset + 24)?;
+        let size = u32_at(offset + 36)?;
+        let hash: [u8; 20] = data.get(offset + 40..offset + 60)?.try_into().ok()?;
+        let flags = u16::from_be_bytes(data.get(offset + 60..offset + 62)?.try_into().ok()?);
+
+        offset += 62;
+
+        let mut extended_flags = 0;
+
+        if version >= 3 && flags & 0x4000 != 0 {
+            extended_flags = u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?);
+            offset += 2;
+        }
+
+        let path = if version == 4 {
+            // path is compressed relatively to the previous one:
+            // number of bytes to strip from it, then the suffix
+            let mut strip: usize = 0;
+
+            loop {
+                let byte = *data.get(offset)?;
+                offset += 1;
+                strip = (strip << 7) | (byte & 0x7f) as usize;
+
+                if byte & 0x80 == 0 {
+                    break;
+                }
+
+                strip += 1;
+            }
+
+            let end = offset + data.get(offset..)?.iter().position(|byte| *byte == 0)?;
+            let suffix = String::from_utf8_lossy(&data[offset..end]);
+            let keep = previous_path.len().checked_sub(strip)?;
+
+            offset = end + 1;
+            format!("{}{suffix}", previous_path.get(..keep)?)
+        } else {
+            let end = offset + data.get(offset..)?.iter().position(|byte| *byte == 0)?;
+            let path = String::from_utf8_lossy(&data[offset..end]).into_owned();
+
+            // entries are padded with NUL bytes to multiple of 8
+            offset = start + (end - start + 8) / 8 * 8;
+            path
+        };
+
+        previous_path = path.clone();
+
+        // entries with non-zero stage are merge conflicts
+        let stage = (flags >> 12) & 0x3;
+
+        entries.push(IndexEntry {
+            mtime,
+            mtime_nsec,
+            mode: if stage == 0 { mode } else { 0 },
+            size,
+            hash: if stage == 0 { hash } else { [0; 20] },
+            path,
+            stage,
+            skip_worktree: extended_flags & 0x4000 != 0,
+            intent_to_add: extended_flags & 0x2000 != 0
+        });
+    }
+
+    let mut cache_tree = HashMap::new();
+
+    // extensions are `<signature><size><data>` up to the checksum
+    while offset + 8 <= data.len().saturating_sub(20) {
+        let size = u32_at(offset + 4)? as usize;
+        let extension = data.get(offset + 8..offset + 8 + size)?;
+
+        if &data[offset..offset + 4] == b"TREE" {
+            read_cache_tree(extension, &mut 0, "", &mut cache_tree)?;
+        }
+
+        offset += 8 + size;
+    }
+
+    Some(Index { entries, cache_tree })
+}
+
+/// Reads the node of the cache tree and its subtrees: path,
+/// number of entries (`-1` if the tree is changed) and number
+/// of subtrees, then the hash of the tree if it is not changed
+fn read_cache_tree(data: &[u8], offset: &mut usize, parent: &str, trees: &mut HashMap<String, [u8; 20]>) -> Option<()> {
+    let end = *offset + data.get(*offset..)?.iter().position(|byte| *byte == 0)?;
+    let name = String::from_utf8_lossy(&data[*offset..end]);
+
+    // root has an empty name
+    let path = if name.is_empty() { parent.to_string() } else { format!("{parent}{name}/") };
+
+    let line_end = end + data.get(end..)?.iter().position(|byte| *byte == b'\n')?;
+    let counts = std::str::from_utf8(&data[end + 1..line_end]).ok()?;
+    let (entries, subtrees) = counts.split_once(' ')?;
+
+    *offset = line_end + 1;
+
+    if entries.parse::<i64>().ok()? >= 0 {
+        trees.insert(path.clone(), data.get(*offset..*offset + 20)?.try_into().ok()?);
+        *offset += 20;
+    }
+
+    for _ in 0..subtrees.parse::<usize>().ok()? {
+        read_cache_tree(data, offset, &path, trees)?;
+    }
+
+    Some(())
+}
+
+/// Hash of the file as a git blob object
+fn blob_hash(path: &Path, symlink: bool) -> Option<[u8; 20]> {
+    let content = if symlink {
+        fs::read_link(path).ok()?.into_os_string().into_encoded_bytes()
+    } else {
+        let mut content = Vec::new();
+        BufReader::new(File::open(path).ok()?).read_to_end(&mut content).ok()?;
+        content
+    };
+
+    let mut object = format!("blob {}\0", content.len()).into_bytes();
+    object.extend(content);
+
+    Some(sha1(&object))
+}
+
+/// SHA-1 as defined in RFC 3174
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+
+    message.extend(((data.len() as u64) * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut words = [0u32; 80];
+
+        for (index, word) in chunk.chunks(4).enumerate() {
+            words[index] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+
+        for index in 16..80 {
+            words[index] = (words[index - 3] ^ words[index - 8] ^ words[index - 14] ^ words[index - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+
+        for (index, word) in words.iter().enumerate() {
+            let (f, k) = match index {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6)
+            };
+
+            let temp = a.rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (value, added) in state.iter_mut().zip([a, b, c, d, e]) {
+            *value = value.wrapping_add(added);
+        }
+    }
+
+    let mut hash = [0u8; 20];
+
+    for (bytes, value) in hash.chunks_mut(4).zip(state) {
+        bytes.copy_from_slice(&value.to_be_bytes());
+    }
+
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    fn hex(hash: [u8; 20]) -> String {
+        hash.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn fixture(version: u32) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join(format!("tests/fixtures/git/v{version}"))
+    }
+
+    /// Working tree with the files of the fixtures
+    fn work_tree(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rsh-git-{}-{name}", std::process::id()));
+
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+        fs::write(root.join("a.txt"), "a\n").unwrap();
+        fs::write(root.join("dir/b.txt"), "b\n").unwrap();
+        fs::write(root.join("dir/c.txt"), "c\n").unwrap();
+        fs::write(root.join("dir/sub/long-name.txt"), "long\n").unwrap();
+        symlink("a.txt", root.join("link")).unwrap();
+
+        root
+    }
+
+    #[test]
+    fn sha1_test_vectors() {
+        // RFC 3174, section 7.3
+        assert_eq!(hex(sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hex(sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(hex(sha1(&vec![b'a'; 1_000_000])), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+        assert_eq!(hex(sha1("01234567".repeat(80).as_bytes())), "dea356a2cddd90c7a7ecedc5ebb563934f460452");
+
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    }
+
+    #[test]
+    fn sha1_padding_boundaries() {
+        // message fits the first block with and without the length
+        assert_eq!(hex(sha1(&[b'a'; 55])), "c1c8bbdc22796e28c0e15163d20899b65621d65a");
+        assert_eq!(hex(sha1(&[b'a'; 56])), "c2db330f6083854c99d4b5bfb6e8f29f201be699");
+        assert_eq!(hex(sha1(&[b'a'; 64])), "0098ba824b5c16427bd7a1122a5a442a25ec644d");
+    }
+
+    #[test]
+    fn blob_hash_matches_git() {
+        let root = work_tree("blob");
+
+        // `git hash-object` of the files
+        assert_eq!(blob_hash(&root.join("a.txt"), false).map(hex).unwrap(), "78981922613b2afb6025042ff6bd878ac1994e85");
+        assert_eq!(blob_hash(&root.join("link"), true).map(hex).unwrap(), "8d14cbf983b3fad683171c9418998d9f68340823");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn reads_index_versions() {
+        let expected = [
+            ("a.txt", 0o100644, "78981922613b2afb6025042ff6bd878ac1994e85"),
+            ("dir/b.txt", 0o100644, "61780798228d17af2d34fce4cfbdf35556832472"),
+            ("dir/c.txt", 0o100644, "f2ad6c76f0115a6ba5b00456a849810e7ec0af20"),
+            ("dir/sub/long-name.txt", 0o100644, "2988452a618dc8ba3eff0d4db49454d7031253bb"),
+            ("link", 0o120000, "8d14cbf983b3fad683171c9418998d9f68340823")
+        ];
+
+        // version 3 has extended flags (`skip-worktree` of `dir/c.txt`),
+        // version 4 has paths compressed relatively to the previous ones
+        for version in 2..=4 {
+            let index = read_index(&fixture(version).join("index")).unwrap();
+
+            let entries: Vec<(&str, u32, String)> = index.entries.iter()
+                .map(|entry| (entry.path.as_str(), entry.mode, hex(entry.hash)))
+                .collect();
+
+            let expected: Vec<(&str, u32, String)> = expected.iter()
+                .map(|(path, mode, hash)| (*path, *mode, hash.to_string()))
+                .collect();
+
+            assert_eq!(entries, expected, "index version {version}");
+
+            let sparse: Vec<&str> = index.entries.iter()
+                .filter(|entry| entry.skip_worktree)
+                .map(|entry| entry.path.as_str())
+                .collect();
+
+            assert_eq!(sparse, if version == 3 { vec!["dir/c.txt"] } else { vec![] }, "index version {version}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_index() {
+        let root = work_tree("invalid");
+        let index = fs::read(fixture(2).join("index")).unwrap();
+
+        fs::write(root.join("index"), &index[..100]).unwrap();
+        assert!(read_index(&root.join("index")).is_none());
+
+        fs::write(root.join("index"), b"DIRC\0\0\0\x05\0\0\0\0").unwrap();
+        assert!(read_index(&root.join("index")).is_none());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn compares_index_with_work_tree() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        for version in 2..=4 {
+            let root = work_tree(&format!("status-v{version}"));
+
+            // timestamps differ from the index, so files are hashed
+            assert!(!index_status(&root, &fixture(version), deadline).modified, "index version {version}");
+
+            fs::write(root.join("dir/sub/long-name.txt"), "changed\n").unwrap();
+            assert!(index_status(&root, &fixture(version), deadline).modified, "index version {version}");
+
+            fs::remove_file(root.join("dir/sub/long-name.txt")).unwrap();
+            assert!(index_status(&root, &fixture(version), deadline).modified, "index version {version}");
+
+            fs::remove_dir_all(root).unwrap();
+        }
+    }
+
+    #[test]
+    fn skips_sparse_and_intent_to_add_entries() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let root = work_tree("sparse");
+
+        // `dir/c.txt` is outside of the sparse checkout in version 3
+        fs::remove_file(root.join("dir/c.txt")).unwrap();
+        assert!(!index_status(&root, &fixture(3), deadline).modified);
+        assert!(index_status(&root, &fixture(2), deadline).modified);
+
+        let mut index = read_index(&fixture(2).join("index")).unwrap();
+        index.entries.iter_mut().filter(|entry| entry.path == "dir/c.txt").for_each(|entry| entry.intent_to_add = true);
+
+        assert_eq!(modified_files(&root, &index, deadline), Some(false));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    // fixture is the `.git` directory of a repository, where `main`
+    // is 2 commits ahead and 1 behind `origin/main`, and `feature`
+    // is 3 commits behind `main`
+
+    const MAIN: &str = "2dc94e46c900e7c6f64105b88c961d31f69fd21b";
+    const FEATURE: &str = "17532a856674f155787433fc570bac6b13c9a101";
+    const ORIGIN: &str = "7fecc20d8a5975649c30f79dba1bb8b8eba66e14";
+
+    fn git_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/git/repository")
+    }
+
+    /// Working tree with the files of `main` of the repository fixture
+    fn repository_work_tree(name: &str) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("rsh-git-{}-{name}", std::process::id()));
+
+        let notes: String = (1..=60)
+            .map(|line| match line {
+                30 => String::from("thirty"),
+                40 => String::from("forty"),
+                50 => String::from("fifty"),
+                line => line.to_string()
+            })
+            .map(|line| format!("line {line} of the notes\n"))
+            .collect();
+
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("dir/sub")).unwrap();
+        fs::write(root.join(".gitignore"), "*.log\n!keep.log\nbuild/\n/out\ndir/**/*.tmp\n").unwrap();
+        fs::write(root.join("a.txt"), "a\na2\n").unwrap();
+        fs::write(root.join("dir/b.txt"), "b\nb2\n").unwrap();
+        fs::write(root.join("dir/c.txt"), "c\n").unwrap();
+        fs::write(root.join("dir/sub/long-name.txt"), "long\n").unwrap();
+        fs::write(root.join("notes.txt"), notes).unwrap();
+        symlink("a.txt", root.join("link")).unwrap();
+
+        root
+    }
+
+    fn commit(hex: &str) -> [u8; 20] {
+        parse_hex(hex).unwrap()
+    }
+
+    #[test]
+    fn computes_status_of_repository() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let root = repository_work_tree("repository");
+
+        let status = index_status(&root, &git_dir(), deadline);
+
+        assert!(!status.staged && !status.modified && !status.untracked && !status.incomplete);
+        assert_eq!((status.ahead, status.behind), (2, 1));
+
+        // ignored by `.gitignore` and by `info/exclude` of the fixture
+        fs::create_dir_all(root.join("build/dir")).unwrap();
+        fs::create_dir_all(root.join("dir/sub/deep")).unwrap();
+        fs::create_dir_all(root.join("empty")).unwrap();
+
+        for path in ["debug.log", "dir/trace.log", "build/dir/main.o", "out", "dir/sub/deep/x.tmp", "dir/x.tmp", ".a.txt.swp"] {
+            fs::write(root.join(path), "").unwrap();
+        }
+
+        assert!(!index_status(&root, &git_dir(), deadline).untracked);
+
+        // negated, anchored to other directory and inside of a new directory
+        for path in ["keep.log", "dir/out", "empty/file"] {
+            fs::write(root.join(path), "").unwrap();
+            assert!(index_status(&root, &git_dir(), deadline).untracked, "{path}");
+            fs::remove_file(root.join(path)).unwrap();
+        }
+
+        // nested repositories are untracked
+        fs::create_dir_all(root.join("empty/nested/.git")).unwrap();
+        assert!(index_status(&root, &git_dir(), deadline).untracked);
+
+        // status after the deadline
+        assert!(index_status(&root, &git_dir(), Instant::now()).incomplete);
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn compares_index_with_head() {
+        let repository = Repository::open(&git_dir());
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let index = || read_index(&git_dir().join("index")).unwrap();
+
+        // trees of the cache are the same as in `HEAD`
+        let unchanged = index();
+        assert_eq!(unchanged.cache_tree.get("").map(|tree| hex(*tree)).as_deref(), Some("20e196d08626646f1ea7e7822ba1db9cb3f83096"));
+        assert_eq!(unchanged.cache_tree.get("dir/").map(|tree| hex(*tree)).as_deref(), Some("3a80f409d6ea87202028369f9083e17b7a7772d1"));
+        assert_eq!(staged_changes(&repository, &unchanged, deadline), Some(false));
+
+        let mut uncached = index();
+        uncached.cache_tree.clear();
+        assert_eq!(staged_changes(&repository, &uncached, deadline), Some(false));
+
+        // entries of changed directories are compared file by file
+        let mut changed = index();
+        changed.cache_tree.clear();
+        changed.entries.iter_mut().filter(|entry| entry.path == "dir/b.txt").for_each(|entry| entry.hash = [0; 20]);
+        assert_eq!(staged_changes(&repository, &changed, deadline), Some(true));
+
+        let mut removed = index();
+        removed.cache_tree.remove("");
+        removed.entries.retain(|entry| entry.path != "notes.txt");
+        assert_eq!(staged_changes(&repository, &removed, deadline), Some(true));
+
+        let mut mode = index();
+        mode.cache_tree.remove("");
+        mode.entries.iter_mut().filter(|entry| entry.path == "a.txt").for_each(|entry| entry.mode = 0o100755);
+        assert_eq!(staged_changes(&repository, &mode, deadline), Some(true));
+
+        // files added with `git add -N` are not staged yet
+        let mut intended = index();
+        intended.cache_tree.remove("");
+        intended.entries.push(IndexEntry {
+            mtime: 0,
+            mtime_nsec: 0,
+            mode: 0o100644,
+            size: 0,
+            hash: [0; 20],
+            path: String::from("new.txt"),
+            stage: 0,
+            skip_worktree: false,
+            intent_to_add: true
+        });
+        assert_eq!(staged_changes(&repository, &intended, deadline), Some(false));
+
+        // merge conflicts are staged
+        let mut conflict = index();
+        conflict.entries[0].stage = 2;
+        assert_eq!(staged_changes(&repository, &conflict, deadline), Some(true));
+    }
+
+    #[test]
+    fn counts_commits_ahead_and_behind() {
+        let repository = Repository::open(&git_dir());
+        let deadline = Instant::now() + Duration::from_secs(10);
+
+        assert_eq!(count_commits(&repository, commit(MAIN), commit(ORIGIN), deadline), Some((2, 1)));
+        assert_eq!(count_commits(&repository, commit(ORIGIN), commit(MAIN), deadline), Some((1, 2)));
+        assert_eq!(count_commits(&repository, commit(FEATURE), commit(MAIN), deadline), Some((0, 3)));
+        assert_eq!(count_commits(&repository, commit(MAIN), commit(MAIN), deadline), Some((0, 0)));
+        assert_eq!(count_commits(&repository, [0; 20], commit(MAIN), deadline), None);
+    }
+
+    #[test]
+    fn finds_upstream_of_branches() {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let worktree = std::env::temp_dir().join(format!("rsh-git-{}-worktree", std::process::id()));
+        let repository = Repository::open(&git_dir());
+        let config = read_config(&git_dir());
+
+        assert_eq!(ahead_behind(&repository, &git_dir(), &config, deadline), Some((2, 1)));
+
+        // linked worktree with `feature`, whose upstream is local `main`
+        fs::create_dir_all(&worktree).unwrap();
+        fs::write(worktree.join("commondir"), format!("{}\n", git_dir().display())).unwrap();
+        fs::write(worktree.join("HEAD"), "ref: refs/heads/feature\n").unwrap();
+
+        let repository = Repository::open(&worktree);
+        assert_eq!(ahead_behind(&repository, &worktree, &config, deadline), Some((0, 3)));
+
+        // branches without upstream and detached `HEAD` have no counts
+        fs::write(worktree.join("HEAD"), "ref: refs/heads/main2\n").unwrap();
+        assert_eq!(ahead_behind(&repository, &worktree, &config, deadline), Some((0, 0)));
+
+        fs::write(worktree.join("HEAD"), format!("{MAIN}\n")).unwrap();
+        assert_eq!(ahead_behind(&repository, &worktree, &config, deadline), Some((0, 0)));
+
+        fs::remove_dir_all(worktree).unwrap();
+    }
+
+    #[test]
+    fn parses_git_config() {
+        let config = GitConfig(parse_config(concat!(
+            "# comment\n",
+            "[Core]\n",
+            "\tExcludesFile = ~/.ignore ; comment\n",
+            "\tbare\n",
+            "[branch \"Main\"]\n",
+            "\tremote = origin\n",
+            "\tmerge = \"refs/heads/Main\" # comment\n",
+            "[remote \"origin\"]\n",
+            "\tfetch = +refs/heads/*:refs/remotes/origin/*\n",
+            "\tfetch = +refs/tags/*:refs/tags/*\n",
+            "[core]\n",
+            "\texcludesfile = \"a \\\"b\\\" #c\"\n"
+        )));
+
+        // sections and keys are case-insensitive, subsections aren't
+        assert_eq!(config.value("core.excludesFile"), Some("a \"b\" #c"));
+        assert_eq!(config.value("core.bare"), Some("true"));
+        assert_eq!(config.value("branch.Main.merge"), Some("refs/heads/Main"));
+        assert_eq!(config.value("BRANCH.Main.REMOTE"), Some("origin"));
+        assert_eq!(config.value("branch.main.remote"), None);
+        assert_eq!(config.values("remote.origin.fetch").collect::<Vec<_>>(), ["+refs/heads/*:refs/remotes/origin/*", "+refs/tags/*:refs/tags/*"]);
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        let matches = |pattern: &str, text: &str| wildmatch(pattern.as_bytes(), text.as_bytes());
+
+        assert!(matches("*.log", "debug.log"));
+        assert!(matches("*", ""));
+        assert!(!matches("*.log", "dir/debug.log"));
+        assert!(matches("a?c", "abc"));
+        assert!(!matches("a?c", "a/c"));
+        assert!(matches("[a-c]x[!0-9]", "bxy"));
+        assert!(!matches("[a-c]x[!0-9]", "bx1"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[x", "[x"));
+        assert!(matches("\\*", "*"));
+        assert!(!matches("\\*", "a"));
+
+        // `**` matches any number of directories
+        assert!(matches("**/foo", "foo"));
+        assert!(matches("**/foo", "a/b/foo"));
+        assert!(matches("dir/**/*.tmp", "dir/x.tmp"));
+        assert!(matches("dir/**/*.tmp", "dir/a/b/x.tmp"));
+        assert!(!matches("dir/**/*.tmp", "other/x.tmp"));
+        assert!(matches("dir/**", "dir/a/b"));
+    }
+
+    #[test]
+    fn applies_ignore_rules() {
+        let mut rules = ignore_rules("# comment\n\n*.log\n!keep.log\nbuild/\n/out\ntrailing \n", "");
+        rules.extend(ignore_rules("*.txt\n!/keep.txt\n", "dir/"));
+
+        assert!(is_ignored(&rules, "debug.log", false));
+        assert!(is_ignored(&rules, "dir/debug.log", false));
+        assert!(!is_ignored(&rules, "keep.log", false));
+        assert!(is_ignored(&rules, "trailing", false));
+
+        // directory only patterns
+        assert!(is_ignored(&rules, "build", true));
+        assert!(is_ignored(&rules, "dir/build", true));
+        assert!(!is_ignored(&rules, "build", false));
+
+        // anchored patterns are relative to the directory of `.gitignore`
+        assert!(is_ignored(&rules, "out", false));
+        assert!(!is_ignored(&rules, "dir/out", false));
+        assert!(is_ignored(&rules, "dir/a.txt", false));
+        assert!(is_ignored(&rules, "dir/sub/a.txt", false));
+        assert!(!is_ignored(&rules, "dir/keep.txt", false));
+        assert!(!is_ignored(&rules, "a.txt", false));
+    }
+}
@@ -0,0 +1,586 @@
+//! Objects and references of git repositories
+//!
+//! Objects are read from loose files (`objects/ab/cdef...`) and
+//! from packs, where they can be stored as deltas of other
+//! objects. Pack indexes are searched with positioned reads, so
+//! big packs are not loaded into memory. References are read from
+//! their files or from `packed-refs`.
+//!
+//! See `Documentation/gitformat-pack.txt` in the git repository.
+
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use crate::inflate::inflate;
+
+/// Delta chains are limited, so broken packs don't loop forever
+const MAX_DELTA_DEPTH: usize = 1000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ObjectKind {
+    Commit,
+    Tree,
+    Blob,
+    Tag
+}
+
+impl ObjectKind {
+    fn parse(name: &str) -> Option<ObjectKind> {
+        match name {
+            "commit" => Some(ObjectKind::Commit),
+            "tree" => Some(ObjectKind::Tree),
+            "blob" => Some(ObjectKind::Blob),
+            "tag" => Some(ObjectKind::Tag),
+            _ => None
+        }
+    }
+}
+
+pub struct Commit {
+    pub tree: [u8; 20],
+    pub parents: Vec<[u8; 20]>,
+    /// Unix time of the commit
+    pub time: i64
+}
+
+pub struct TreeEntry {
+    pub mode: u32,
+    pub name: String,
+    pub hash: [u8; 20]
+}
+
+/// Pack file with its index
+struct Pack {
+    index: File,
+    pack: File,
+    /// Number of objects with the first byte of the hash up to the index
+    fanout: [u32; 256]
+}
+
+pub struct Repository {
+    /// Directory of the worktree: `HEAD`, index and per-worktree refs
+    git_dir: PathBuf,
+    /// Directory shared by worktrees: objects, refs and config
+    common_dir: PathBuf,
+    packs: Vec<Pack>
+}
+
+impl Repository {
+    pub fn open(git_dir: &Path) -> Repository {
+        // linked worktrees point to the main git directory
+        let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+            Ok(path) => git_dir.join(path.trim()),
+            Err(_) => git_dir.to_path_buf()
+        };
+
+        let packs = fs::read_dir(common_dir.join("objects/pack"))
+            .map(|entries| entries.flatten()
+                .map(|entry| entry.path())
+                .filter(|path| path.extension().is_some_and(|extension| extension == "idx"))
+                .filter_map(|path| Pack::open(&path))
+                .collect())
+            .unwrap_or_default();
+
+        Repository { git_dir: git_dir.to_path_buf(), common_dir, packs }
+    }
+
+    pub fn common_dir(&self) -> &Path {
+        &self.common_dir
+    }
+
+    /// Kind and content of the object
+    pub fn read(&self, hash: &[u8; 20]) -> Option<(ObjectKind, Vec<u8>)> {
+        self.read_loose(hash).or_else(|| {
+            self.packs.iter().find_map(|pack| pack.read(self, pack.offset(hash)?))
+        })
+    }
+
+    fn read_loose(&self, hash: &[u8; 20]) -> Option<(ObjectKind, Vec<u8>)> {
+        let hex = to_hex(hash);
+        let file = File::open(self.common_dir.join("objects").join(&hex[..2]).join(&hex[2..])).ok()?;
+        let data = inflate(file)?;
+
+        // header is `<kind> <size>\0`
+        let end = data.iter().position(|byte| *byte == 0)?;
+        let (kind, size) = std::str::from_utf8(&data[..end]).ok()?.split_once(' ')?;
+
+        let kind = ObjectKind::parse(kind)?;
+        let size: usize = size.parse().ok()?;
+
+        (data.len() - end - 1 == size).then(|| (kind, data[end + 1..].to_vec()))
+    }
+
+    pub fn commit(&self, hash: &[u8; 20]) -> Option<Commit> {
+        let (ObjectKind::Commit, data) = self.read(hash)? else { return None };
+        let text = String::from_utf8_lossy(&data);
+
+        let mut tree = None;
+        let mut parents = Vec::new();
+        let mut time = 0;
+
+        // headers end with an empty line before the message
+        for line in text.lines().take_while(|line| !line.is_empty()) {
+            match line.split_once(' ') {
+                Some(("tree", hash)) => tree = parse_hex(hash),
+                Some(("parent", hash)) => parents.push(parse_hex(hash)?),
+                // `committer Name <email> 1700000000 +0100`
+                Some(("committer", identity)) => {
+                    time = identity.rsplit(' ').nth(1).and_then(|time| time.parse().ok()).unwrap_or(0);
+                },
+                _ => {}
+            }
+        }
+
+        Some(Commit { tree: tree?, parents, time })
+    }
+
+    /// Entries of the tree, entries of subtrees have mode `0o40000`
+    pub fn tree(&self, hash: &[u8; 20]) -> Option<Vec<TreeEntry>> {
+        let (ObjectKind::Tree, data) = self.read(hash)? else { return None };
+        let mut entries = Vec::new();
+        let mut rest = &data[..];
+
+        // entries are `<octal mode> <name>\0<20 bytes of hash>`
+        while !rest.is_empty() {
+            let space = rest.iter().position(|byte| *byte == b' ')?;
+            let end = space + rest[space..].iter().position(|byte| *byte == 0)?;
+
+            let mode = u32::from_str_radix(std::str::from_utf8(&rest[..space]).ok()?, 8).ok()?;
+            let name = String::from_utf8_lossy(&rest[space + 1..end]).into_owned();
+            let hash = rest.get(end + 1..end + 21)?.try_into().ok()?;
+
+            entries.push(TreeEntry { mode, name, hash });
+            rest = &rest[end + 21..];
+        }
+
+        Some(entries)
+    }
+
+    /// Hash of the commit the reference points to, e.g. `HEAD`
+    /// or `refs/heads/main`. Symbolic references are followed
+    pub fn resolve(&self, reference: &str) -> Option<[u8; 20]> {
+        let mut reference = reference.to_string();
+
+        // symbolic references can't form long chains
+        for _ in 0..5 {
+            let value = self.read_reference(&reference)?;
+
+            match value.strip_prefix("ref: ") {
+                Some(target) => reference = target.to_string(),
+                None => return parse_hex(&value)
+            }
+        }
+
+        None
+    }
+
+    fn read_reference(&self, reference: &str) -> Option<String> {
+        // `HEAD` and these refs are kept for each worktree
+        let per_worktree = !reference.contains('/') || reference.starts_with("refs/bisect/") || reference.starts_with("refs/worktree/");
+        let directory = if per_worktree { &self.git_dir } else { &self.common_dir };
+
+        if let Ok(value) = fs::read_to_string(directory.join(reference)) {
+            return Some(value.trim().to_string());
+        }
+
+        // lines are `<hash> <name>`, or `^<hash>` of the peeled tag
+        fs::read_to_string(self.common_dir.join("packed-refs")).ok()?
+            .lines()
+            .filter(|line| !line.starts_with(['#', '^']))
+            .find_map(|line| match line.split_once(' ') {
+                Some((hash, name)) if name == reference => Some(hash.to_string()),
+                _ => None
+            })
+    }
+}
+
+impl Pack {
+    /// Opens the pack of the index of version 2
+    fn open(index_path: &Path) -> Option<Pack> {
+        let index = File::open(index_path).ok()?;
+        let pack = File::open(index_path.with_extension("pack")).ok()?;
+
+        let mut header = [0u8; 8 + 256 * 4];
+        index.read_exact_at(&mut header, 0).ok()?;
+
+        if header[..8] != *b"\xfftOc\0\0\0\x02" {
+            return None;
+        }
+
+        let mut fanout = [0u32; 256];
+
+        for (index, count) in fanout.iter_mut().enumerate() {
+            *count = u32::from_be_bytes(header[8 + index * 4..12 + index * 4].try_into().ok()?);
+        }
+
+        Some(Pack { index, pack, fanout })
+    }
+
+    /// Offset of the object in the pack, found by binary search
+    /// among hashes with the same first byte
+    fn offset(&self, hash: &[u8; 20]) -> Option<u64> {
+        let first = hash[0] as usize;
+        let count = self.fanout[255] as u64;
+
+        let mut low = if first == 0 { 0 } else { self.fanout[first - 1] } as u64;
+        let mut high = self.fanout[first] as u64;
+
+        let hashes = 8 + 256 * 4;
+
+        while low < high {
+            let middle = (low + high) / 2;
+
+            let mut found = [0u8; 20];
+            self.index.read_exact_at(&mut found, hashes + middle * 20).ok()?;
+
+            match found.cmp(hash) {
+                std::cmp::Ordering::Less => low = middle + 1,
+                std::cmp::Ordering::Greater => high = middle,
+                std::cmp::Ordering::Equal => {
+                    // offsets follow hashes and CRC32 of the objects
+                    let offsets = hashes + count * 24;
+                    let offset = read_u32_at(&self.index, offsets + middle * 4)?;
+
+                    if offset & 0x8000_0000 == 0 {
+                        return Some(offset as u64);
+                    }
+
+                    // big packs have 8-byte offsets in a separate table
+                    let mut large = [0u8; 8];
+                    self.index.read_exact_at(&mut large, offsets + count * 4 + (offset & 0x7fff_ffff) as u64 * 8).ok()?;
+
+                    return Some(u64::from_be_bytes(large));
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Reads the object at the offset, applying deltas to their bases
+    fn read(&self, repository: &Repository, offset: u64) -> Option<(ObjectKind, Vec<u8>)> {
+        let mut deltas = Vec::new();
+        let mut offset = offset;
+
+        let (kind, mut data) = loop {
+            if deltas.len() > MAX_DELTA_DEPTH {
+                return None;
+            }
+
+            let mut header = [0u8; 32];
+            let length = self.pack.read_at(&mut header, offset).ok()?;
+            let header = &header[..length];
+
+            // type in bits 4-6 of the first byte, then the size in 7-bit groups
+            let kind = (header.first()? >> 4) & 0x07;
+            let mut position = header.iter().position(|byte| byte & 0x80 == 0)? + 1;
+
+            match kind {
+                1..=4 => {
+                    let kind = [ObjectKind::Commit, ObjectKind::Tree, ObjectKind::Blob, ObjectKind::Tag][kind as usize - 1];
+                    break (kind, inflate(self.reader(offset + position as u64))?);
+                },
+                // base at the negative offset from this object
+                6 => {
+                    let mut byte = *header.get(position)?;
+                    let mut distance = (byte & 0x7f) as u64;
+
+                    while byte & 0x80 != 0 {
+                        position += 1;
+                        byte = *header.get(position)?;
+                        distance = ((distance + 1) << 7) | (byte & 0x7f) as u64;
+                    }
+
+                    deltas.push(inflate(self.reader(offset + position as u64 + 1))?);
+                    offset = offset.checked_sub(distance)?;
+                },
+                // base with the hash, possibly in another pack
+                7 => {
+                    let base: [u8; 20] = header.get(position..position + 20)?.try_into().ok()?;
+                    let delta = inflate(self.reader(offset + position as u64 + 20))?;
+
+                    let (kind, mut data) = repository.read(&base)?;
+
+                    data = apply_delta(&data, &delta)?;
+
+                    for delta in deltas.iter().rev() {
+                        data = apply_delta(&data, delta)?;
+                    }
+
+                    return Some((kind, data));
+                },
+                _ => return None
+            }
+        };
+
+        for delta in deltas.iter().rev() {
+            data = apply_delta(&data, delta)?;
+        }
+
+        Some((kind, data))
+    }
+
+    fn reader(&self, offset: u64) -> PackReader<'_> {
+        PackReader { file: &self.pack, offset }
+    }
+}
+
+/// Reads the pack from the offset
+struct PackReader<'a> {
+    file: &'a File,
+    offset: u64
+}
+
+impl Read for PackReader<'_> {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        let length = self.file.read_at(buffer, self.offset)?;
+        self.offset += length as u64;
+
+        Ok(length)
+    }
+}
+
+/// Builds the object from the base and the delta: sizes of the base
+/// and the result, then instructions that copy ranges of the base
+/// or insert new data
+fn apply_delta(base: &[u8], delta: &[u8]) -> Option<Vec<u8>> {
+    let mut delta = delta.iter().copied();
+
+    let mut size = || {
+        let mut size = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = delta.next()?;
+            size |= ((byte & 0x7f) as usize) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Some(size);
+            }
+        }
+    };
+
+    let base_size = size()?;
+    let result_size = size()?;
+
+    if base_size != base.len() {
+        return None;
+    }
+
+    let mut result = Vec::with_capacity(result_size);
+
+    while let Some(instruction) = delta.next() {
+        if instruction & 0x80 != 0 {
+            // bits 0-3 select bytes of the offset, bits 4-6 bytes of the size
+            let mut offset = 0;
+            let mut length = 0;
+
+            for bit in 0..4 {
+                if instruction & (1 << bit) != 0 {
+                    offset |= (delta.next()? as usize) << (bit * 8);
+                }
+            }
+
+            for bit in 0..3 {
+                if instruction & (1 << (bit + 4)) != 0 {
+                    length |= (delta.next()? as usize) << (bit * 8);
+                }
+            }
+
+            if length == 0 {
+                length = 0x10000;
+            }
+
+            result.extend_from_slice(base.get(offset..offset.checked_add(length)?)?);
+        } else if instruction != 0 {
+            for _ in 0..instruction {
+                result.push(delta.next()?);
+            }
+        } else {
+            return None;
+        }
+    }
+
+    (result.len() == result_size).then_some(result)
+}
+
+fn read_u32_at(file: &File, offset: u64) -> Option<u32> {
+    let mut bytes = [0u8; 4];
+    file.read_exact_at(&mut bytes, offset).ok()?;
+
+    Some(u32::from_be_bytes(bytes))
+}
+
+pub fn parse_hex(text: &str) -> Option<[u8; 20]> {
+    let text = text.trim();
+
+    if text.len() != 40 {
+        return None;
+    }
+
+    let mut hash = [0u8; 20];
+
+    for (index, byte) in hash.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(text.get(index * 2..index * 2 + 2)?, 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+pub fn to_hex(hash: &[u8; 20]) -> String {
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::sha1;
+
+    // fixture is made by `git` 2.39 with two packed commits of `main`,
+    // `feature` and `origin/main`, and two loose commits on top of them
+
+    const HEAD: &str = "2dc94e46c900e7c6f64105b88c961d31f69fd21b";
+    const FOURTH: &str = "6c7001651c97917ba8ed02683785c0d4bf178631";
+    const FIRST: &str = "31b8a4761a8109a3a7f9b32995a0361ab02c06f9";
+
+    fn repository() -> Repository {
+        Repository::open(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/git/repository"))
+    }
+
+    fn hash(hex: &str) -> [u8; 20] {
+        parse_hex(hex).unwrap()
+    }
+
+    /// Hash of the object computed from its content
+    fn object_hash(kind: ObjectKind, data: &[u8]) -> String {
+        let name = format!("{kind:?}").to_lowercase();
+        let mut object = format!("{name} {}\0", data.len()).into_bytes();
+        object.extend(data);
+
+        to_hex(&sha1(&object))
+    }
+
+    #[test]
+    fn reads_loose_and_packed_objects() {
+        let repository = repository();
+
+        let objects = [
+            // loose
+            (HEAD, ObjectKind::Commit),
+            ("20e196d08626646f1ea7e7822ba1db9cb3f83096", ObjectKind::Tree),
+            ("9ad2ebbaff6f3397bb65002dcf4294d8d6243982", ObjectKind::Blob),
+            // packed whole
+            ("54c2f312c42329d59fb98f056f35847594f3b190", ObjectKind::Commit),
+            ("d4c62976cb295329bf9d01b1a9ef3684029b75ae", ObjectKind::Blob),
+            ("8edfd9fbd7307efaa4832e7a2b1a4a70fa4f286a", ObjectKind::Tree),
+            // deltas of other objects, and a delta of a delta
+            ("6283938f8a0cfe5c2b5f13561c16e693765e1da9", ObjectKind::Blob),
+            ("eb9f3f674988368870ca0bfa7a6cd090087d375a", ObjectKind::Tree),
+            ("cae5f1e7def6b5dee72ea71f386eeb4413fd3b7e", ObjectKind::Blob)
+        ];
+
+        for (hex, expected) in objects {
+            let (kind, data) = repository.read(&hash(hex)).unwrap_or_else(|| panic!("object {hex}"));
+
+            assert_eq!(kind, expected, "object {hex}");
+            assert_eq!(object_hash(kind, &data), hex);
+        }
+
+        let (_, notes) = repository.read(&hash("6283938f8a0cfe5c2b5f13561c16e693765e1da9")).unwrap();
+        assert!(String::from_utf8(notes).unwrap().contains("line 29 of the notes\nline thirty of the notes\n"));
+
+        assert!(repository.read(&[0; 20]).is_none());
+        assert!(repository.read(&[0xff; 20]).is_none());
+    }
+
+    #[test]
+    fn parses_commits_and_trees() {
+        let repository = repository();
+
+        let commit = repository.commit(&hash(HEAD)).unwrap();
+        assert_eq!(to_hex(&commit.tree), "20e196d08626646f1ea7e7822ba1db9cb3f83096");
+        assert_eq!(commit.parents, [hash(FOURTH)]);
+        assert_eq!(commit.time, 1000005000);
+
+        let commit = repository.commit(&hash(FIRST)).unwrap();
+        assert!(commit.parents.is_empty());
+        assert_eq!(commit.time, 1000000000);
+
+        let entries: Vec<(u32, String, String)> = repository.tree(&commit_tree(&repository, HEAD)).unwrap()
+            .into_iter()
+            .map(|entry| (entry.mode, entry.name, to_hex(&entry.hash)))
+            .collect();
+
+        assert_eq!(entries, [
+            (0o100644, String::from(".gitignore"), String::from("eb6db7e68e17fa331777f3a63104841c1c1e28d0")),
+            (0o100644, String::from("a.txt"), String::from("9ad2ebbaff6f3397bb65002dcf4294d8d6243982")),
+            (0o40000, String::from("dir"), String::from("3a80f409d6ea87202028369f9083e17b7a7772d1")),
+            (0o120000, String::from("link"), String::from("8d14cbf983b3fad683171c9418998d9f68340823")),
+            (0o100644, String::from("notes.txt"), String::from("ea416582ba7ce4cd33f95b03716231d00fe13b64"))
+        ]);
+
+        // objects of other kinds are not parsed
+        assert!(repository.commit(&commit_tree(&repository, HEAD)).is_none());
+        assert!(repository.tree(&hash(HEAD)).is_none());
+    }
+
+    fn commit_tree(repository: &Repository, commit: &str) -> [u8; 20] {
+        repository.commit(&hash(commit)).unwrap().tree
+    }
+
+    #[test]
+    fn resolves_loose_and_packed_references() {
+        let repository = repository();
+
+        assert_eq!(repository.resolve("HEAD"), Some(hash(HEAD)));
+        assert_eq!(repository.resolve("refs/heads/main"), Some(hash(HEAD)));
+        assert_eq!(repository.resolve("refs/heads/feature"), Some(hash("17532a856674f155787433fc570bac6b13c9a101")));
+        assert_eq!(repository.resolve("refs/remotes/origin/main"), Some(hash("7fecc20d8a5975649c30f79dba1bb8b8eba66e14")));
+        assert_eq!(repository.resolve("refs/heads/missing"), None);
+    }
+
+    #[test]
+    fn reads_linked_worktrees_from_the_common_directory() {
+        let git_dir = std::env::temp_dir().join(format!("rsh-git-objects-{}", std::process::id()));
+        let common_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/git/repository");
+
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("commondir"), format!("{}\n", common_dir.display())).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/feature\n").unwrap();
+
+        let repository = Repository::open(&git_dir);
+
+        assert_eq!(repository.common_dir(), common_dir);
+        assert_eq!(repository.resolve("HEAD"), Some(hash("17532a856674f155787433fc570bac6b13c9a101")));
+        assert!(repository.commit(&hash(FIRST)).is_some());
+
+        fs::remove_dir_all(git_dir).unwrap();
+    }
+
+    #[test]
+    fn applies_deltas() {
+        let base = b"hello, delta world";
+
+        // sizes, copy of 7 bytes at 0, insert of 3 bytes, copy of 6 bytes at 12
+        let delta = b"\x12\x10\x90\x07\x03new\x91\x0c\x06";
+        assert_eq!(apply_delta(base, delta).unwrap(), b"hello, new world");
+
+        // size of the base or the result doesn't match
+        assert!(apply_delta(b"hello", delta).is_none());
+        assert!(apply_delta(base, b"\x12\x11\x90\x07\x03new\x91\x0c\x06").is_none());
+
+        // copy out of the base, reserved instruction
+        assert!(apply_delta(base, b"\x12\x10\x91\x10\x10").is_none());
+        assert!(apply_delta(base, b"\x12\x00\x00").is_none());
+    }
+
+    #[test]
+    fn parses_hex_hashes() {
+        assert_eq!(to_hex(&hash(HEAD)), HEAD);
+        assert_eq!(parse_hex(&format!("{HEAD}\n")), Some(hash(HEAD)));
+        assert_eq!(parse_hex(&HEAD[1..]), None);
+        assert_eq!(parse_hex(&HEAD.replace('2', "g")), None);
+    }
+}
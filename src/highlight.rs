@@ -0,0 +1,114 @@
+//! Syntax highlighting of the input line
+//!
+//! Line is split with the same lexer that is used for
+//! execution, and commands are resolved in the same order
+//! as they are executed, so highlighting never disagrees
+//! with how the line will run.
+//!
+//! | element | color |
+//! |:----|:----|
+//! | executable from `$PATH` | green |
+//! | builtin, keyword | bold green |
+//! | alias, function | italic green |
+//! | instant | magenta |
+//! | command that is not found | red |
+//! | `"string"`, `'literal` | yellow |
+//! | `$variable` | cyan |
+//! | `\|`, `&&`, `;`, `&`, `>`, `>>` | blue |
+//! | comment | dimmed |
+
+use crate::colors::*;
+use crate::execute::{CommandKind, command_kind};
+use crate::lexer::{TokenKind, tokenize};
+use crate::parse_command::{COMMAND_KEYWORDS, KEYWORDS};
+
+pub fn highlight(line: &str) -> String {
+    let mut highlighted = String::new();
+    let mut last = 0;
+    let mut command_position = true;
+
+    for token in tokenize(line) {
+        highlighted += &line[last..token.start];
+        last = token.end();
+
+        match token.kind {
+            TokenKind::Comment => {
+                highlighted += &paint(DIMMED, token.text);
+                continue;
+            },
+            TokenKind::Word if command_position => {
+                highlighted += &paint(command_color(token.text), token.text);
+            },
+            TokenKind::Word => highlighted += &highlight_word(token.text),
+            _ => highlighted += &paint(BLUE, token.text)
+        }
+
+        // body of the function starts with a command
+        command_position = token.is_command_separator()
+            || (command_position && COMMAND_KEYWORDS.contains(&token.text))
+            || (token.kind == TokenKind::Word && token.text == "{");
+    }
+
+    highlighted + &line[last..]
+}
+
+fn command_color(word: &str) -> String {
+    if KEYWORDS.contains(&word) {
+        return format!("{BOLD}{GREEN}");
+    }
+
+    match command_kind(word) {
+        Some(CommandKind::Executable) => GREEN.to_string(),
+        Some(CommandKind::Builtin) => format!("{BOLD}{GREEN}"),
+        Some(CommandKind::Alias | CommandKind::Function) => format!("{ITALIC}{GREEN}"),
+        Some(CommandKind::Instant) => MAGENTA.to_string(),
+        None => RED.to_string()
+    }
+}
+
+/// Highlights variables, literal strings and
+/// double-quoted parts of the argument
+fn highlight_word(word: &str) -> String {
+    if word.starts_with('$') {
+        return paint(CYAN, word);
+    }
+
+    if word.starts_with('\'') {
+        return paint(YELLOW, word);
+    }
+
+    let mut highlighted = String::new();
+    let mut chars = word.chars();
+    let mut quoted = false;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => {
+                highlighted.push(char);
+                highlighted.extend(chars.next());
+            },
+            '"' if quoted => {
+                highlighted.push(char);
+                highlighted += RESET;
+                quoted = false;
+            },
+            '"' => {
+                highlighted += YELLOW;
+                highlighted.push(char);
+                quoted = true;
+            },
+            char => highlighted.push(char)
+        }
+    }
+
+    // unclosed string lasts until the end of the word
+    if quoted {
+        highlighted += RESET;
+    }
+
+    highlighted
+}
+
+fn paint(color: impl AsRef<str>, text: &str) -> String {
+    format!("{}{text}{RESET}", color.as_ref())
+}
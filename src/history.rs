@@ -0,0 +1,595 @@
+//! Command history
+//!
+//! Each entry is stored in the history file as a line with
+//! tab-separated fields: start time (Unix seconds), duration
+//! (milliseconds), exit status, working directory and the
+//! command. Backslashes, tabs and new lines are escaped, so
+//! multi-line commands take a single line:
+//!
+//! ```text
+//! 1760875200<TAB>12<TAB>0<TAB>/home/user<TAB>echo a |\ncat
+//! ```
+//!
+//! Entries are appended right after the command finishes, so
+//! history of concurrent sessions is merged in the file. The file
+//! is locked with `flock(2)` while it is written. Lines without
+//! metadata (e.g. from older versions) are loaded as commands
+//! with unknown time.
+
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::config::config;
+use crate::execute::ExecutionResult;
+use crate::lexer::{TokenKind, tokenize};
+use crate::prompt::{format_duration, format_timestamp};
+use crate::utils::{error_log, matches_pattern, open_locked, rewrite_file};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Dedup {
+    /// All commands are recorded
+    None,
+    /// Command is not recorded if it is the same as the previous one
+    Consecutive,
+    /// Older entries with the same command are removed
+    All
+}
+
+#[derive(Clone)]
+pub struct Entry {
+    pub command: String,
+    /// Unix time the command started at, `0` if unknown
+    pub start: u64,
+    pub duration: Duration,
+    pub status: i32,
+    /// Working directory the command was started in, empty if unknown
+    pub directory: String
+}
+
+impl Entry {
+    fn parse(line: &str) -> Entry {
+        let fields: Vec<&str> = line.splitn(5, '\t').collect();
+
+        if let [start, duration, status, directory, command] = fields[..] {
+            if let (Ok(start), Ok(duration), Ok(status)) = (start.parse(), duration.parse(), status.parse()) {
+                return Entry {
+                    command: unescape(command),
+                    start,
+                    duration: Duration::from_millis(duration),
+                    status,
+                    directory: unescape(directory)
+                };
+            }
+        }
+
+        Entry { command: unescape(line), start: 0, duration: Duration::ZERO, status: 0, directory: String::new() }
+    }
+
+    fn to_line(&self) -> String {
+        format!(
+            "{}\t{}\t{}\t{}\t{}\n",
+            self.start,
+            self.duration.as_millis(),
+            self.status,
+            escape(&self.directory),
+            escape(&self.command)
+        )
+    }
+}
+
+struct History {
+    entries: Vec<Entry>,
+    /// Entries were removed, so the line editor must reload them
+    modified: bool
+}
+
+static HISTORY: Mutex<History> = Mutex::new(History { entries: Vec::new(), modified: false });
+
+/// Loads history from the file of the config, and trims
+/// the file if it exceeds the size limit
+pub fn load_history() -> io::Result<()> {
+    let config = config();
+    let mut file = open_locked(&config.history_path)?;
+
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+
+    let mut entries: Vec<Entry> = content.lines()
+        // header of files written by the line editor
+        .filter(|line| !line.is_empty() && *line != "#V2")
+        .map(Entry::parse)
+        .collect();
+
+    let count = entries.len();
+
+    if config.history_dedup == Dedup::All {
+        let mut seen = std::collections::HashSet::new();
+
+        // the latest entry of each command is kept
+        entries.reverse();
+        entries.retain(|entry| seen.insert(entry.command.clone()));
+        entries.reverse();
+    }
+
+    if entries.len() > config.history_size {
+        entries.drain(..entries.len() - config.history_size);
+    }
+
+    if entries.len() != count {
+        rewrite(&mut file, &entries)?;
+    }
+
+    HISTORY.lock().unwrap().entries = entries;
+
+    Ok(())
+}
+
+/// Whether the command must be recorded according to the config
+pub fn should_record(command: &str) -> bool {
+    let config = config();
+
+    if command.trim().is_empty() || config.history_size == 0 {
+        return false;
+    }
+
+    if config.history_ignore_space && command.starts_with(char::is_whitespace) {
+        return false;
+    }
+
+    if config.history_ignore.iter().any(|pattern| matches_pattern(pattern, command)) {
+        return false;
+    }
+
+    let history = HISTORY.lock().unwrap();
+
+    config.history_dedup == Dedup::None
+        || history.entries.last().is_none_or(|last| last.command != command)
+}
+
+/// Adds the entry to the history and appends it to the file
+pub fn record(entry: Entry) {
+    let config = config();
+    let mut history = HISTORY.lock().unwrap();
+
+    if config.history_dedup == Dedup::All {
+        let count = history.entries.len();
+        history.entries.retain(|previous| previous.command != entry.command);
+        history.modified |= history.entries.len() != count;
+    }
+
+    let line = entry.to_line();
+    history.entries.push(entry);
+
+    if history.entries.len() > config.history_size {
+        let excess = history.entries.len() - config.history_size;
+        history.entries.drain(..excess);
+    }
+
+    // file is trimmed when the next session starts
+    let appended = open_locked(&config.history_path)
+        .and_then(|mut file| file.write_all(line.as_bytes()));
+
+    if let Err(err) = appended {
+        error_log(Box::<dyn Error>::from(format!("failed to save history: {err}")));
+    }
+}
+
+/// Whether entries were removed since the last call, so the
+/// line editor must reload them
+pub fn take_modified() -> bool {
+    std::mem::take(&mut HISTORY.lock().unwrap().modified)
+}
+
+/// Calls the function with the entries, from the oldest to the newest
+pub fn with_entries<T>(f: impl FnOnce(&[Entry]) -> T) -> T {
+    f(&HISTORY.lock().unwrap().entries)
+}
+
+/// Current Unix time in seconds
+pub fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())
+}
+
+/// Expands references to history entries, like bash does
+///
+/// | reference | expands to |
+/// |:----|:----|
+/// | `!!` | previous command |
+/// | `!n`, `!-n` | command number `n`, `n`-th command back |
+/// | `!prefix` | the latest command that starts with the prefix |
+/// | `!?text?` | the latest command that contains the text |
+/// | `!$`, `!^`, `!*` | last argument, first argument, all arguments of the previous command |
+/// | `^old^new` | previous command with `old` replaced with `new` |
+///
+/// Word of the command can be selected with `:` after the
+/// reference, e.g. `!cargo:2` or `!!:$`. `!` before whitespace,
+/// `=`, `(` or `"`, escaped with `\` or in literal strings
+/// is not expanded. Returns `None` if nothing was expanded
+pub fn expand_history(line: &str) -> Result<Option<String>, Box<dyn Error>> {
+    with_entries(|entries| expand_references(line, entries))
+}
+
+/// Expands references in the line to the entries, see `expand_history`
+fn expand_references(line: &str, entries: &[Entry]) -> Result<Option<String>, Box<dyn Error>> {
+    if let Some(substitution) = line.strip_prefix('^') {
+        return quick_substitution(substitution, entries).map(Some);
+    }
+
+    let chars: Vec<char> = line.chars().collect();
+    let mut expanded = String::new();
+    let mut changed = false;
+    let mut word_start = true;
+    let mut index = 0;
+
+    while index < chars.len() {
+        let char = chars[index];
+
+        match char {
+            '\\' => {
+                expanded.extend(&chars[index..(index + 2).min(chars.len())]);
+                index += 2;
+                word_start = false;
+                continue;
+            },
+            // literal string lasts until the whitespace
+            '\'' if word_start => {
+                while index < chars.len() && !chars[index].is_whitespace() {
+                    expanded.push(chars[index]);
+                    index += 1;
+                }
+
+                continue;
+            },
+            '!' => if let Some((text, length)) = reference(&chars[index + 1..], entries)? {
+                expanded += &text;
+                index += length + 1;
+                changed = true;
+                word_start = false;
+                continue;
+            },
+            _ => {}
+        }
+
+        expanded.push(char);
+        word_start = char.is_whitespace() || matches!(char, '|' | '&' | ';');
+        index += 1;
+    }
+
+    Ok(changed.then_some(expanded))
+}
+
+/// Expands the reference after `!`, returns the text and
+/// the number of characters it takes
+fn reference(chars: &[char], entries: &[Entry]) -> Result<Option<(String, usize)>, Box<dyn Error>> {
+    let previous = || entries.last().ok_or_else(|| Box::<dyn Error>::from("!!: event not found"));
+
+    let (entry, mut length) = match chars.first() {
+        None => return Ok(None),
+        Some(char) if char.is_whitespace() || matches!(char, '=' | '(' | '"') => return Ok(None),
+        Some('!') => (previous()?, 1),
+        // word of the previous command
+        Some('$' | '^' | '*') => (previous()?, 0),
+        Some('?') => {
+            let text: String = chars[1..].iter().take_while(|char| **char != '?').collect();
+            let length = text.chars().count() + if chars.get(text.chars().count() + 1) == Some(&'?') { 2 } else { 1 };
+
+            let entry = entries.iter().rev().find(|entry| entry.command.contains(&text));
+            (entry.ok_or_else(|| Box::<dyn Error>::from(format!("!?{text}: event not found")))?, length)
+        },
+        Some(_) => {
+            let text: String = chars.iter().take_while(|char| !char.is_whitespace() && **char != ':').collect();
+            let length = text.chars().count();
+
+            let entry = match text.parse::<i64>() {
+                Ok(number) if number > 0 => entries.get(number as usize - 1),
+                Ok(number) if number < 0 => entries.len().checked_sub(number.unsigned_abs() as usize).and_then(|index| entries.get(index)),
+                Ok(_) => None,
+                Err(_) => entries.iter().rev().find(|entry| entry.command.starts_with(&text))
+            };
+
+            (entry.ok_or_else(|| Box::<dyn Error>::from(format!("!{text}: event not found")))?, length)
+        }
+    };
+
+    let words: Vec<&str> = tokenize(&entry.command)
+        .into_iter()
+        .filter(|token| token.kind != TokenKind::Comment)
+        .map(|token| token.text)
+        .collect();
+
+    let designator: String = match chars.get(length) {
+        // `!$` is the same as `!!:$`
+        Some(char @ ('$' | '^' | '*')) if length == 0 => {
+            length = 1;
+            char.to_string()
+        },
+        Some(':') => {
+            let designator: String = chars[length + 1..].iter()
+                .take_while(|char| char.is_ascii_digit() || matches!(char, '$' | '^' | '*'))
+                .collect();
+
+            length += designator.chars().count() + 1;
+            designator
+        },
+        _ => return Ok(Some((entry.command.clone(), length)))
+    };
+
+    let word = match designator.as_str() {
+        "$" => words.last().map(|word| word.to_string()),
+        "^" => words.get(1).map(|word| word.to_string()),
+        "*" => Some(words.get(1..).unwrap_or_default().join(" ")),
+        number => number.parse::<usize>().ok().and_then(|number| words.get(number)).map(|word| word.to_string())
+    };
+
+    match word {
+        Some(word) => Ok(Some((word, length))),
+        None => Err(Box::<dyn Error>::from(format!("{}: bad word specifier", entry.command)))
+    }
+}
+
+/// Replaces the first occurrence of `old` in the previous command,
+/// `substitution` is `old^new` or `old^new^`
+fn quick_substitution(substitution: &str, entries: &[Entry]) -> Result<String, Box<dyn Error>> {
+    let mut parts = substitution.splitn(3, '^');
+    let old = parts.next().unwrap_or_default();
+    let new = parts.next().unwrap_or_default();
+
+    let previous = match entries.last() {
+        Some(previous) => &previous.command,
+        None => return Err(Box::<dyn Error>::from("^: event not found"))
+    };
+
+    if old.is_empty() || !previous.contains(old) {
+        return Err(Box::<dyn Error>::from(format!("^{old}^{new}: substitution failed")));
+    }
+
+    Ok(previous.replacen(old, new, 1))
+}
+
+/// Implementation of shell built-in `history` function
+///
+/// ```rsh
+/// history             # list all entries
+/// history 10          # list the last 10 entries
+/// history -l          # list with time, duration, status and directory
+/// history -s cargo    # list entries that contain `cargo`
+/// history -d 12 15    # delete entries 12 and 15
+/// history -c          # clear the history
+/// ```
+pub fn history_builtin(args: &[String]) -> ExecutionResult {
+    match run_builtin(args) {
+        Ok(_) => ExecutionResult::Success,
+        Err(err) => ExecutionResult::Error(Box::<dyn Error>::from(format!("history: {err}")))
+    }
+}
+
+fn run_builtin(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut long = false;
+    let mut args = args.iter().map(String::as_str).peekable();
+
+    if args.next_if_eq(&"-l").is_some() {
+        long = true;
+    }
+
+    match (args.next(), args.peek()) {
+        (None, _) => list(|_| true, usize::MAX, long),
+        (Some("-s"), Some(_)) => {
+            let text = args.collect::<Vec<&str>>().join(" ");
+            list(|entry| entry.command.contains(&text), usize::MAX, long);
+        },
+        (Some("-d"), Some(_)) => {
+            let mut indexes = Vec::new();
+
+            for index in args {
+                match index.parse::<usize>() {
+                    Ok(index) if index > 0 => indexes.push(index - 1),
+                    _ => return Err(Box::<dyn Error>::from(format!("invalid entry number `{index}`")))
+                }
+            }
+
+            delete(&indexes)?;
+        },
+        (Some("-c"), None) => clear()?,
+        (Some(count), None) if !count.starts_with('-') => match count.parse() {
+            Ok(count) => list(|_| true, count, long),
+            Err(_) => return Err(Box::<dyn Error>::from(format!("invalid number of entries `{count}`")))
+        },
+        (Some(flag @ ("-s" | "-d")), None) => {
+            return Err(Box::<dyn Error>::from(format!("`{flag}` requires an argument")));
+        },
+        (Some(argument), _) => return Err(Box::<dyn Error>::from(format!("unexpected argument `{argument}`")))
+    }
+
+    Ok(())
+}
+
+/// Prints the last `count` entries that match the filter
+fn list(filter: impl Fn(&Entry) -> bool, count: usize, long: bool) {
+    with_entries(|entries| {
+        let matched: Vec<(usize, &Entry)> = entries.iter().enumerate().filter(|(_, entry)| filter(entry)).collect();
+
+        for (index, entry) in &matched[matched.len().saturating_sub(count)..] {
+            let time = match entry.start {
+                0 => String::from("-"),
+                start => format_timestamp("%Y-%m-%d %H:%M", start as i64)
+            };
+
+            // continuation lines are aligned with the first one
+            let command = entry.command.replace('\n', "\n\t");
+
+            if long {
+                let duration = if entry.start == 0 { String::from("-") } else { format_duration(entry.duration) };
+                println!("{:>5}  {time:<16}  {duration:>8}  {:>3}  {}\t{command}", index + 1, entry.status, entry.directory);
+            } else {
+                println!("{:>5}  {time:<16}  {command}", index + 1);
+            }
+        }
+    });
+}
+
+/// Deletes entries by their indexes both from the memory and the file
+fn delete(indexes: &[usize]) -> Result<(), Box<dyn Error>> {
+    let mut history = HISTORY.lock().unwrap();
+
+    if let Some(index) = indexes.iter().find(|index| **index >= history.entries.len()) {
+        return Err(Box::<dyn Error>::from(format!("no entry number {}", index + 1)));
+    }
+
+    let deleted: Vec<Entry> = indexes.iter().map(|index| history.entries[*index].clone()).collect();
+
+    let mut index = 0;
+    history.entries.retain(|_| {
+        index += 1;
+        !indexes.contains(&(index - 1))
+    });
+    history.modified = true;
+
+    // file is read again to keep entries of other sessions
+    let mut file = open_locked(&config().history_path)?;
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+
+    let entries: Vec<Entry> = content.lines()
+        .filter(|line| !line.is_empty() && *line != "#V2")
+        .map(Entry::parse)
+        .filter(|entry| !deleted.iter().any(|deleted| deleted.start == entry.start && deleted.command == entry.command))
+        .collect();
+
+    rewrite(&mut file, &entries)?;
+
+    Ok(())
+}
+
+fn clear() -> io::Result<()> {
+    let mut history = HISTORY.lock().unwrap();
+
+    history.entries.clear();
+    history.modified = true;
+
+    open_locked(&config().history_path)?.set_len(0)
+}
+
+/// Replaces content of the locked file with the entries
+fn rewrite(file: &mut File, entries: &[Entry]) -> io::Result<()> {
+    let content: String = entries.iter().map(Entry::to_line).collect();
+
+    rewrite_file(file, &content)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n").replace('\t', "\\t")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+
+    while let Some(char) = chars.next() {
+        match (char, chars.clone().next()) {
+            ('\\', Some('n')) => unescaped.push('\n'),
+            ('\\', Some('t')) => unescaped.push('\t'),
+            ('\\', Some('\\')) => unescaped.push('\\'),
+            (char, _) => {
+                unescaped.push(char);
+                continue;
+            }
+        }
+
+        chars.next();
+    }
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(commands: &[&str]) -> Vec<Entry> {
+        commands.iter()
+            .map(|command| Entry { command: command.to_string(), start: 0, duration: Duration::ZERO, status: 0, directory: String::new() })
+            .collect()
+    }
+
+    fn expand(line: &str) -> Result<Option<String>, String> {
+        let entries = entries(&["cargo build --release", "ls -la src", "echo one two three"]);
+
+        expand_references(line, &entries).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn expands_events() {
+        assert_eq!(expand("!!"), Ok(Some(String::from("echo one two three"))));
+        assert_eq!(expand("sudo !!"), Ok(Some(String::from("sudo echo one two three"))));
+        assert_eq!(expand("!1"), Ok(Some(String::from("cargo build --release"))));
+        assert_eq!(expand("!-2"), Ok(Some(String::from("ls -la src"))));
+        assert_eq!(expand("!car"), Ok(Some(String::from("cargo build --release"))));
+        assert_eq!(expand("!?la?"), Ok(Some(String::from("ls -la src"))));
+        assert_eq!(expand("!?la"), Ok(Some(String::from("ls -la src"))));
+    }
+
+    #[test]
+    fn expands_words() {
+        assert_eq!(expand("cat !$"), Ok(Some(String::from("cat three"))));
+        assert_eq!(expand("cat !^"), Ok(Some(String::from("cat one"))));
+        assert_eq!(expand("cat !*"), Ok(Some(String::from("cat one two three"))));
+        assert_eq!(expand("cd !ls:2"), Ok(Some(String::from("cd src"))));
+        assert_eq!(expand("!!:0 !1:$"), Ok(Some(String::from("echo --release"))));
+    }
+
+    #[test]
+    fn quick_substitution_replaces_first_occurrence() {
+        assert_eq!(expand("^one^1"), Ok(Some(String::from("echo 1 two three"))));
+        assert_eq!(expand("^o^0^"), Ok(Some(String::from("ech0 one two three"))));
+        assert_eq!(expand("^four^4"), Err(String::from("^four^4: substitution failed")));
+    }
+
+    #[test]
+    fn keeps_text_that_is_not_a_reference() {
+        for line in ["echo hi", "echo hi!", "echo ! x", "[ ! -f a ]", "a != b", "echo \\!!", "echo '!!", "echo \"!\""] {
+            assert_eq!(expand(line), Ok(None), "{line}");
+        }
+    }
+
+    #[test]
+    fn reports_missing_events() {
+        assert_eq!(expand("!vim"), Err(String::from("!vim: event not found")));
+        assert_eq!(expand("!9"), Err(String::from("!9: event not found")));
+        assert_eq!(expand("!?nothing?"), Err(String::from("!?nothing: event not found")));
+        assert_eq!(expand("!!:7"), Err(String::from("echo one two three: bad word specifier")));
+        assert_eq!(expand_references("!!", &[]).map_err(|err| err.to_string()), Err(String::from("!!: event not found")));
+    }
+
+    #[test]
+    fn entries_round_trip_through_file_lines() {
+        let entry = Entry {
+            command: String::from("printf 'a\\tb' |\ncat"),
+            start: 1760875200,
+            duration: Duration::from_millis(12),
+            status: 3,
+            directory: String::from("/home/user/my\tdir")
+        };
+
+        let line = entry.to_line();
+        assert_eq!(line.matches('\n').count(), 1);
+        assert!(line.ends_with('\n'));
+
+        let parsed = Entry::parse(line.trim_end_matches('\n'));
+        assert_eq!(parsed.command, entry.command);
+        assert_eq!(parsed.start, entry.start);
+        assert_eq!(parsed.duration, entry.duration);
+        assert_eq!(parsed.status, entry.status);
+        assert_eq!(parsed.directory, entry.directory);
+    }
+
+    #[test]
+    fn lines_without_metadata_are_commands() {
+        let entry = Entry::parse("git commit -m 'x'");
+
+        assert_eq!(entry.command, "git commit -m 'x'");
+        assert_eq!(entry.start, 0);
+        assert_eq!(entry.directory, "");
+    }
+}
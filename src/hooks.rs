@@ -0,0 +1,194 @@
+//! Functions called on events of the shell
+//!
+//! Functions are registered with `hook` builtin and stored
+//! as space-separated names in `__HOOK_<event>` variables:
+//!
+//! | event | called | arguments |
+//! |:----|:----|:----|
+//! | `preexec` | before the command line is executed | command line |
+//! | `precmd` | before the prompt is drawn | |
+//! | `chpwd` | after the working directory is changed | new directory |
+//! | `exit` | when the interactive shell exits | exit status |
+//!
+//! Hooks don't change `$?`, and a hook that triggers its own
+//! event (e.g. `cd` inside `chpwd`) doesn't call it again.
+
+use std::env::{set_var, var};
+use std::error::Error;
+use std::sync::Mutex;
+use crate::execute::ExecutionResult;
+use crate::utils::{error_log, exec_function, is_function, last_exit_status, set_exit_status};
+
+pub const EVENTS: [&str; 4] = ["preexec", "precmd", "chpwd", "exit"];
+
+/// Events whose hooks are running
+static RUNNING: Mutex<Vec<&str>> = Mutex::new(Vec::new());
+
+fn hooks(event: &str) -> Vec<String> {
+    var("__HOOK_".to_owned() + event)
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(String::from)
+        .collect()
+}
+
+/// Calls functions registered for the event
+///
+/// Returns `Exit` if one of them exits the shell
+pub fn run_hooks(event: &'static str, args: &[String]) -> ExecutionResult {
+    let functions = hooks(event);
+
+    if functions.is_empty() {
+        return ExecutionResult::Success;
+    }
+
+    {
+        let mut running = RUNNING.lock().unwrap();
+
+        if running.contains(&event) {
+            return ExecutionResult::Success;
+        }
+
+        running.push(event);
+    }
+
+    let status = last_exit_status();
+    let mut result = ExecutionResult::Success;
+
+    for function in functions {
+        if !is_function(&function) {
+            error_log(Box::<dyn Error>::from(format!("{event} hook: {function}: function not found")));
+            continue;
+        }
+
+        match exec_function(&function, args) {
+            ExecutionResult::Error(err) => error_log(err),
+            ExecutionResult::Exit(code) => {
+                result = ExecutionResult::Exit(code);
+                break;
+            },
+            _ => {}
+        }
+    }
+
+    set_exit_status(status);
+    RUNNING.lock().unwrap().retain(|running| *running != event);
+
+    result
+}
+
+/// Implementation of shell built-in `hook` function
+///
+/// ```rsh
+/// hook precmd update_title    # call `update_title` before each prompt
+/// hook -d precmd update_title # stop calling it
+/// hook                        # list hooks
+/// ```
+pub fn hook_builtin(args: &[String]) -> ExecutionResult {
+    let (remove, args) = match args.split_first() {
+        Some((option, rest)) if option == "-d" => (true, rest),
+        _ => (false, args)
+    };
+
+    let Some((event, functions)) = args.split_first() else {
+        if remove {
+            return ExecutionResult::Error(Box::<dyn Error>::from("hook: event name required"));
+        }
+
+        for event in EVENTS {
+            let functions = hooks(event);
+
+            if !functions.is_empty() {
+                println!("{event}: {}", functions.join(" "));
+            }
+        }
+
+        return ExecutionResult::Success;
+    };
+
+    if !EVENTS.contains(&event.as_str()) {
+        return ExecutionResult::Error(Box::<dyn Error>::from(format!(
+            "hook: {event}: unknown event (expected {})", EVENTS.join(", ")
+        )));
+    }
+
+    if functions.is_empty() {
+        return ExecutionResult::Error(Box::<dyn Error>::from("hook: function name required"));
+    }
+
+    let mut registered = hooks(event);
+
+    for function in functions {
+        if remove {
+            registered.retain(|name| name != function);
+        } else if !registered.contains(function) {
+            registered.push(function.clone());
+        }
+    }
+
+    set_var("__HOOK_".to_owned() + event, registered.join(" "));
+
+    ExecutionResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env::remove_var;
+    use std::fs;
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn hook(args: &[&str]) -> ExecutionResult {
+        hook_builtin(&strings(args))
+    }
+
+    /// Hooks are kept in variables, so a single test changes them
+    #[test]
+    fn registers_and_runs_hooks_in_order() {
+        let output = std::env::temp_dir().join(format!("rsh-hooks-{}", std::process::id()));
+        let _ = fs::remove_file(&output);
+
+        for name in ["first", "second", "third"] {
+            set_var(format!("__FN_rsh_test_{name}"), format!("echo {name} &arg >> {}", output.display()));
+        }
+
+        assert!(matches!(hook(&["precmd", "rsh_test_second", "rsh_test_first"]), ExecutionResult::Success));
+        assert_eq!(hooks("precmd"), ["rsh_test_second", "rsh_test_first"]);
+
+        // registered functions keep their place
+        hook(&["precmd", "rsh_test_third", "rsh_test_second"]);
+        assert_eq!(hooks("precmd"), ["rsh_test_second", "rsh_test_first", "rsh_test_third"]);
+
+        hook(&["-d", "precmd", "rsh_test_first", "rsh_test_missing"]);
+        assert_eq!(hooks("precmd"), ["rsh_test_second", "rsh_test_third"]);
+        assert!(hooks("chpwd").is_empty());
+
+        // functions that are not found are skipped
+        hook(&["precmd", "rsh_test_missing", "rsh_test_first"]);
+        assert_eq!(run_hooks("precmd", &strings(&["x"])).status(), 0);
+        assert_eq!(fs::read_to_string(&output).unwrap(), "second x\nthird x\nfirst x\n");
+
+        assert!(matches!(hook(&["precmd"]), ExecutionResult::Error(_)));
+        assert!(matches!(hook(&["-d", "precmd"]), ExecutionResult::Error(_)));
+        assert!(matches!(hook(&["-d"]), ExecutionResult::Error(_)));
+        assert!(matches!(hook(&["postexec", "rsh_test_first"]), ExecutionResult::Error(_)));
+        assert!(matches!(run_hooks("exit", &[]), ExecutionResult::Success));
+
+        // the first hook that exits stops the others
+        set_var("__FN_rsh_test_exit", "exit 4");
+        hook(&["-d", "precmd", "rsh_test_missing", "rsh_test_second", "rsh_test_third", "rsh_test_first"]);
+        hook(&["precmd", "rsh_test_exit", "rsh_test_first"]);
+        assert!(matches!(run_hooks("precmd", &[]), ExecutionResult::Exit(4)));
+        assert_eq!(fs::read_to_string(&output).unwrap(), "second x\nthird x\nfirst x\n");
+
+        for name in ["first", "second", "third", "exit"] {
+            remove_var(format!("__FN_rsh_test_{name}"));
+        }
+
+        remove_var("__HOOK_precmd");
+        let _ = fs::remove_file(&output);
+    }
+}
@@ -0,0 +1,339 @@
+//! Decompression of zlib streams
+//!
+//! Git stores objects compressed with zlib, both as loose
+//! files and inside packs. Only decompression is needed to
+//! read them, so DEFLATE (RFC 1951) is decoded here instead
+//! of depending on a compression library. Huffman codes are
+//! decoded bit by bit, which is fast enough for commits and
+//! trees read for the prompt.
+
+use std::io::{BufReader, Read};
+
+/// Length of the code at most, in bits
+const MAX_BITS: usize = 15;
+
+/// Base lengths of length codes 257..285 and their extra bits
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31,
+    35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2,
+    3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0
+];
+
+/// Base distances of distance codes 0..29 and their extra bits
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193,
+    257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6,
+    7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13
+];
+
+/// Order of code length codes in the header of a dynamic block
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompresses the zlib stream at the start of the input
+///
+/// Input after the end of the stream is not read further than
+/// the buffer of the reader, so streams inside packs are read
+/// without knowing their compressed size. Returns `None` if the
+/// stream is invalid or its checksum doesn't match
+pub fn inflate<R: Read>(input: R) -> Option<Vec<u8>> {
+    let mut bits = BitReader { input: BufReader::new(input), buffer: 0, count: 0 };
+
+    // compression method 8 (DEFLATE), no preset dictionary
+    let header = bits.bits(16)?;
+    let (method, flags) = (header & 0xff, header >> 8);
+
+    if method & 0x0f != 8 || ((method << 8) | flags) % 31 != 0 || flags & 0x20 != 0 {
+        return None;
+    }
+
+    let output = inflate_raw(&mut bits)?;
+
+    bits.align();
+    let checksum = (0..4).try_fold(0u32, |checksum, _| Some((checksum << 8) | bits.bits(8)?))?;
+
+    (adler32(&output) == checksum).then_some(output)
+}
+
+/// Decodes DEFLATE blocks until the final one
+fn inflate_raw<R: Read>(bits: &mut BitReader<R>) -> Option<Vec<u8>> {
+    let mut output = Vec::new();
+
+    loop {
+        let last = bits.bits(1)? == 1;
+
+        match bits.bits(2)? {
+            0 => stored_block(bits, &mut output)?,
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                compressed_block(bits, &mut output, &lengths, &distances)?;
+            },
+            2 => {
+                let (lengths, distances) = dynamic_codes(bits)?;
+                compressed_block(bits, &mut output, &lengths, &distances)?;
+            },
+            _ => return None
+        }
+
+        if last {
+            return Some(output);
+        }
+    }
+}
+
+fn stored_block<R: Read>(bits: &mut BitReader<R>, output: &mut Vec<u8>) -> Option<()> {
+    bits.align();
+
+    let length = bits.bits(16)?;
+
+    if bits.bits(16)? != !length & 0xffff {
+        return None;
+    }
+
+    for _ in 0..length {
+        output.push(bits.bits(8)? as u8);
+    }
+
+    Some(())
+}
+
+fn compressed_block<R: Read>(bits: &mut BitReader<R>, output: &mut Vec<u8>, lengths: &Huffman, distances: &Huffman) -> Option<()> {
+    loop {
+        let symbol = lengths.decode(bits)? as usize;
+
+        match symbol {
+            0..=255 => output.push(symbol as u8),
+            256 => return Some(()),
+            _ => {
+                let index = symbol - 257;
+                let length = *LENGTH_BASE.get(index)? as usize + bits.bits(LENGTH_EXTRA[index])? as usize;
+
+                let index = distances.decode(bits)? as usize;
+                let distance = *DISTANCE_BASE.get(index)? as usize + bits.bits(DISTANCE_EXTRA[index])? as usize;
+
+                let start = output.len().checked_sub(distance)?;
+
+                // copied bytes may overlap the ones being written
+                for offset in 0..length {
+                    output.push(output[start + offset]);
+                }
+            }
+        }
+    }
+}
+
+/// Codes of the block with fixed Huffman codes
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+/// Reads code lengths from the header of the dynamic block
+fn dynamic_codes<R: Read>(bits: &mut BitReader<R>) -> Option<(Huffman, Huffman)> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+
+    let mut lengths = [0u8; 19];
+
+    for index in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+        lengths[*index] = bits.bits(3)? as u8;
+    }
+
+    let code = Huffman::new(&lengths);
+    let mut lengths = Vec::with_capacity(literals + distances);
+
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => (*lengths.last()?, 3 + bits.bits(2)?),
+            17 => (0, 3 + bits.bits(3)?),
+            18 => (0, 11 + bits.bits(7)?),
+            _ => return None
+        };
+
+        lengths.extend(std::iter::repeat_n(length, repeat as usize));
+    }
+
+    if lengths.len() != literals + distances || lengths[256] == 0 {
+        return None;
+    }
+
+    Some((Huffman::new(&lengths[..literals]), Huffman::new(&lengths[literals..])))
+}
+
+/// Canonical Huffman code
+struct Huffman {
+    /// Number of codes of each length
+    counts: [u16; MAX_BITS + 1],
+    /// Symbols ordered by their codes
+    symbols: Vec<u16>
+}
+
+impl Huffman {
+    /// Code with the lengths of the symbols, `0` for unused symbols
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; MAX_BITS + 1];
+
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+
+        counts[0] = 0;
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16).filter(|symbol| lengths[*symbol as usize] != 0).collect();
+        symbols.sort_by_key(|symbol| lengths[*symbol as usize]);
+
+        Huffman { counts, symbols }
+    }
+
+    /// Reads the code bit by bit, from the shortest codes to the longest
+    fn decode<R: Read>(&self, bits: &mut BitReader<R>) -> Option<u16> {
+        // first code of the length and index of its symbol
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+
+        for count in &self.counts[1..] {
+            code |= bits.bits(1)? as i32;
+
+            let count = *count as i32;
+
+            if code - first < count {
+                return self.symbols.get((index + code - first) as usize).copied();
+            }
+
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        None
+    }
+}
+
+/// Reads bits from the least significant ones, as DEFLATE packs them
+struct BitReader<R: Read> {
+    input: BufReader<R>,
+    buffer: u32,
+    count: u8
+}
+
+impl<R: Read> BitReader<R> {
+    fn bits(&mut self, count: u8) -> Option<u32> {
+        while self.count < count {
+            let mut byte = [0];
+            self.input.read_exact(&mut byte).ok()?;
+
+            self.buffer |= (byte[0] as u32) << self.count;
+            self.count += 8;
+        }
+
+        let value = self.buffer & ((1u64 << count) - 1) as u32;
+
+        self.buffer = self.buffer.checked_shr(count as u32).unwrap_or(0);
+        self.count -= count;
+
+        Some(value)
+    }
+
+    /// Skips the rest of the current byte
+    fn align(&mut self) {
+        self.buffer >>= self.count % 8;
+        self.count -= self.count % 8;
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    // sums don't overflow in chunks of this size
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // streams are made with `zlib.compress` of Python
+
+    #[test]
+    fn inflates_stored_blocks() {
+        let stream = b"\x78\x01\x01\x12\x00\xed\xff\x68\x65\x6c\x6c\x6f\x20\x68\x65\x6c\x6c\x6f\x20\x68\x65\x6c\x6c\x6f\x0a\x40\xb5\x06\x87";
+        assert_eq!(inflate(&stream[..]).unwrap(), b"hello hello hello\n");
+    }
+
+    #[test]
+    fn inflates_fixed_codes() {
+        let stream = b"\x78\xda\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x5c\x00\x40\xb5\x06\x87";
+        assert_eq!(inflate(&stream[..]).unwrap(), b"hello hello hello\n");
+
+        assert_eq!(inflate(&b"\x78\xda\x03\x00\x00\x00\x00\x01"[..]).unwrap(), b"");
+
+        // distance is shorter than the copied length
+        let stream = b"\x78\xda\x4b\x4c\x1c\x05\xc4\x02\x00\xd8\xa8\x71\xad";
+        assert_eq!(inflate(&stream[..]).unwrap(), [b'a'; 300]);
+    }
+
+    #[test]
+    fn inflates_dynamic_codes() {
+        let stream = b"\x78\xda\x0d\xc7\xd1\x09\x80\x30\x0c\x04\xd0\x55\x6e\x00\xf7\x70\x8e\xa6\x46\x8c\x44\xae\
+            \x78\x81\x82\xd3\xdb\xbf\xf7\xf6\x28\xa8\xf8\xba\x40\xbb\xbd\x97\xd0\xf9\x8c\x75\xf9\x81\x19\x75\
+            \xe1\xcb\xb0\x0d\xc6\xc5\x26\x24\x29\xc7\x19\xe9\xfa\x01\xae\xad\x16\x53";
+
+        assert_eq!(inflate(&stream[..]).unwrap(), b"Git stores objects compressed with zlib, both as loose files");
+    }
+
+    #[test]
+    fn ignores_input_after_the_stream() {
+        let mut stream = b"\x78\xda\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x5c\x00\x40\xb5\x06\x87".to_vec();
+        stream.extend(b"next object");
+
+        assert_eq!(inflate(&stream[..]).unwrap(), b"hello hello hello\n");
+    }
+
+    #[test]
+    fn rejects_invalid_streams() {
+        let stream = b"\x78\xda\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x5c\x00\x40\xb5\x06\x87";
+
+        // checksum, header, truncated stream
+        assert!(inflate(&b"\x78\xda\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x5c\x00\x40\xb5\x06\x88"[..]).is_none());
+        assert!(inflate(&b"\x78\xdb\xcb\x48\xcd\xc9\xc9\x57\xc8\x40\x90\x5c\x00\x40\xb5\x06\x87"[..]).is_none());
+        assert!(inflate(&stream[..10]).is_none());
+        assert!(inflate(&b""[..]).is_none());
+
+        // reserved block type
+        assert!(inflate(&b"\x78\xda\x07\x00"[..]).is_none());
+    }
+
+    #[test]
+    fn adler32_of_known_strings() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(adler32(&[0xff; 100_000]), {
+            let (a, b) = (0..100_000u64).fold((1u64, 0u64), |(a, b), _| ((a + 0xff) % 65521, (b + a + 0xff) % 65521));
+            ((b << 16) | a) as u32
+        });
+    }
+}
@@ -1,15 +1,74 @@
-/// instant commands that support pipes, but
-/// instantly return ExecutionResult.
-/// They print result of the action directly
-/// to the console (stdout).
-/// 
-/// In shell, this functions starts with `@` char.
-/// For example, function `instant_exec` will be
-/// `@exec`
+//! instant commands that support pipes, but
+//! instantly return ExecutionResult.
+//! They print result of the action directly
+//! to the console (stdout).
+//! 
+//! In shell, this functions starts with `@` char.
+//! For example, function `instant_exec` will be
+//! `@exec`
 
 use std::{process::Child, io::Read};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
 use crate::{execute::{ExecutionResult, execute_file}, colors::*};
 
+pub const INSTANTS: [&str; 2] = ["@exec", "@format"];
+
+/// Instant registered by the program that embeds the shell,
+/// called with the output of the previous command of the
+/// pipeline (if any) and the expanded arguments
+pub type CustomInstant = Arc<dyn Fn(Option<String>, &[String]) -> ExecutionResult + Send + Sync>;
+
+static CUSTOM_INSTANTS: Mutex<BTreeMap<String, CustomInstant>> = Mutex::new(BTreeMap::new());
+
+/// Registers the instant, its name must start with `@`
+pub fn register_instant(name: &str, instant: CustomInstant) -> Result<(), Box<dyn Error>> {
+    if name.len() < 2 || !name.starts_with('@') || name.contains(char::is_whitespace) {
+        return Err(Box::<dyn Error>::from(format!("{name}: invalid instant name, it must start with `@`")));
+    }
+
+    if INSTANTS.contains(&name) {
+        return Err(Box::<dyn Error>::from(format!("{name}: instant already exists")));
+    }
+
+    CUSTOM_INSTANTS.lock().unwrap().insert(name.to_string(), instant);
+
+    Ok(())
+}
+
+pub fn is_instant(name: &str) -> bool {
+    INSTANTS.contains(&name) || CUSTOM_INSTANTS.lock().unwrap().contains_key(name)
+}
+
+/// Names of built-in and custom instants
+pub fn instant_names() -> Vec<String> {
+    let mut names: Vec<String> = INSTANTS.iter().map(|name| name.to_string()).collect();
+    names.extend(CUSTOM_INSTANTS.lock().unwrap().keys().cloned());
+
+    names
+}
+
+/// Calls the custom instant, returns `None` if there is no such instant
+pub fn instant_custom(name: &str, stdin: Option<Child>, args: Vec<String>) -> Option<ExecutionResult> {
+    let instant = CUSTOM_INSTANTS.lock().unwrap().get(name).cloned()?;
+
+    let input = match stdin {
+        Some(process) => {
+            let mut input = String::new();
+
+            if let Err(err) = process.stdout.unwrap().read_to_string(&mut input) {
+                return Some(ExecutionResult::Error(Box::new(err)));
+            }
+
+            Some(input)
+        },
+        None => None
+    };
+
+    Some(instant(input, &args))
+}
+
 pub fn instant_exec(stdin: Option<Child>, args: Vec<String>) -> ExecutionResult {
     let mut first_arg = String::new();
 
@@ -20,15 +79,14 @@ pub fn instant_exec(stdin: Option<Child>, args: Vec<String>) -> ExecutionResult
                 Err(err) => return ExecutionResult::Error(Box::new(err))
             };
         },
-        None => first_arg = args.to_owned().into_iter().next().unwrap_or_default()
+        None => first_arg = args.first().cloned().unwrap_or_default()
     };
 
     let no_exit = args.contains(&"--noexit".to_string()) || args.contains(&"-n".to_string());
 
     match execute_file(first_arg.trim()) {
-        ExecutionResult::Success => ExecutionResult::Success,
-        ExecutionResult::Error(err) => ExecutionResult::Error(err),
-        ExecutionResult::Exit => if no_exit {ExecutionResult::Success} else {ExecutionResult::Exit}
+        ExecutionResult::Exit(code) => if no_exit {ExecutionResult::Success} else {ExecutionResult::Exit(code)},
+        result => result
     }
 }
 
@@ -45,7 +103,7 @@ pub fn instant_format(stdin: Option<Child>, args: Vec<String>) -> ExecutionResul
         None => {
             let mut string_to_format = String::new();
 
-            for arg in args.to_owned().into_iter().peekable() {
+            for arg in args.iter().cloned() {
                 if arg.starts_with('-') {
                     break;
                 }
@@ -60,20 +118,9 @@ pub fn instant_format(stdin: Option<Child>, args: Vec<String>) -> ExecutionResul
     first_arg = first_arg.trim_end().to_string();
 
     for arg in args.iter() {
-        let format_modifier = match arg.as_str() {
-            "--red" => RED,
-            "--green" => GREEN,
-            "--yellow" => YELLOW,
-            "--blue" => BLUE,
-            "--magenta" => MAGENTA,
-            "--cyan" => CYAN,
-            "--white" => WHITE,
-            "--bold" => BOLD,
-            "--dimmed" => DIMMED,
-            "--italic" => ITALIC,
-            "--underline" => UNDERLINE,
-            _ => ""
-        };
+        let format_modifier = arg.strip_prefix("--")
+            .and_then(color_by_name)
+            .unwrap_or_default();
 
         first_arg = String::from(format_modifier) + &first_arg;
     }
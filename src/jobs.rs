@@ -0,0 +1,150 @@
+//! Background jobs
+//!
+//! Pipeline that ends with `&` is not waited by the shell.
+//! Its processes are stored in the job table until they finish.
+
+use std::process::Child;
+use std::sync::Mutex;
+use crate::execute::ExecutionResult;
+use crate::signals::is_interactive;
+
+pub struct Job {
+    pub id: usize,
+    pub command: String,
+    processes: Vec<Child>
+}
+
+static JOBS: Mutex<Vec<Job>> = Mutex::new(Vec::new());
+
+/// Adds processes of the pipeline to the job table
+///
+/// Interactive shell prints id of the job and pid
+/// of its last process, e.g. `[1] 4242`
+pub fn add_job(command: &str, processes: Vec<Child>) {
+    let mut jobs = JOBS.lock().unwrap();
+
+    let id = jobs.iter().map(|job| job.id).max().unwrap_or(0) + 1;
+
+    if let Some(process) = processes.last().filter(|_| is_interactive()) {
+        println!("[{id}] {}", process.id());
+    }
+
+    jobs.push(Job { id, command: command.to_string(), processes });
+}
+
+/// Removes finished jobs from the table, interactive shell reports them
+pub fn reap_jobs() {
+    JOBS.lock().unwrap().retain_mut(|job| {
+        let finished = job.processes.iter_mut()
+            .all(|process| matches!(process.try_wait(), Ok(Some(_)) | Err(_)));
+
+        if finished && is_interactive() {
+            println!("[{}] done    {}", job.id, job.command);
+        }
+
+        !finished
+    });
+}
+
+/// Sends the signal to processes of all jobs
+pub fn signal_jobs(signal: libc::c_int) {
+    for job in JOBS.lock().unwrap().iter_mut() {
+        for process in job.processes.iter_mut() {
+            if matches!(process.try_wait(), Ok(None)) {
+                unsafe {
+                    libc::kill(process.id() as libc::pid_t, signal);
+                }
+            }
+        }
+    }
+}
+
+/// Number of jobs that are still running
+pub fn job_count() -> usize {
+    JOBS.lock().unwrap().len()
+}
+
+/// Implementation of shell built-in `jobs` function
+///
+/// Lists running background jobs
+pub fn list_jobs() -> ExecutionResult {
+    reap_jobs();
+
+    for job in JOBS.lock().unwrap().iter() {
+        println!("[{}] running {}", job.id, job.command);
+    }
+
+    ExecutionResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+    use crate::execute::execute;
+
+    fn jobs() -> Vec<(usize, String)> {
+        JOBS.lock().unwrap().iter().map(|job| (job.id, job.command.clone())).collect()
+    }
+
+    fn kill_jobs() {
+        for job in JOBS.lock().unwrap().iter_mut() {
+            for process in &mut job.processes {
+                let _ = process.kill();
+                let _ = process.wait();
+            }
+        }
+
+        reap_jobs();
+    }
+
+    /// Reaps the jobs until there are `count` of them left
+    fn wait_for_jobs(count: usize) {
+        let start = Instant::now();
+
+        while job_count() > count && start.elapsed() < Duration::from_secs(5) {
+            reap_jobs();
+            sleep(Duration::from_millis(10));
+        }
+    }
+
+    // the job table is global, so it is tested in one test
+    #[test]
+    fn job_table() {
+        add_job("sleep 10", vec![Command::new("sleep").arg("10").spawn().unwrap()]);
+        add_job("true", vec![Command::new("true").spawn().unwrap()]);
+
+        assert_eq!(jobs(), [(1, String::from("sleep 10")), (2, String::from("true"))]);
+
+        // finished jobs are removed, and their ids are reused
+        wait_for_jobs(1);
+        assert_eq!(jobs(), [(1, String::from("sleep 10"))]);
+
+        add_job("true", vec![Command::new("true").spawn().unwrap()]);
+        assert_eq!(jobs()[1].0, 2);
+
+        // job with several processes runs until all of them finish
+        add_job("sleep 10 | true", vec![
+            Command::new("sleep").arg("10").spawn().unwrap(),
+            Command::new("true").spawn().unwrap()
+        ]);
+
+        sleep(Duration::from_millis(100));
+        reap_jobs();
+        assert!(jobs().iter().any(|(_, command)| command == "sleep 10 | true"));
+
+        kill_jobs();
+        assert_eq!(job_count(), 0);
+
+        // pipeline that ends with `&` is not waited for
+        let start = Instant::now();
+        assert!(matches!(execute("sleep 10 | cat &"), ExecutionResult::Success));
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(jobs(), [(1, String::from("sleep 10 | cat"))]);
+
+        kill_jobs();
+        assert_eq!(job_count(), 0);
+    }
+}
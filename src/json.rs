@@ -0,0 +1,346 @@
+//! JSON values
+//!
+//! Reader and writer of JSON for the messages of the
+//! language server and the output of `rsh --check --json`.
+
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// Fields in the order they are written
+    Object(Vec<(String, Json)>)
+}
+
+/// Value of the missing fields
+static NULL: Json = Json::Null;
+
+impl Json {
+    /// Object with the fields
+    pub fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+        Json::Object(fields.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+    }
+
+    /// Field of the object, `Null` if there is no such field
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(fields) => fields.iter()
+                .find(|(name, _)| name == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(text) => Some(text),
+            _ => None
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Json::Number(number) if *number >= 0.0 && number.fract() == 0.0 => Some(*number as usize),
+            _ => None
+        }
+    }
+
+    pub fn as_array(&self) -> &[Json] {
+        match self {
+            Json::Array(items) => items,
+            _ => &[]
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Json, Box<dyn Error>> {
+        let mut chars = text.chars().peekable();
+        let value = parse_value(&mut chars)?;
+
+        skip_whitespace(&mut chars);
+
+        match chars.next() {
+            None => Ok(value),
+            Some(char) => Err(Box::<dyn Error>::from(format!("unexpected `{char}` after JSON value")))
+        }
+    }
+}
+
+impl From<&str> for Json {
+    fn from(text: &str) -> Json {
+        Json::String(text.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(text: String) -> Json {
+        Json::String(text)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(number: usize) -> Json {
+        Json::Number(number as f64)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(value: bool) -> Json {
+        Json::Bool(value)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(items: Vec<Json>) -> Json {
+        Json::Array(items)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(value) => write!(f, "{value}"),
+            Json::Number(number) if number.fract() == 0.0 && number.abs() < 1e15 => write!(f, "{}", *number as i64),
+            Json::Number(number) => write!(f, "{number}"),
+            Json::String(text) => write!(f, "{}", quote(text)),
+            Json::Array(items) => {
+                write!(f, "[")?;
+
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write!(f, "{item}")?;
+                }
+
+                write!(f, "]")
+            },
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+
+                for (index, (key, value)) in fields.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+
+                    write!(f, "{}:{value}", quote(key))?;
+                }
+
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+/// Quotes the text as JSON string
+pub fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+
+    for char in text.chars() {
+        match char {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\t' => quoted += "\\t",
+            char if char.is_control() => quoted += &format!("\\u{:04x}", char as u32),
+            char => quoted.push(char)
+        }
+    }
+
+    quoted + "\""
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.next_if(|char| char.is_ascii_whitespace()).is_some() {}
+}
+
+fn expect(chars: &mut Peekable<Chars>, expected: &str) -> Result<(), Box<dyn Error>> {
+    for char in expected.chars() {
+        if chars.next() != Some(char) {
+            return Err(Box::<dyn Error>::from(format!("expected `{expected}`")));
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Json, Box<dyn Error>> {
+    skip_whitespace(chars);
+
+    match chars.peek() {
+        Some('n') => expect(chars, "null").map(|_| Json::Null),
+        Some('t') => expect(chars, "true").map(|_| Json::Bool(true)),
+        Some('f') => expect(chars, "false").map(|_| Json::Bool(false)),
+        Some('"') => parse_string(chars).map(Json::String),
+        Some('[') => {
+            chars.next();
+            let mut items = Vec::new();
+
+            skip_whitespace(chars);
+
+            if chars.next_if_eq(&']').is_some() {
+                return Ok(Json::Array(items));
+            }
+
+            loop {
+                items.push(parse_value(chars)?);
+                skip_whitespace(chars);
+
+                match chars.next() {
+                    Some(',') => {},
+                    Some(']') => return Ok(Json::Array(items)),
+                    _ => return Err(Box::<dyn Error>::from("expected `,` or `]` in array"))
+                }
+            }
+        },
+        Some('{') => {
+            chars.next();
+            let mut fields = Vec::new();
+
+            skip_whitespace(chars);
+
+            if chars.next_if_eq(&'}').is_some() {
+                return Ok(Json::Object(fields));
+            }
+
+            loop {
+                skip_whitespace(chars);
+
+                if chars.peek() != Some(&'"') {
+                    return Err(Box::<dyn Error>::from("expected field name in object"));
+                }
+
+                let key = parse_string(chars)?;
+
+                skip_whitespace(chars);
+                expect(chars, ":")?;
+                fields.push((key, parse_value(chars)?));
+                skip_whitespace(chars);
+
+                match chars.next() {
+                    Some(',') => {},
+                    Some('}') => return Ok(Json::Object(fields)),
+                    _ => return Err(Box::<dyn Error>::from("expected `,` or `}` in object"))
+                }
+            }
+        },
+        Some(char) if *char == '-' || char.is_ascii_digit() => {
+            let mut number = String::new();
+
+            while let Some(char) = chars.next_if(|char| char.is_ascii_digit() || matches!(char, '-' | '+' | '.' | 'e' | 'E')) {
+                number.push(char);
+            }
+
+            number.parse::<f64>()
+                .map(Json::Number)
+                .map_err(|_| Box::<dyn Error>::from(format!("invalid number `{number}`")))
+        },
+        Some(char) => Err(Box::<dyn Error>::from(format!("unexpected `{char}`"))),
+        None => Err(Box::<dyn Error>::from("unexpected end of JSON"))
+    }
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, Box<dyn Error>> {
+    chars.next();
+
+    let mut text = String::new();
+
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(text),
+            Some('\\') => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some('t') => text.push('\t'),
+                Some('r') => text.push('\r'),
+                Some('b') => text.push('\u{8}'),
+                Some('f') => text.push('\u{c}'),
+                Some('u') => {
+                    let mut code = parse_hex(chars)?;
+
+                    // characters outside of the basic plane are surrogate pairs
+                    if (0xd800..0xdc00).contains(&code) {
+                        expect(chars, "\\u")?;
+
+                        let low = parse_hex(chars)?;
+
+                        if !(0xdc00..0xe000).contains(&low) {
+                            return Err(Box::<dyn Error>::from("invalid surrogate pair"));
+                        }
+
+                        code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                    }
+
+                    text.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                },
+                Some(char) => text.push(char),
+                None => return Err(Box::<dyn Error>::from("unclosed string"))
+            },
+            Some(char) => text.push(char),
+            None => return Err(Box::<dyn Error>::from("unclosed string"))
+        }
+    }
+}
+
+fn parse_hex(chars: &mut Peekable<Chars>) -> Result<u32, Box<dyn Error>> {
+    let digits: String = chars.take(4).collect();
+
+    u32::from_str_radix(&digits, 16).map_err(|_| Box::<dyn Error>::from(format!("invalid escape `\\u{digits}`")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let value = Json::object([
+            ("jsonrpc", Json::from("2.0")),
+            ("id", Json::from(7)),
+            ("result", Json::Null),
+            ("done", Json::from(true)),
+            ("numbers", Json::from(vec![Json::Number(-1.5), Json::Number(2e20), Json::Number(0.0)])),
+            ("text", Json::from("quote \" backslash \\ new line \n tab \t bell \u{7} 𝄞 é")),
+            ("nested", Json::object([("empty", Json::from(vec![])), ("object", Json::Object(vec![]))]))
+        ]);
+
+        let text = value.to_string();
+
+        assert!(!text.contains('\n'));
+        assert_eq!(Json::parse(&text).unwrap(), value);
+    }
+
+    #[test]
+    fn serializes_compactly() {
+        let value = Json::object([("a", Json::from(vec![Json::from(1), Json::Null])), ("b", Json::from("x\ny"))]);
+
+        assert_eq!(value.to_string(), r#"{"a":[1,null],"b":"x\ny"}"#);
+        assert_eq!(Json::Number(0.25).to_string(), "0.25");
+        assert_eq!(quote("\u{1b}[0m"), r#""\u001b[0m""#);
+    }
+
+    #[test]
+    fn parses_whitespace_and_escapes() {
+        let value = Json::parse(" { \"a\" : [ 1 , 2.5e1 , -3 ] ,\n\t\"b\" : \"\\u00e9\\ud834\\udd1e\\/\\r\" } ").unwrap();
+
+        assert_eq!(value.get("a").as_array(), [Json::Number(1.0), Json::Number(25.0), Json::Number(-3.0)]);
+        assert_eq!(value.get("b").as_str(), Some("é𝄞/\r"));
+        assert_eq!(value.get("missing"), &Json::Null);
+        assert_eq!(value.get("a").as_array()[0].as_usize(), Some(1));
+        assert_eq!(value.get("a").as_array()[2].as_usize(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        for text in ["", "nul", "[1,", "[1 2]", "{\"a\" 1}", "{a:1}", "\"open", "1 2", "\"\\uzzzz\"", "\"\\ud834\\u0041\"", "-"] {
+            assert!(Json::parse(text).is_err(), "{text:?}");
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Jumping to frequently used directories
+//!
+//! Directories entered with `cd` in the interactive shell are
+//! recorded in `~/.rsh_directories` with their rank and the time
+//! of the last visit. Rank grows with each visit, and the score
+//! of the directory is its rank weighted by recency ("frecency"),
+//! like in z:
+//!
+//! | last visit | score |
+//! |:----|:----|
+//! | within an hour | rank × 4 |
+//! | within a day | rank × 2 |
+//! | within a week | rank / 2 |
+//! | earlier | rank / 4 |
+//!
+//! When the sum of ranks exceeds `MAX_TOTAL_RANK`, all ranks are
+//! multiplied by 0.9 and directories with rank below 1 are forgotten.
+//!
+//! File has one directory per line: `rank<TAB>time<TAB>path`.
+
+use std::env::var;
+use std::error::Error;
+use std::fs::read_to_string;
+use std::io::{self, ErrorKind, Read};
+use std::path::Path;
+use crate::builtins::change_directory;
+use crate::config::config;
+use crate::directories::working_directory;
+use crate::execute::ExecutionResult;
+use crate::history::now;
+use crate::picker::pick_item;
+use crate::utils::{error_log, open_locked, rewrite_file};
+
+const MAX_TOTAL_RANK: f64 = 9000.0;
+
+struct Directory {
+    path: String,
+    rank: f64,
+    /// Time of the last visit, seconds since the epoch
+    time: u64
+}
+
+impl Directory {
+    fn parse(line: &str) -> Option<Directory> {
+        let mut fields = line.splitn(3, '\t');
+
+        let rank = fields.next()?.parse().ok()?;
+        let time = fields.next()?.parse().ok()?;
+        let path = fields.next()?.to_string();
+
+        Some(Directory { path, rank, time })
+    }
+
+    fn to_line(&self) -> String {
+        format!("{}\t{}\t{}\n", self.rank, self.time, self.path)
+    }
+
+    fn score(&self, now: u64) -> f64 {
+        match now.saturating_sub(self.time) {
+            0..=3599 => self.rank * 4.0,
+            3600..=86399 => self.rank * 2.0,
+            86400..=604799 => self.rank / 2.0,
+            _ => self.rank / 4.0
+        }
+    }
+}
+
+/// Records the visit of the directory
+///
+/// Home directory is not recorded, since `cd` without
+/// arguments is shorter than any jump
+pub fn record_directory(directory: &Path) {
+    if var("HOME").is_ok_and(|home| Path::new(&home) == directory) {
+        return;
+    }
+
+    let path = directory.display().to_string();
+
+    let updated = update(|directories| {
+        match directories.iter_mut().find(|entry| entry.path == path) {
+            Some(entry) => {
+                entry.rank += 1.0;
+                entry.time = now();
+            },
+            None => directories.push(Directory { path, rank: 1.0, time: now() })
+        }
+
+        if directories.iter().map(|entry| entry.rank).sum::<f64>() > MAX_TOTAL_RANK {
+            for entry in directories.iter_mut() {
+                entry.rank *= 0.9;
+            }
+
+            directories.retain(|entry| entry.rank >= 1.0);
+        }
+    });
+
+    if let Err(err) = updated {
+        error_log(Box::<dyn Error>::from(format!("{}: {err}", config().directories_path)));
+    }
+}
+
+fn load() -> io::Result<Vec<Directory>> {
+    match read_to_string(&config().directories_path) {
+        Ok(content) => Ok(content.lines().filter_map(Directory::parse).collect()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err)
+    }
+}
+
+/// Reads the locked database, changes and writes it back
+fn update<T>(change: impl FnOnce(&mut Vec<Directory>) -> T) -> io::Result<T> {
+    let mut file = open_locked(&config().directories_path)?;
+    let mut content = String::new();
+
+    file.read_to_string(&mut content)?;
+
+    let mut directories: Vec<Directory> = content.lines().filter_map(Directory::parse).collect();
+    let result = change(&mut directories);
+
+    let content: String = directories.iter().map(Directory::to_line).collect();
+    rewrite_file(&mut file, &content)?;
+
+    Ok(result)
+}
+
+/// Whether the words appear in the path in the same order, and
+/// the last word is in the last component of the path. Words
+/// without uppercase letters match case-insensitively
+fn matches(path: &str, words: &[String]) -> bool {
+    let ignore_case = !words.iter().any(|word| word.chars().any(char::is_uppercase));
+    let normalize = |text: &str| if ignore_case { text.to_lowercase() } else { text.to_string() };
+
+    let path = normalize(path);
+    let mut rest = path.as_str();
+
+    for word in words.iter().map(|word| normalize(word)) {
+        match rest.find(&word) {
+            Some(index) => rest = &rest[index + word.len()..],
+            None => return false
+        }
+    }
+
+    let basename = path.rsplit('/').next().unwrap_or_default();
+
+    words.last().is_none_or(|last| basename.contains(&normalize(last)))
+}
+
+/// Implementation of shell built-in `z` (and `j`) function
+///
+/// Changes to the directory with the highest score that matches
+/// the words, e.g. `z proj rsh` jumps to `~/projects/rshell`.
+/// If several directories have close scores and the shell runs
+/// in a terminal, the picker is opened to choose one of them.
+/// Options:
+///
+/// * `-l` lists matching directories with their scores
+/// * `-i` always opens the picker
+/// * `-x` forgets the working directory
+/// * `--prune` forgets directories that don't exist anymore
+pub fn jump(name: &str, args: &[String]) -> ExecutionResult {
+    let current = working_directory().display().to_string();
+
+    let mut list = false;
+    let mut interactive = false;
+    let mut words = Vec::new();
+
+    for arg in args {
+        match arg.as_str() {
+            "-l" => list = true,
+            "-i" => interactive = true,
+            "-x" => return forget(name, |entry| entry.path == current),
+            "--prune" => return forget(name, |entry| !Path::new(&entry.path).is_dir()),
+            option if option.starts_with('-') => {
+                return ExecutionResult::Error(Box::<dyn Error>::from(format!("{name}: invalid option: {option}")));
+            },
+            word => words.push(word.to_string())
+        }
+    }
+
+    let directories = match load() {
+        Ok(directories) => directories,
+        Err(err) => return ExecutionResult::Error(Box::<dyn Error>::from(format!("{name}: {err}")))
+    };
+
+    let now = now();
+
+    let mut matched: Vec<(f64, String)> = directories.into_iter()
+        .filter(|entry| entry.path != current && matches(&entry.path, &words) && Path::new(&entry.path).is_dir())
+        .map(|entry| (entry.score(now), entry.path))
+        .collect();
+
+    matched.sort_by(|(first, _), (second, _)| second.total_cmp(first));
+
+    if list || words.is_empty() {
+        for (score, path) in matched {
+            println!("{score:>10.1}  {path}");
+        }
+
+        return ExecutionResult::Success;
+    }
+
+    let terminal = unsafe { libc::isatty(libc::STDIN_FILENO) == 1 && libc::isatty(libc::STDOUT_FILENO) == 1 };
+
+    // the best match is ambiguous if the next one is at least half as good
+    let ambiguous = matched.len() > 1 && matched[1].0 * 2.0 >= matched[0].0;
+
+    let directory = match matched.first() {
+        None => return ExecutionResult::Error(Box::<dyn Error>::from(format!("{name}: no match for `{}`", words.join(" ")))),
+        Some(_) if terminal && (interactive || ambiguous) => {
+            match pick_item(matched.into_iter().map(|(_, path)| path).collect()) {
+                Some(directory) => directory,
+                None => return ExecutionResult::Failure(1)
+            }
+        },
+        Some((_, path)) => path.clone()
+    };
+
+    change_directory(&[String::from("--"), directory])
+}
+
+/// Removes directories from the database and prints their number
+fn forget(name: &str, predicate: impl Fn(&Directory) -> bool) -> ExecutionResult {
+    match update(|directories| {
+        let length = directories.len();
+        directories.retain(|entry| !predicate(entry));
+        length - directories.len()
+    }) {
+        Ok(removed) => {
+            println!("{name}: removed {removed} directories");
+            ExecutionResult::Success
+        },
+        Err(err) => ExecutionResult::Error(Box::<dyn Error>::from(format!("{name}: {err}")))
+    }
+}
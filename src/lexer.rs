@@ -0,0 +1,283 @@
+//! Lexer of the shell language
+//!
+//! Splits a line into words and operators. It is used
+//! for execution as well as for the line editor, so the
+//! editor always sees the line the same way as the shell.
+//!
+//! Words are separated with whitespace and operators.
+//! Special characters can be escaped with `\` or put
+//! into double quotes: `echo "a | b" c\ d`. Word that
+//! starts with `'` is a literal string and lasts until
+//! the whitespace.
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TokenKind {
+    Word,
+    /// `|`
+    Pipe,
+    /// `&&`
+    And,
+    /// `;` or new line
+    Separator,
+    /// `&` at the end of the pipeline
+    Background,
+    /// `>`
+    Write,
+    /// `>>`
+    Append,
+    /// `#` and the rest of the line
+    Comment
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Token<'a> {
+    pub kind: TokenKind,
+    pub text: &'a str,
+    /// Byte offset of the token in the line
+    pub start: usize
+}
+
+impl Token<'_> {
+    pub fn end(&self) -> usize {
+        self.start + self.text.len()
+    }
+
+    /// Whether the token separates commands (`|`, `&&`, `;`, `&`)
+    pub fn is_command_separator(&self) -> bool {
+        matches!(self.kind, TokenKind::Pipe | TokenKind::And | TokenKind::Separator | TokenKind::Background)
+    }
+}
+
+pub fn tokenize(line: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let bytes = line.as_bytes();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let start = position;
+
+        let kind = match bytes[position] {
+            b'\n' | b';' => {
+                position += 1;
+                TokenKind::Separator
+            },
+            byte if byte.is_ascii_whitespace() => {
+                position += 1;
+                continue;
+            },
+            b'|' => {
+                position += 1;
+                TokenKind::Pipe
+            },
+            b'&' if bytes.get(position + 1) == Some(&b'&') => {
+                position += 2;
+                TokenKind::And
+            },
+            b'&' if is_word_end(bytes, position + 1) => {
+                position += 1;
+                TokenKind::Background
+            },
+            b'>' if bytes.get(position + 1) == Some(&b'>') => {
+                position += 2;
+                TokenKind::Append
+            },
+            b'>' => {
+                position += 1;
+                TokenKind::Write
+            },
+            b'#' => {
+                position = line[position..].find('\n').map_or(bytes.len(), |end| position + end);
+                TokenKind::Comment
+            },
+            b'\'' => {
+                position = line[position..].find(char::is_whitespace).map_or(bytes.len(), |end| position + end);
+                TokenKind::Word
+            },
+            _ => {
+                position = word_end(bytes, position);
+                TokenKind::Word
+            }
+        };
+
+        tokens.push(Token { kind, text: &line[start..position], start });
+    }
+
+    tokens
+}
+
+/// Whether the word ends at the position
+fn is_word_end(bytes: &[u8], position: usize) -> bool {
+    match bytes.get(position) {
+        None => true,
+        Some(byte) => byte.is_ascii_whitespace() || matches!(byte, b'|' | b';' | b'>')
+    }
+}
+
+fn word_end(bytes: &[u8], mut position: usize) -> usize {
+    while position < bytes.len() {
+        match bytes[position] {
+            b'\\' => position += 2,
+            b'"' => {
+                position += 1;
+
+                while position < bytes.len() && bytes[position] != b'"' {
+                    position += if bytes[position] == b'\\' { 2 } else { 1 };
+                }
+
+                position += 1;
+            },
+            b'&' if bytes.get(position + 1) == Some(&b'&') || is_word_end(bytes, position + 1) => break,
+            _ if is_word_end(bytes, position) => break,
+            _ => position += 1
+        }
+    }
+
+    position.min(bytes.len())
+}
+
+/// Whether the word has unclosed double quote or ends with `\`
+pub fn is_unterminated(word: &str) -> bool {
+    if word.starts_with('\'') {
+        return false;
+    }
+
+    let mut chars = word.chars();
+    let mut quoted = false;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' if chars.next().is_none() => return true,
+            '\\' => {},
+            '"' => quoted = !quoted,
+            _ => {}
+        }
+    }
+
+    quoted
+}
+
+/// Removes quotes and escape characters from the word
+///
+/// Inside double quotes only `\"` and `\\` are escapes.
+/// Escaped new line outside of quotes continues the line
+pub fn unquote(word: &str) -> String {
+    let mut unquoted = String::new();
+    let mut chars = word.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(char) = chars.next() {
+        match char {
+            '"' => quoted = !quoted,
+            '\\' if quoted => match chars.next_if(|next| *next == '"' || *next == '\\') {
+                Some(escaped) => unquoted.push(escaped),
+                None => unquoted.push('\\')
+            },
+            '\\' => unquoted.extend(chars.next().filter(|escaped| *escaped != '\n')),
+            char => unquoted.push(char)
+        }
+    }
+
+    unquoted
+}
+
+/// Escapes characters that have special meaning in the shell
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+
+    for (index, char) in text.chars().enumerate() {
+        let special = char.is_whitespace()
+            || matches!(char, '\\' | '"' | '|' | '&' | ';' | '>')
+            || (index == 0 && matches!(char, '\'' | '#' | '$' | '@' | '~'));
+
+        if special {
+            escaped.push('\\');
+        }
+
+        escaped.push(char);
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use TokenKind::*;
+
+    fn kinds(line: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(line).into_iter().map(|token| (token.kind, token.text)).collect()
+    }
+
+    #[test]
+    fn splits_words_and_operators() {
+        assert_eq!(kinds("ls -la | grep x && echo ok; pwd"), [
+            (Word, "ls"), (Word, "-la"), (Pipe, "|"), (Word, "grep"), (Word, "x"),
+            (And, "&&"), (Word, "echo"), (Word, "ok"), (Separator, ";"), (Word, "pwd")
+        ]);
+
+        assert_eq!(kinds("a|b>c>>d"), [
+            (Word, "a"), (Pipe, "|"), (Word, "b"), (Write, ">"), (Word, "c"), (Append, ">>"), (Word, "d")
+        ]);
+
+        assert_eq!(kinds("a\nb"), [(Word, "a"), (Separator, "\n"), (Word, "b")]);
+        assert!(tokenize("   ").is_empty());
+    }
+
+    #[test]
+    fn background_only_at_word_end() {
+        assert_eq!(kinds("sleep 1 &"), [(Word, "sleep"), (Word, "1"), (Background, "&")]);
+        assert_eq!(kinds("sleep 1&; x"), [(Word, "sleep"), (Word, "1"), (Background, "&"), (Separator, ";"), (Word, "x")]);
+        assert_eq!(kinds("echo &name a&b"), [(Word, "echo"), (Word, "&name"), (Word, "a&b")]);
+    }
+
+    #[test]
+    fn quotes_and_escapes_are_part_of_words() {
+        assert_eq!(kinds(r#"echo "a | b" c\ d e\;f"#), [(Word, "echo"), (Word, r#""a | b""#), (Word, r"c\ d"), (Word, r"e\;f")]);
+        assert_eq!(kinds(r#"echo "say \"hi\"""#), [(Word, "echo"), (Word, r#""say \"hi\"""#)]);
+        assert_eq!(kinds("echo 'a|b c"), [(Word, "echo"), (Word, "'a|b"), (Word, "c")]);
+        assert_eq!(kinds("echo é\\ü ö"), [(Word, "echo"), (Word, "é\\ü"), (Word, "ö")]);
+    }
+
+    #[test]
+    fn comments_last_until_end_of_line() {
+        assert_eq!(kinds("a # b | c\nd"), [(Word, "a"), (Comment, "# b | c"), (Separator, "\n"), (Word, "d")]);
+        assert_eq!(kinds("a#b"), [(Word, "a#b")]);
+    }
+
+    #[test]
+    fn tokens_have_offsets() {
+        let tokens = tokenize("ab  | cd");
+
+        assert_eq!(tokens.iter().map(|token| (token.start, token.end())).collect::<Vec<_>>(), [(0, 2), (4, 5), (6, 8)]);
+        assert!(tokens[1].is_command_separator());
+        assert!(!tokens[0].is_command_separator());
+    }
+
+    #[test]
+    fn unterminated_words() {
+        assert!(is_unterminated("\"abc"));
+        assert!(is_unterminated("abc\\"));
+        assert!(!is_unterminated("\"abc\""));
+        assert!(!is_unterminated("a\\\"b"));
+        assert!(!is_unterminated("'\"abc"));
+    }
+
+    #[test]
+    fn unquote_removes_quotes_and_escapes() {
+        assert_eq!(unquote(r#""a | b""#), "a | b");
+        assert_eq!(unquote(r"c\ d"), "c d");
+        assert_eq!(unquote(r#""\"x\" \\ \n""#), r#""x" \ \n"#);
+        assert_eq!(unquote("a\\\nb"), "ab");
+    }
+
+    #[test]
+    fn escape_round_trips_through_lexer() {
+        for text in ["my dir", "a|b&c;d>e", "#tag", "$HOME", "~user", "say \"hi\"", "back\\slash"] {
+            let escaped = escape(text);
+            let tokens = tokenize(&escaped);
+
+            assert_eq!(tokens.len(), 1, "{escaped}");
+            assert_eq!(unquote(tokens[0].text), text);
+        }
+    }
+}
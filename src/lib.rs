@@ -0,0 +1,59 @@
+//! UNIX shell in Rust
+//!
+//! The library contains the interpreter of the `rsh` binary,
+//! so programs can use rsh as their scripting language:
+//!
+//! ```no_run
+//! use rsh::{ExecutionResult, Shell};
+//!
+//! let mut shell = Shell::new();
+//!
+//! shell.register_builtin("greet", |args| {
+//!     println!("hello {}", args.join(" "));
+//!     ExecutionResult::Success
+//! }).unwrap();
+//!
+//! shell.set_var("name", "world");
+//! shell.run_line("greet $name");
+//! ```
+//!
+//! The binary itself is [`run`] called with the arguments
+//! of the process.
+
+mod builtins;
+mod cli;
+mod colors;
+mod completion;
+mod config;
+mod debugger;
+mod diagnostics;
+mod directories;
+mod editor;
+mod execute;
+mod formatter;
+mod git;
+mod git_objects;
+mod highlight;
+mod history;
+mod hooks;
+mod inflate;
+mod instants;
+mod jobs;
+mod json;
+mod jump;
+mod lexer;
+mod lint;
+mod lsp;
+mod not_found;
+mod options;
+mod parse_command;
+mod picker;
+mod prompt;
+mod repl;
+mod shell;
+mod signals;
+mod utils;
+
+pub use execute::ExecutionResult;
+pub use repl::run;
+pub use shell::Shell;
@@ -0,0 +1,511 @@
+//! Static checks of scripts
+//!
+//! `rsh --check file.rsh` (or `rsh -n`) parses scripts without
+//! executing them and reports problems it can find:
+//!
+//! | code | severity | problem |
+//! |:----|:----|:----|
+//! | `syntax` | error | syntax error, e.g. unclosed `if` |
+//! | `undefined-command` | warning | command that is not a builtin, alias, function of the script or executable in `PATH` |
+//! | `unset-variable` | warning | variable that is set neither in the script nor in the environment, only with `--check-env` |
+//! | `unreachable` | warning | commands after `exit` |
+//! | `single-quotes` | warning | `'text'`, where the closing quote is a part of the literal word |
+//! | `quoted-variable` | warning | `$name` inside a word or quotes, which is not expanded |
+//! | `command-substitution` | warning | `$(...)` or backticks, which are not supported |
+//! | `set-spaces` | warning | spaces around `=` in `set name = value` |
+//!
+//! Problems are printed as `file:line:column: severity: message [code]`,
+//! or as JSON array with `--json`.
+//!
+//! Whether a variable is set depends on the environment the
+//! checker runs in, so `unset-variable` is reported only with
+//! `--check-env`, and the result is the same on all machines
+//! otherwise.
+
+use std::collections::BTreeSet;
+use std::env::var_os;
+use std::error::Error;
+use std::fs::read_to_string;
+use std::io::{self, Read};
+use crate::builtins::is_builtin;
+use crate::cli::Input;
+use crate::instants::is_instant;
+use crate::json::quote;
+use crate::lexer::{Token, TokenKind, tokenize, unquote};
+use crate::parse_command::{Node, NodeKind, Span, parse_command};
+use crate::utils::{error_log, find_executable};
+
+/// Variables that the shell sets itself
+const SHELL_VARIABLES: [&str; 13] = ["?", "#", "@", "PWD", "OLDPWD", "SHLVL", "PS1", "PS2", "PS4", "RPROMPT", "CMD_DURATION", "CDPATH", "profile"];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning"
+        }
+    }
+}
+
+pub struct Diagnostic {
+    pub span: Span,
+    pub severity: Severity,
+    pub code: &'static str,
+    pub message: String
+}
+
+/// Finds problems in the script, with `check_env` also
+/// variables that are not set in the environment
+pub fn lint(source: &str, check_env: bool) -> Vec<Diagnostic> {
+    let nodes = match parse_command(source) {
+        Ok(nodes) => nodes,
+        Err(err) => return vec![Diagnostic {
+            span: Span { start: err.position, end: err.position },
+            severity: Severity::Error,
+            code: "syntax",
+            message: err.message
+        }]
+    };
+
+    let mut linter = Linter {
+        source,
+        diagnostics: Vec::new(),
+        functions: BTreeSet::new(),
+        aliases: BTreeSet::new(),
+        variables: BTreeSet::new(),
+        commands: Vec::new(),
+        uses: Vec::new()
+    };
+
+    linter.nodes(&nodes, 0);
+    linter.check_names(check_env);
+
+    let mut diagnostics = linter.diagnostics;
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.start);
+
+    diagnostics
+}
+
+/// Line and column of the offset, both counted from 1
+pub fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+    (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1)
+}
+
+struct Linter<'a> {
+    source: &'a str,
+    diagnostics: Vec<Diagnostic>,
+    functions: BTreeSet<String>,
+    aliases: BTreeSet<String>,
+    variables: BTreeSet<String>,
+    /// Command words, checked when all functions are known
+    commands: Vec<(String, Span)>,
+    /// Variable references, checked when all assignments are known
+    uses: Vec<(String, Span)>
+}
+
+impl Linter<'_> {
+    fn warn(&mut self, span: Span, code: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic { span, severity: Severity::Warning, code, message });
+    }
+
+    /// Checks the commands, spans of the nodes start at `base`
+    fn nodes(&mut self, nodes: &[Node], base: usize) {
+        let mut exited = false;
+        let mut reported = false;
+
+        for node in nodes {
+            // only the first unreachable command of the block is reported
+            if exited && !reported {
+                let span = Span { start: base + node.span.start, end: base + node.span.end };
+
+                self.warn(span, "unreachable", String::from("command is never executed, since the shell exits before it"));
+                reported = true;
+            }
+
+            self.node(node, base);
+
+            exited = exited || self.is_exit(node, base);
+        }
+    }
+
+    fn node(&mut self, node: &Node, base: usize) {
+        match &node.kind {
+            NodeKind::Chain(pipelines) => {
+                for span in pipelines {
+                    self.pipeline(Span { start: base + span.start, end: base + span.end });
+                }
+            },
+            NodeKind::Function { name, body } => {
+                self.functions.insert(name.clone());
+
+                if let Ok(nodes) = parse_command(&self.source[base + body.start..base + body.end]) {
+                    self.nodes(&nodes, base + body.start);
+                }
+            },
+            NodeKind::If { branches, otherwise } => {
+                for (condition, body) in branches {
+                    self.nodes(condition, base);
+                    self.nodes(body, base);
+                }
+
+                if let Some(body) = otherwise {
+                    self.nodes(body, base);
+                }
+            },
+            NodeKind::While { condition, body } => {
+                self.nodes(condition, base);
+                self.nodes(body, base);
+            },
+            NodeKind::For { variable, body, .. } => {
+                self.variables.insert(variable.clone());
+
+                // words after `in` until the end of the line
+                let start = base + node.span.start;
+                let header: Vec<Token> = tokenize(&self.source[start..base + node.span.end]).into_iter()
+                    .skip(3)
+                    .take_while(|token| token.kind == TokenKind::Word)
+                    .collect();
+
+                for token in header {
+                    self.word(token.text, Span { start: start + token.start, end: start + token.end() });
+                }
+
+                self.nodes(body, base);
+            }
+        }
+    }
+
+    /// Whether the node is `exit` that is not a part of `&&` chain
+    fn is_exit(&self, node: &Node, base: usize) -> bool {
+        let NodeKind::Chain(pipelines) = &node.kind else {
+            return false;
+        };
+
+        let [pipeline] = pipelines.as_slice() else {
+            return false;
+        };
+
+        let tokens = tokenize(&self.source[base + pipeline.start..base + pipeline.end]);
+
+        tokens.first().is_some_and(|token| token.text == "exit")
+            && !tokens.iter().any(|token| matches!(token.kind, TokenKind::Pipe | TokenKind::Background))
+    }
+
+    fn pipeline(&mut self, span: Span) {
+        let mut stages: Vec<Vec<(&str, Span)>> = vec![Vec::new()];
+
+        for token in tokenize(&self.source[span.start..span.end]) {
+            let token_span = Span { start: span.start + token.start, end: span.start + token.end() };
+
+            match token.kind {
+                TokenKind::Word => stages.last_mut().unwrap().push((token.text, token_span)),
+                TokenKind::Pipe => stages.push(Vec::new()),
+                _ => {}
+            }
+        }
+
+        for mut words in stages {
+            if words.first().is_some_and(|(word, _)| *word == "time") {
+                words.remove(0);
+            }
+
+            let Some(&(command, command_span)) = words.first() else {
+                continue;
+            };
+
+            match command {
+                // body of the inline function is checked when it is called
+                "fn" => {
+                    self.functions.extend(words.get(1).map(|(name, _)| name.to_string()));
+                    continue;
+                },
+                "alias" => {
+                    let definition: String = words[1..].iter().map(|(word, _)| *word).collect();
+                    self.aliases.extend(definition.split('=').next().filter(|name| !name.is_empty()).map(str::to_string));
+                },
+                "set" => self.assignment(&words[1..], command_span),
+                _ => self.commands.push((command.to_string(), command_span))
+            }
+
+            self.single_quotes(&words);
+
+            for (index, (word, span)) in words.into_iter().enumerate() {
+                match word.split_once('=') {
+                    // name and value of `set name=value` are separate words
+                    Some((name, value)) if command == "set" && index == 1 => {
+                        let equals = span.start + name.len();
+
+                        self.word(name, Span { start: span.start, end: equals });
+                        self.word(value, Span { start: equals + 1, end: span.end });
+                    },
+                    _ => self.word(word, span)
+                }
+            }
+        }
+    }
+
+    /// Records the variable assigned with `set`
+    fn assignment(&mut self, args: &[(&str, Span)], span: Span) {
+        let Some(&(first, _)) = args.first().filter(|(first, _)| !first.starts_with(['-', '+'])) else {
+            return;
+        };
+
+        let name = first.split('=').next().unwrap_or_default();
+
+        if !name.starts_with('$') {
+            self.variables.insert(name.to_string());
+        }
+
+        let spaced = args.len() > 1 && (first.ends_with('=') || args[1].0.starts_with('='));
+
+        if spaced {
+            let end = args.last().map_or(span.end, |(_, last)| last.end);
+
+            self.warn(
+                Span { start: span.start, end },
+                "set-spaces",
+                String::from("`set` takes a single `name=value` word, remove spaces around `=`")
+            );
+        }
+    }
+
+    /// Reports `'text'` and `'several words'`, where single
+    /// quotes are used as in other shells
+    fn single_quotes(&mut self, words: &[(&str, Span)]) {
+        for (index, (word, span)) in words.iter().enumerate() {
+            if !word.starts_with('\'') {
+                continue;
+            }
+
+            let closing = words[index..].iter().enumerate()
+                .find(|(offset, (word, _))| word.ends_with('\'') && (*offset > 0 || word.len() > 1));
+
+            if let Some((_, (_, closing))) = closing {
+                self.warn(Span { start: span.start, end: closing.end }, "single-quotes", String::from(
+                    "single quote only starts a literal word and lasts until a space, so the closing `'` is kept; use double quotes"
+                ));
+
+                return;
+            }
+        }
+    }
+
+    fn word(&mut self, word: &str, span: Span) {
+        if word.starts_with('\'') {
+            return;
+        }
+
+        if word.contains("$(") || word.contains('`') {
+            self.warn(span, "command-substitution", String::from("command substitution is not supported"));
+            return;
+        }
+
+        if word.starts_with('$') && word.len() > 1 {
+            self.uses.push((word.replace('$', ""), span));
+            return;
+        }
+
+        let mut chars = word.chars().peekable();
+
+        while let Some(char) = chars.next() {
+            match char {
+                '\\' => {
+                    chars.next();
+                },
+                '$' if chars.peek().is_some_and(|next| next.is_alphanumeric() || matches!(next, '_' | '?' | '#' | '@')) => {
+                    self.warn(span, "quoted-variable", String::from(
+                        "variable inside a word or quotes is not expanded, only `$name` as a whole word is"
+                    ));
+
+                    return;
+                },
+                _ => {}
+            }
+        }
+    }
+
+    /// Reports undefined commands, and unset variables with `check_env`
+    fn check_names(&mut self, check_env: bool) {
+        for (word, span) in std::mem::take(&mut self.commands) {
+            // commands from variables and paths are known only at runtime
+            if word.contains(['$', '/']) {
+                continue;
+            }
+
+            let command = unquote(word.strip_prefix('\'').unwrap_or(&word));
+
+            let defined = is_builtin(&command)
+                || is_instant(&command)
+                || self.functions.contains(&command)
+                || self.aliases.contains(&command)
+                || find_executable(&command).is_some();
+
+            if !defined {
+                self.warn(span, "undefined-command", format!(
+                    "`{command}` is not a builtin, alias, function of the script or executable in PATH"
+                ));
+            }
+        }
+
+        if !check_env {
+            return;
+        }
+
+        for (name, span) in std::mem::take(&mut self.uses) {
+            let set = self.variables.contains(&name)
+                || SHELL_VARIABLES.contains(&name.as_str())
+                || name.chars().all(|char| char.is_ascii_digit())
+                || var_os(&name).is_some();
+
+            if !set {
+                self.warn(span, "unset-variable", format!("variable `{name}` is set neither in the script nor in the environment"));
+            }
+        }
+    }
+}
+
+/// Checks the scripts and prints their problems
+///
+/// Returns the exit status: 0 if there are no errors, 1 if there
+/// are errors (or warnings with `strict`), and 2 if a script can't be read
+pub fn check_scripts(inputs: Vec<Input>, json: bool, strict: bool, check_env: bool) -> i32 {
+    let mut status = 0;
+    let mut reports = Vec::new();
+
+    for input in inputs {
+        let (file, source) = match input {
+            Input::Command(command) => (String::from("-c"), Ok(command)),
+            Input::File(path) => {
+                let source = read_to_string(&path);
+                (path, source)
+            },
+            Input::Stdin => {
+                let mut source = String::new();
+                let read = io::stdin().read_to_string(&mut source).map(|_| source);
+                (String::from("stdin"), read)
+            }
+        };
+
+        let source = match source {
+            Ok(source) => source,
+            Err(err) => {
+                error_log(Box::<dyn Error>::from(format!("{file}: {err}")));
+                status = 2;
+                continue;
+            }
+        };
+
+        for diagnostic in lint(&source, check_env) {
+            let (line, column) = line_column(&source, diagnostic.span.start);
+            let (end_line, end_column) = line_column(&source, diagnostic.span.end);
+
+            if status == 0 && (strict || diagnostic.severity == Severity::Error) {
+                status = 1;
+            }
+
+            if json {
+                reports.push(format!(
+                    "  {{\"file\": {}, \"line\": {line}, \"column\": {column}, \"end_line\": {end_line}, \"end_column\": {end_column}, \"severity\": \"{}\", \"code\": \"{}\", \"message\": {}}}",
+                    quote(&file), diagnostic.severity.as_str(), diagnostic.code, quote(&diagnostic.message)
+                ));
+            } else {
+                println!("{file}:{line}:{column}: {}: {} [{}]", diagnostic.severity.as_str(), diagnostic.message, diagnostic.code);
+            }
+        }
+    }
+
+    if json {
+        match reports.is_empty() {
+            true => println!("[]"),
+            false => println!("[\n{}\n]", reports.join(",\n"))
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Codes of the problems with the text they point to
+    fn codes(source: &str) -> Vec<(&'static str, &str)> {
+        codes_with_env(source, false)
+    }
+
+    fn codes_with_env(source: &str, check_env: bool) -> Vec<(&'static str, &str)> {
+        lint(source, check_env).into_iter()
+            .map(|diagnostic| (diagnostic.code, &source[diagnostic.span.start..diagnostic.span.end]))
+            .collect()
+    }
+
+    #[test]
+    fn clean_script() {
+        let source = "fn greet {\n    cd &dir\n}\nset name=world\nalias ll=greet\nfor x in a b; do greet $x; done\nll $name\n";
+
+        assert!(lint(source, true).is_empty());
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let diagnostics = lint("cd a\nif true; then cd b", false);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].code, "syntax");
+        assert!(diagnostics[0].severity == Severity::Error);
+        assert_eq!(line_column("cd a\nif true; then cd b", diagnostics[0].span.start), (2, 1));
+    }
+
+    #[test]
+    fn undefined_commands() {
+        assert_eq!(codes("rsh-undefined-command a | cd"), [("undefined-command", "rsh-undefined-command")]);
+        assert_eq!(codes("time rsh-undefined-command"), [("undefined-command", "rsh-undefined-command")]);
+
+        // functions are known in the whole script, paths and variables only at runtime
+        assert!(codes("f\nfn f cd a\n./run.sh\n$command").is_empty());
+    }
+
+    #[test]
+    fn unset_variables_only_with_env() {
+        let source = "cd $RSH_LINT_UNSET_VARIABLE $PWD $1";
+
+        assert!(codes(source).is_empty());
+        assert_eq!(codes_with_env(source, true), [("unset-variable", "$RSH_LINT_UNSET_VARIABLE")]);
+        assert!(codes_with_env("set RSH_LINT_UNSET_VARIABLE=1\ncd $RSH_LINT_UNSET_VARIABLE", true).is_empty());
+    }
+
+    #[test]
+    fn unreachable_after_exit() {
+        assert_eq!(codes("cd a\nexit 1\ncd b\ncd c"), [("unreachable", "cd b")]);
+        assert!(codes("cd a && exit 1\ncd b").is_empty());
+        assert_eq!(codes("if true; then exit; cd x; fi\ncd y"), [("unreachable", "cd x")]);
+    }
+
+    #[test]
+    fn pitfalls() {
+        assert_eq!(codes("cd 'my dir'"), [("single-quotes", "'my dir'")]);
+        assert_eq!(codes("cd \"$HOME/src\""), [("quoted-variable", "\"$HOME/src\"")]);
+        assert_eq!(codes("cd $(pwd)"), [("command-substitution", "$(pwd)")]);
+        assert_eq!(codes("set a = 1"), [("set-spaces", "set a = 1")]);
+        assert!(codes("cd 'literal \\$x").is_empty());
+    }
+
+    #[test]
+    fn problems_are_sorted_by_position() {
+        let positions: Vec<usize> = lint("cd $(a)\nrsh-undefined-command\ncd 'x y'", false).iter()
+            .map(|diagnostic| diagnostic.span.start)
+            .collect();
+
+        assert_eq!(positions.len(), 3);
+        assert!(positions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,539 @@
+//! Language server
+//!
+//! `rsh lsp` speaks the Language Server Protocol over the
+//! standard input and output, so editors get for `.rsh` files:
+//!
+//! * diagnostics of the parser and the linter (see `rsh --check`),
+//!   updated when the document changes
+//! * go to definition and hover of functions and aliases
+//!   defined in the document, hover of builtins and instants
+//! * completion of builtins, instants and their flags, and
+//!   functions and aliases of the document
+//! * functions and aliases as document symbols
+//! * formatting with the formatter of `rsh fmt`, indented as
+//!   the `tabSize` and `insertSpaces` options of the editor say
+//!
+//! Documents are synchronized in full on every change. Positions
+//! are counted in UTF-16 code units, as the protocol requires.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{self, BufRead, Write};
+use crate::builtins::BUILTINS;
+use crate::completion::{self, command_flags};
+use crate::formatter::format_script_with_indent;
+use crate::instants::INSTANTS;
+use crate::json::Json;
+use crate::lexer::{Token, TokenKind, tokenize};
+use crate::lint::{Severity, lint};
+use crate::parse_command::{COMMAND_KEYWORDS, Span};
+use crate::utils::error_log;
+
+/// Descriptions of builtins and instants, shown on hover and completion
+const DESCRIPTIONS: [(&str, &str); 18] = [
+    ("alias", "alias name=value - set an alias"),
+    ("cd", "cd [-L|-P] [directory] - change directory"),
+    ("complete", "complete command [-s subcommand] [-w words] [-o options] [-F function] - register completion of a command"),
+    ("dirs", "dirs [-c|-l|-p|-v] - show the directory stack"),
+    ("exit", "exit [status] - exit the shell with the given status"),
+    ("fn", "fn name { body } - declare a function"),
+    ("history", "history [-l] [-s text|-d number...|-c] - list, search and edit the command history"),
+    ("hook", "hook [-d] event function - call a function on events of the shell"),
+    ("j", "j [-i|-l|-x|--prune] words... - jump to a frequently used directory"),
+    ("jobs", "jobs - list background jobs"),
+    ("popd", "popd [+N|-N] - remove a directory from the stack"),
+    ("pushd", "pushd [directory|+N|-N] - change directory and push it to the stack"),
+    ("set", "set name=value, set [-eufxCv] [-o option] - set a variable or shell options"),
+    ("time", "time pipeline - measure execution time of a pipeline"),
+    ("trap", "trap [code] signal... - run code when the shell gets a signal or exits"),
+    ("z", "z [-i|-l|-x|--prune] words... - jump to a frequently used directory"),
+    ("@exec", "@exec file [--noexit] - execute the file in the current shell"),
+    ("@format", "@format [--color...] text - print formatted text")
+];
+
+/// Kinds of completion items and symbols of the protocol
+const FUNCTION_KIND: usize = 3;
+const KEYWORD_KIND: usize = 14;
+const VALUE_KIND: usize = 12;
+const SYMBOL_FUNCTION: usize = 12;
+const SYMBOL_CONSTANT: usize = 14;
+
+/// Error code of unknown requests
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Function or alias defined in the document
+struct Definition {
+    name: String,
+    alias: bool,
+    /// Whole definition, e.g. from `fn` to `}`
+    span: Span,
+    name_span: Span
+}
+
+struct Server {
+    /// Text of the open documents by URI
+    documents: BTreeMap<String, String>,
+    shutdown: bool
+}
+
+/// Runs the language server until the client exits, returns the exit status
+pub fn run_server() -> i32 {
+    let mut input = io::stdin().lock();
+    let mut server = Server { documents: BTreeMap::new(), shutdown: false };
+
+    loop {
+        let message = match read_message(&mut input) {
+            Ok(Some(message)) => message,
+            Ok(None) => return if server.shutdown { 0 } else { 1 },
+            Err(err) => {
+                error_log(Box::<dyn Error>::from(format!("lsp: {err}")));
+                continue;
+            }
+        };
+
+        let method = message.get("method").as_str().unwrap_or_default();
+        let id = message.get("id");
+
+        if method == "exit" {
+            return if server.shutdown { 0 } else { 1 };
+        }
+
+        let result = server.handle(method, message.get("params"));
+
+        // notifications have no id and get no response
+        if *id == Json::Null {
+            continue;
+        }
+
+        let response = match result {
+            Some(result) => Json::object([("jsonrpc", "2.0".into()), ("id", id.clone()), ("result", result)]),
+            None => Json::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.clone()),
+                ("error", Json::object([
+                    ("code", Json::Number(METHOD_NOT_FOUND as f64)),
+                    ("message", format!("{method}: method not found").into())
+                ]))
+            ])
+        };
+
+        send(&response);
+    }
+}
+
+/// Reads the message with `Content-Length` header,
+/// returns `None` at the end of the input
+fn read_message(input: &mut impl BufRead) -> Result<Option<Json>, Box<dyn Error>> {
+    let mut length = None;
+
+    loop {
+        let mut header = String::new();
+
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+
+        let header = header.trim_end();
+
+        if header.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+
+    let Some(length) = length else {
+        return Err(Box::<dyn Error>::from("message without Content-Length header"));
+    };
+
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+
+    Json::parse(&String::from_utf8(body)?).map(Some)
+}
+
+fn send(message: &Json) {
+    let body = message.to_string();
+    let mut output = io::stdout().lock();
+
+    // client is gone if the output is closed
+    let _ = write!(output, "Content-Length: {}\r\n\r\n{body}", body.len());
+    let _ = output.flush();
+}
+
+impl Server {
+    /// Handles the request or notification, returns
+    /// `None` if the method is not supported
+    fn handle(&mut self, method: &str, params: &Json) -> Option<Json> {
+        let uri = params.get("textDocument").get("uri").as_str().unwrap_or_default().to_string();
+
+        let result = match method {
+            "initialize" => capabilities(),
+            "initialized" | "$/cancelRequest" | "$/setTrace" | "workspace/didChangeConfiguration" => Json::Null,
+            "shutdown" => {
+                self.shutdown = true;
+                Json::Null
+            },
+            "textDocument/didOpen" => {
+                let text = params.get("textDocument").get("text").as_str().unwrap_or_default();
+                self.documents.insert(uri.clone(), text.to_string());
+                self.publish_diagnostics(&uri);
+                Json::Null
+            },
+            "textDocument/didChange" => {
+                // whole text is sent, the last change is the current one
+                if let Some(text) = params.get("contentChanges").as_array().last().and_then(|change| change.get("text").as_str()) {
+                    self.documents.insert(uri.clone(), text.to_string());
+                    self.publish_diagnostics(&uri);
+                }
+
+                Json::Null
+            },
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                self.publish_diagnostics(&uri);
+                Json::Null
+            },
+            "textDocument/didSave" => Json::Null,
+            _ => {
+                let source = self.documents.get(&uri).map_or("", String::as_str);
+                let offset = offset(source, params.get("position"));
+
+                match method {
+                    "textDocument/definition" => definition(&uri, source, offset),
+                    "textDocument/hover" => hover(source, offset),
+                    "textDocument/completion" => completions(source, offset),
+                    "textDocument/documentSymbol" => symbols(source),
+                    "textDocument/formatting" => formatting(source, params.get("options")),
+                    _ => return None
+                }
+            }
+        };
+
+        Some(result)
+    }
+
+    /// Sends problems found by the linter, or no problems
+    /// if the document is closed
+    fn publish_diagnostics(&self, uri: &str) {
+        let diagnostics = match self.documents.get(uri) {
+            Some(source) => lint(source, false).into_iter()
+                .map(|diagnostic| Json::object([
+                    ("range", range(source, diagnostic.span)),
+                    ("severity", match diagnostic.severity {
+                        Severity::Error => 1usize,
+                        Severity::Warning => 2
+                    }.into()),
+                    ("code", diagnostic.code.into()),
+                    ("source", "rsh".into()),
+                    ("message", diagnostic.message.into())
+                ]))
+                .collect(),
+            None => Vec::new()
+        };
+
+        send(&Json::object([
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            ("params", Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]))
+        ]));
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        ("capabilities", Json::object([
+            // full text of the document on every change
+            ("textDocumentSync", Json::object([("openClose", true.into()), ("change", 1usize.into())])),
+            ("definitionProvider", true.into()),
+            ("hoverProvider", true.into()),
+            ("completionProvider", Json::object([("triggerCharacters", vec!["-".into(), "@".into()].into())])),
+            ("documentSymbolProvider", true.into()),
+            ("documentFormattingProvider", true.into())
+        ])),
+        ("serverInfo", Json::object([("name", "rsh".into()), ("version", env!("CARGO_PKG_VERSION").into())]))
+    ])
+}
+
+/// Position of the protocol at the byte offset
+fn position(source: &str, offset: usize) -> Json {
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |index| index + 1);
+
+    Json::object([
+        ("line", before.matches('\n').count().into()),
+        ("character", before[line_start..].encode_utf16().count().into())
+    ])
+}
+
+fn range(source: &str, span: Span) -> Json {
+    Json::object([("start", position(source, span.start)), ("end", position(source, span.end))])
+}
+
+/// Byte offset of the position of the protocol
+fn offset(source: &str, position: &Json) -> usize {
+    let line = position.get("line").as_usize().unwrap_or(0);
+    let character = position.get("character").as_usize().unwrap_or(0);
+
+    let line_start = match line {
+        0 => 0,
+        _ => source.match_indices('\n').nth(line - 1).map_or(source.len(), |(index, _)| index + 1)
+    };
+
+    let mut units = 0;
+
+    for (index, char) in source[line_start..].char_indices() {
+        if units >= character || char == '\n' {
+            return line_start + index;
+        }
+
+        units += char.len_utf16();
+    }
+
+    source.len()
+}
+
+/// Tokens of the document without comments
+fn tokens(source: &str) -> Vec<Token<'_>> {
+    tokenize(source).into_iter().filter(|token| token.kind != TokenKind::Comment).collect()
+}
+
+/// Word under the offset, including the word that ends at it
+fn word_at(source: &str, offset: usize) -> Option<Token<'_>> {
+    tokens(source).into_iter()
+        .find(|token| token.kind == TokenKind::Word && token.start <= offset && offset <= token.end())
+}
+
+/// Functions and aliases defined in the document
+///
+/// Only tokens are used, so definitions are found
+/// while the document is being edited and doesn't parse
+fn definitions(source: &str) -> Vec<Definition> {
+    let tokens = tokens(source);
+    let mut definitions = Vec::new();
+    // functions whose body is not closed yet
+    let mut open: Vec<usize> = Vec::new();
+    let mut command = true;
+    let mut index = 0;
+
+    while index < tokens.len() {
+        let token = tokens[index];
+        index += 1;
+
+        if token.kind != TokenKind::Word {
+            command = token.is_command_separator();
+            continue;
+        }
+
+        if !command {
+            continue;
+        }
+
+        command = COMMAND_KEYWORDS.contains(&token.text);
+
+        let name = tokens.get(index).filter(|name| name.kind == TokenKind::Word);
+
+        match (token.text, name) {
+            ("}", _) if !open.is_empty() => {
+                let definition: &mut Definition = &mut definitions[open.pop().unwrap_or_default()];
+                definition.span.end = token.end();
+            },
+            ("fn", Some(name)) if tokens.get(index + 1).is_some_and(|brace| brace.kind == TokenKind::Word && brace.text == "{") => {
+                open.push(definitions.len());
+                definitions.push(Definition {
+                    name: name.text.to_string(),
+                    alias: false,
+                    span: Span { start: token.start, end: source.len() },
+                    name_span: Span { start: name.start, end: name.end() }
+                });
+
+                index += 2;
+                command = true;
+            },
+            ("fn" | "alias", Some(name)) => {
+                let alias = token.text == "alias";
+                let name_text = if alias { name.text.split('=').next().unwrap_or_default() } else { name.text };
+
+                // inline function and alias last until the end of the command
+                let end = tokens[index..].iter()
+                    .take_while(|next| next.kind != TokenKind::Separator)
+                    .last()
+                    .map_or(name.end(), |last| last.end());
+
+                definitions.push(Definition {
+                    name: name_text.to_string(),
+                    alias,
+                    span: Span { start: token.start, end },
+                    name_span: Span { start: name.start, end: name.start + name_text.len() }
+                });
+            },
+            _ => {}
+        }
+    }
+
+    definitions
+}
+
+fn definition(uri: &str, source: &str, offset: usize) -> Json {
+    let Some(word) = word_at(source, offset) else {
+        return Json::Null;
+    };
+
+    let locations: Vec<Json> = definitions(source).into_iter()
+        .filter(|definition| definition.name == word.text)
+        .map(|definition| Json::object([("uri", uri.into()), ("range", range(source, definition.name_span))]))
+        .collect();
+
+    locations.into()
+}
+
+fn hover(source: &str, offset: usize) -> Json {
+    let Some(word) = word_at(source, offset) else {
+        return Json::Null;
+    };
+
+    let definitions: Vec<Definition> = definitions(source).into_iter()
+        .filter(|definition| definition.name == word.text)
+        .collect();
+
+    let contents = match definitions.last() {
+        Some(definition) => {
+            let code = &source[definition.span.start..definition.span.end];
+            let comments = comments_before(source, definition.span.start);
+
+            format!("```rsh\n{code}\n```{}", if comments.is_empty() { String::new() } else { format!("\n\n{comments}") })
+        },
+        None => match DESCRIPTIONS.iter().find(|(name, _)| *name == word.text) {
+            Some((_, description)) => format!("```rsh\n{description}\n```"),
+            None => return Json::Null
+        }
+    };
+
+    Json::object([
+        ("contents", Json::object([("kind", "markdown".into()), ("value", contents.into())])),
+        ("range", range(source, Span { start: word.start, end: word.end() }))
+    ])
+}
+
+/// Comment lines right above the line of the offset,
+/// which describe the function or alias
+fn comments_before(source: &str, offset: usize) -> String {
+    let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+
+    let mut comments: Vec<&str> = source[..line_start].lines().rev()
+        .map(str::trim)
+        .take_while(|line| line.starts_with('#') && !line.starts_with("#!"))
+        .map(|line| line.trim_start_matches('#').trim())
+        .collect();
+
+    comments.reverse();
+    comments.join("\n")
+}
+
+fn completions(source: &str, offset: usize) -> Json {
+    let tokens = tokenize(&source[..offset]);
+
+    let (prefix, previous) = match tokens.split_last() {
+        Some((last, rest)) if last.kind == TokenKind::Word && last.end() == offset => (last.text, rest),
+        Some((last, _)) if last.kind == TokenKind::Comment => return Json::Array(Vec::new()),
+        _ => ("", &tokens[..])
+    };
+
+    let mut items: Vec<Json> = Vec::new();
+
+    match completion::Position::of(previous) {
+        completion::Position::Command => {
+            let definitions = definitions(source);
+
+            let names = BUILTINS.iter().chain(INSTANTS.iter())
+                .map(|name| (name.to_string(), FUNCTION_KIND, description(name)))
+                .chain(COMMAND_KEYWORDS.iter().map(|keyword| (keyword.to_string(), KEYWORD_KIND, String::new())))
+                .chain(definitions.iter().map(|definition| {
+                    let detail = if definition.alias { "alias" } else { "function" };
+                    (definition.name.clone(), FUNCTION_KIND, detail.to_string())
+                }));
+
+            let mut seen = Vec::new();
+
+            for (name, kind, detail) in names {
+                if name.starts_with(prefix) && !seen.contains(&name) {
+                    items.push(Json::object([("label", name.as_str().into()), ("kind", kind.into()), ("detail", detail.into())]));
+                    seen.push(name);
+                }
+            }
+        },
+        completion::Position::Argument(index) if prefix.starts_with(['-', '+']) => {
+            let command = previous[index].text;
+
+            for flag in command_flags(command).iter().filter(|flag| flag.starts_with(prefix)) {
+                items.push(Json::object([("label", (*flag).into()), ("kind", VALUE_KIND.into()), ("detail", description(command).into())]));
+            }
+        },
+        _ => {}
+    }
+
+    items.into()
+}
+
+fn description(name: &str) -> String {
+    DESCRIPTIONS.iter()
+        .find(|(described, _)| *described == name)
+        .map_or(String::new(), |(_, description)| description.to_string())
+}
+
+fn symbols(source: &str) -> Json {
+    let symbols: Vec<Json> = definitions(source).into_iter()
+        .map(|definition| Json::object([
+            ("name", definition.name.as_str().into()),
+            ("detail", if definition.alias { "alias" } else { "function" }.into()),
+            ("kind", if definition.alias { SYMBOL_CONSTANT } else { SYMBOL_FUNCTION }.into()),
+            ("range", range(source, definition.span)),
+            ("selectionRange", range(source, definition.name_span))
+        ]))
+        .collect();
+
+    symbols.into()
+}
+
+/// Replaces the whole document with the formatted one, no
+/// changes if it is formatted or has syntax errors
+fn formatting(source: &str, options: &Json) -> Json {
+    let unit = match options.get("insertSpaces") {
+        Json::Bool(false) => String::from("\t"),
+        _ => " ".repeat(options.get("tabSize").as_usize().unwrap_or(4))
+    };
+
+    match format_script_with_indent(source, &unit) {
+        Ok(formatted) if formatted != source => vec![Json::object([
+            ("range", range(source, Span { start: 0, end: source.len() })),
+            ("newText", formatted.into())
+        ])].into(),
+        _ => Json::Array(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// New text of the formatting edit with the options
+    fn formatted(source: &str, options: &str) -> String {
+        let edits = formatting(source, &Json::parse(options).unwrap());
+
+        edits.as_array()[0].get("newText").as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn formatting_follows_editor_options() {
+        let source = "if true; then\necho a\nfi\n";
+
+        assert_eq!(formatted(source, r#"{"tabSize":2,"insertSpaces":true}"#), "if true; then\n  echo a\nfi\n");
+        assert_eq!(formatted(source, r#"{"tabSize":8,"insertSpaces":false}"#), "if true; then\n\techo a\nfi\n");
+        assert_eq!(formatted(source, "{}"), "if true; then\n    echo a\nfi\n");
+        assert!(formatting("echo a\n", &Json::Null).as_array().is_empty());
+    }
+}
@@ -0,0 +1,184 @@
+//! Commands that are not found
+//!
+//! If `command_not_found` function is defined, it is called with
+//! the command and its arguments instead, and its status becomes
+//! the status of the command:
+//!
+//! ```rsh
+//! fn command_not_found { echo &command is not installed; }
+//! ```
+//!
+//! Otherwise the error is printed with commands that differ from
+//! the typed one by a few characters, and the status is 127.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::completion::command_names;
+use crate::execute::ExecutionResult;
+use crate::utils::{error_log, exec_function, is_function};
+
+const HANDLER: &str = "command_not_found";
+
+/// Status of the command that is not found, as in other shells
+const NOT_FOUND_STATUS: i32 = 127;
+
+/// Whether the handler is running, so that commands
+/// it doesn't find don't call it again
+static HANDLING: AtomicBool = AtomicBool::new(false);
+
+pub fn command_not_found(command: &str, args: &[String]) -> ExecutionResult {
+    if is_function(HANDLER) && !HANDLING.swap(true, Ordering::Relaxed) {
+        let line = [vec![command.to_string()], args.to_vec()].concat();
+        let result = exec_function(HANDLER, &line);
+
+        HANDLING.store(false, Ordering::Relaxed);
+
+        return result;
+    }
+
+    if command.contains('/') {
+        error_log(Box::<dyn Error>::from(format!("no such file or directory: {command}")));
+        return ExecutionResult::Failure(NOT_FOUND_STATUS);
+    }
+
+    error_log(Box::<dyn Error>::from(format!("command not found: {command}")));
+
+    let similar = similar_commands(command);
+
+    if !similar.is_empty() {
+        let quoted: Vec<String> = similar.iter().map(|name| format!("`{name}`")).collect();
+        eprintln!("did you mean {}?", quoted.join(", "));
+    }
+
+    ExecutionResult::Failure(NOT_FOUND_STATUS)
+}
+
+/// Up to 3 commands with the smallest distance to the typed one
+fn similar_commands(command: &str) -> Vec<String> {
+    closest_names(command, command_names(""))
+}
+
+/// Up to 3 names with the smallest distance to the command,
+/// if it is within the limit for the length of the command
+fn closest_names<I: IntoIterator<Item = String>>(command: &str, names: I) -> Vec<String> {
+    let length = command.chars().count();
+
+    // longer names are allowed to have more typos
+    let limit = match length {
+        0..=3 => 1,
+        4..=6 => 2,
+        _ => 3
+    };
+
+    let mut similar: Vec<(usize, String)> = names
+        .into_iter()
+        .map(|name| (edit_distance(command, &name), name))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect();
+
+    similar.sort();
+
+    let closest = similar.first().map_or(0, |(distance, _)| *distance);
+
+    similar.into_iter()
+        .take_while(|(distance, _)| *distance == closest)
+        .take(3)
+        .map(|(_, name)| name)
+        .collect()
+}
+
+/// Number of inserted, deleted, replaced and swapped adjacent
+/// characters needed to turn one string into another
+/// (optimal string alignment distance)
+fn edit_distance(first: &str, second: &str) -> usize {
+    let first: Vec<char> = first.chars().collect();
+    let second: Vec<char> = second.chars().collect();
+
+    // distances[i][j] is the distance between first i and first j characters
+    let mut distances = vec![vec![0; second.len() + 1]; first.len() + 1];
+
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=first.len() {
+        for j in 1..=second.len() {
+            let cost = usize::from(first[i - 1] != second[j - 1]);
+
+            distances[i][j] = (distances[i - 1][j] + 1)
+                .min(distances[i][j - 1] + 1)
+                .min(distances[i - 1][j - 1] + cost);
+
+            if i > 1 && j > 1 && first[i - 1] == second[j - 2] && first[i - 2] == second[j - 1] {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    distances[first.len()][second.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn closest(command: &str, names: &[&str]) -> Vec<String> {
+        closest_names(command, names.iter().map(|name| name.to_string()))
+    }
+
+    #[test]
+    fn counts_edits() {
+        assert_eq!(edit_distance("git", "git"), 0);
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("", "ls"), 2);
+        assert_eq!(edit_distance("ls", ""), 2);
+        assert_eq!(edit_distance("gti", "git"), 1);
+        assert_eq!(edit_distance("gi", "git"), 1);
+        assert_eq!(edit_distance("gitt", "git"), 1);
+        assert_eq!(edit_distance("gut", "git"), 1);
+        assert_eq!(edit_distance("rsnyc", "rsync"), 1);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        // characters are swapped only once
+        assert_eq!(edit_distance("ca", "abc"), 3);
+    }
+
+    #[test]
+    fn counts_characters_not_bytes() {
+        assert_eq!(edit_distance("é", "e"), 1);
+        assert_eq!(edit_distance("日本", "本日"), 1);
+    }
+
+    #[test]
+    fn suggests_names_within_the_limit() {
+        let names = ["ls", "lsd", "cargo", "git", "python3", "rsync", "systemctl"];
+
+        assert_eq!(closest("sl", &names), ["ls"]);
+        assert_eq!(closest("gti", &names), ["git"]);
+        // short commands allow one edit
+        assert!(closest("xyz", &names).is_empty());
+        assert!(closest("gxx", &names).is_empty());
+        // up to 6 characters allow two edits
+        assert_eq!(closest("crgoo", &names), ["cargo"]);
+        assert!(closest("crgooo", &names).is_empty());
+        // longer commands allow three edits
+        assert_eq!(closest("pyhton", &names), ["python3"]);
+        assert_eq!(closest("sytsemclt", &names), ["systemctl"]);
+        assert!(closest("sxxxxmctl", &names).is_empty());
+    }
+
+    #[test]
+    fn suggests_only_the_closest_names() {
+        assert_eq!(closest("lx", &["ls", "lsd", "lxc"]), ["ls", "lxc"]);
+        assert_eq!(closest("a", &["b", "c", "d", "e"]), ["b", "c", "d"]);
+        assert!(closest("", &["ls"]).is_empty());
+    }
+
+    #[test]
+    fn suggests_builtins() {
+        assert!(similar_commands("pushdd").contains(&String::from("pushd")));
+    }
+}
@@ -0,0 +1,106 @@
+//! Shell options
+//!
+//! Options are set with `set -o name` (or its short flag) and
+//! `[options]` section of the config. They are kept in the state
+//! of the shell, so child shells don't inherit them.
+
+use std::error::Error;
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::execute::ExecutionResult;
+
+/// Names of the supported options
+///
+/// * `errexit` - exit the shell when a command fails
+/// * `pipefail` - status of a pipeline is the status of
+///   the last command that failed, not of the last command
+/// * `autocd` - command that is a name of a directory
+///   and has no arguments changes to the directory
+/// * `nounset` - expansion of unset variable is an error
+/// * `xtrace` - print commands with `PS4` prefix before
+///   they are executed
+/// * `noglob` - don't expand wildcards
+/// * `noclobber` - `>` doesn't overwrite existing files
+/// * `verbose` - print the code before it is executed
+pub const OPTIONS: [&str; 8] = ["errexit", "pipefail", "autocd", "nounset", "xtrace", "noglob", "noclobber", "verbose"];
+
+/// Short flags of `set` builtin
+const FLAGS: [(char, &str); 6] = [
+    ('e', "errexit"),
+    ('u', "nounset"),
+    ('x', "xtrace"),
+    ('f', "noglob"),
+    ('C', "noclobber"),
+    ('v', "verbose")
+];
+
+/// Enabled options, in the order of `OPTIONS`
+static ENABLED: [AtomicBool; OPTIONS.len()] = [const { AtomicBool::new(false) }; OPTIONS.len()];
+
+pub fn set_option(name: &str, enabled: bool) -> Result<(), Box<dyn Error>> {
+    match OPTIONS.iter().position(|option| *option == name) {
+        Some(index) => {
+            ENABLED[index].store(enabled, Ordering::Relaxed);
+            Ok(())
+        },
+        None => Err(Box::<dyn Error>::from(format!("{name}: invalid option name")))
+    }
+}
+
+pub fn is_set(name: &str) -> bool {
+    OPTIONS.iter()
+        .position(|option| *option == name)
+        .is_some_and(|index| ENABLED[index].load(Ordering::Relaxed))
+}
+
+/// Implementation of `set` builtin called with options
+///
+/// `-` enables options and `+` disables them:
+///
+/// ```rsh
+/// set -eu             # errexit and nounset
+/// set -o pipefail     # option by its name
+/// set +x              # stop tracing
+/// set -o              # list options
+/// set +o              # print options as `set` commands
+/// ```
+pub fn option_builtin(args: &[String]) -> ExecutionResult {
+    let mut args = args.iter();
+
+    while let Some(arg) = args.next() {
+        let enabled = arg.starts_with('-');
+
+        let result = match arg.as_str() {
+            "-o" | "+o" => match args.next() {
+                Some(name) => set_option(name, enabled),
+                None => {
+                    print_options(enabled);
+                    Ok(())
+                }
+            },
+            _ => arg.chars().skip(1).try_for_each(|flag| {
+                match FLAGS.iter().find(|(short, _)| *short == flag) {
+                    Some((_, name)) => set_option(name, enabled),
+                    None => Err(Box::<dyn Error>::from(format!("{}{flag}: invalid option", &arg[..1])))
+                }
+            })
+        };
+
+        if let Err(err) = result {
+            return ExecutionResult::Error(Box::<dyn Error>::from(format!("set: {err}")));
+        }
+    }
+
+    ExecutionResult::Success
+}
+
+/// Prints the table of options for `set -o`,
+/// or commands that restore them for `set +o`
+fn print_options(table: bool) {
+    for option in OPTIONS {
+        match (table, is_set(option)) {
+            (true, enabled) => println!("{option:<12}{}", if enabled { "on" } else { "off" }),
+            (false, true) => println!("set -o {option}"),
+            (false, false) => println!("set +o {option}")
+        }
+    }
+}
@@ -1,19 +1,475 @@
-/// Parses whole command to separate commands
-/// 
+//! Parser of the shell language
+//!
+//! Builds a tree of commands from tokens of the lexer:
+//!
+//! * pipelines joined with `&&`, separated with `;`, `&` or new line
+//! * functions: `fn name { ... }`
+//! * conditions: `if ...; then ...; elif ...; then ...; else ...; fi`
+//! * loops: `while ...; do ...; done` and `for name in words; do ...; done`
+//!
+//! Keywords are recognized only in command position, so
+//! `echo if` prints `if`. Pipelines are kept as source code
+//! and are split by `execute` itself.
+//!
+//! Input that ends before a construct is closed (unclosed quote,
+//! `fn f {`, `if` without `fi`, trailing `|` or `&&`) produces an
+//! incomplete error, so the line editor can ask for more lines.
+
+use std::error::Error;
+use std::fmt;
+use crate::lexer::{Token, TokenKind, is_unterminated, tokenize};
+
+/// Keywords that are followed by a command, e.g. `if` or `then`
+pub const COMMAND_KEYWORDS: [&str; 7] = ["time", "if", "then", "elif", "else", "while", "do"];
+
+/// Keywords of the language
+pub const KEYWORDS: [&str; 10] = ["if", "then", "elif", "else", "fi", "while", "do", "done", "for", "}"];
+
+/// Part of the source code, byte offsets
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize
+}
+
+/// Command of the tree with its source code
+#[derive(Debug)]
+pub struct Node {
+    pub kind: NodeKind,
+    /// From the first token of the command to its last token,
+    /// e.g. from `if` to `fi`
+    pub span: Span
+}
+
+#[derive(Debug)]
+pub enum NodeKind {
+    /// Pipelines joined with `&&`
+    Chain(Vec<Span>),
+    /// `fn name { ... }`, body is kept as source code
+    Function { name: String, body: Span },
+    /// `if` and `elif` branches with their conditions, and `else` branch
+    If { branches: Vec<(Vec<Node>, Vec<Node>)>, otherwise: Option<Vec<Node>> },
+    While { condition: Vec<Node>, body: Vec<Node> },
+    /// Loop over the words, which are expanded when the loop starts
+    For { variable: String, words: Vec<String>, body: Vec<Node> }
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub message: String,
+    /// Byte offset of the error in the source
+    pub position: usize,
+    /// Input ends before the construct is closed
+    pub incomplete: bool
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "syntax error: {}", self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+/// Parses whole command to the tree of commands
+///
 /// Example:
-/// 
-/// ```rust
+///
+/// ```rust,ignore
 /// let input = "echo hello && echo world; echo a > text.txt";
-/// let commands = parse_commands(input);
-/// 
-/// assert_eq!(commands, vec![vec!["echo hello", "echo world"], vec![" echo a > text.txt"]])
+/// let commands = parse_command(input)?;
+///
+/// // Chain([0..10, 14..24]), Chain([26..42])
 /// ```
-/// 
-pub fn parse_command(command: &str) -> Vec<Vec<&str>> {
-    let mut separate_commands: Vec<Vec<&str>> = Vec::new();
+pub fn parse_command(source: &str) -> Result<Vec<Node>, ParseError> {
+    let tokens: Vec<Token> = tokenize(source)
+        .into_iter()
+        .filter(|token| token.kind != TokenKind::Comment)
+        .collect();
+
+    if let Some(last) = tokens.last().filter(|last| last.kind == TokenKind::Word && is_unterminated(last.text)) {
+        return Err(ParseError { message: String::from("unclosed quote"), position: last.start, incomplete: true });
+    }
+
+    let mut parser = Parser { source, tokens, position: 0, open: Vec::new() };
+    let mut nodes = Vec::new();
+
+    loop {
+        parser.skip_separators(false);
+
+        match parser.peek() {
+            Some(token) => nodes.push(parser.command(token)?),
+            None => return Ok(nodes)
+        }
+    }
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token<'a>>,
+    position: usize,
+    /// Keywords of the compound commands being parsed,
+    /// e.g. `if`, for errors about unclosed blocks
+    open: Vec<Token<'a>>
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn peek_word(&self, offset: usize) -> Option<&'a str> {
+        self.tokens.get(self.position + offset)
+            .filter(|token| token.kind == TokenKind::Word)
+            .map(|token| token.text)
+    }
+
+    /// Skips `;` and new lines, or only new lines
+    fn skip_separators(&mut self, only_newlines: bool) {
+        while self.peek().is_some_and(|token| token.kind == TokenKind::Separator && (!only_newlines || token.text == "\n")) {
+            self.position += 1;
+        }
+    }
+
+    fn error(&self, message: String, token: Option<Token>) -> ParseError {
+        match token {
+            Some(token) => ParseError { message, position: token.start, incomplete: false },
+            None => ParseError { message, position: self.source.len(), incomplete: true }
+        }
+    }
+
+    /// Parses commands until one of the terminators in command
+    /// position, returns the commands and the terminator
+    fn block(&mut self, terminators: &[&'static str]) -> Result<(Vec<Node>, &'a str), ParseError> {
+        let mut nodes = Vec::new();
+
+        loop {
+            self.skip_separators(false);
+
+            let Some(token) = self.peek() else {
+                let expected = expected(terminators);
+
+                // unclosed block is reported at its keyword
+                return Err(match self.open.last() {
+                    Some(keyword) => ParseError {
+                        message: format!("expected {expected} to close `{}`", keyword.text),
+                        position: keyword.start,
+                        incomplete: true
+                    },
+                    None => self.error(format!("expected {expected}"), None)
+                });
+            };
+
+            if let Some(word) = self.peek_word(0).filter(|word| terminators.contains(word)) {
+                self.position += 1;
+                return Ok((nodes, word));
+            }
+
+            nodes.push(self.command(token)?);
+        }
+    }
+
+    /// Parses the command that starts with the token
+    fn command(&mut self, token: Token<'a>) -> Result<Node, ParseError> {
+        let compound = match self.peek_word(0) {
+            Some("if" | "while" | "for") => true,
+            Some("fn") => self.peek_word(1).is_some() && self.peek_word(2) == Some("{"),
+            Some(word) if KEYWORDS.contains(&word) => return Err(self.error(format!("unexpected `{word}`"), Some(token))),
+            _ => false
+        };
+
+        let kind = if compound {
+            self.open.push(token);
+
+            let kind = match token.text {
+                "if" => self.condition()?,
+                "while" => self.while_loop()?,
+                "for" => self.for_loop()?,
+                _ => self.function()?
+            };
+
+            self.open.pop();
+            kind
+        } else {
+            self.chain()?
+        };
+
+        let end = self.tokens[self.position - 1].end();
+
+        Ok(Node { kind, span: Span { start: token.start, end } })
+    }
+
+    /// Parses the block that must contain at least one command
+    fn body(&mut self, keyword: &str, terminators: &[&'static str]) -> Result<(Vec<Node>, &'a str), ParseError> {
+        let start = self.peek();
+        let (nodes, terminator) = self.block(terminators)?;
+
+        if nodes.is_empty() {
+            return Err(self.error(format!("expected command after `{keyword}`"), start));
+        }
+
+        Ok((nodes, terminator))
+    }
+
+    /// Compound command must be followed by the end of the command
+    fn end_of_command(&self, keyword: &str) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(token) if token.kind != TokenKind::Separator => {
+                Err(self.error(format!("expected `;` or new line after `{keyword}`"), Some(token)))
+            },
+            _ => Ok(())
+        }
+    }
+
+    fn chain(&mut self) -> Result<NodeKind, ParseError> {
+        let mut pipelines = Vec::new();
+        let mut pipeline: Option<Span> = None;
+
+        while let Some(token) = self.peek() {
+            match token.kind {
+                TokenKind::Separator => break,
+                TokenKind::And => {
+                    match pipeline.take() {
+                        Some(span) => pipelines.push(span),
+                        None => return Err(self.error(String::from("expected command before `&&`"), Some(token)))
+                    }
+
+                    self.position += 1;
+
+                    // chain continues on the next line
+                    self.skip_separators(true);
+
+                    match self.peek() {
+                        None => return Err(self.error(String::from("expected command after `&&`"), None)),
+                        Some(next) if next.kind == TokenKind::Separator => {
+                            return Err(self.error(String::from("expected command after `&&`"), Some(next)));
+                        },
+                        _ => {}
+                    }
+                },
+                kind => {
+                    let start = pipeline.map_or(token.start, |span| span.start);
+                    pipeline = Some(Span { start, end: token.end() });
+                    self.position += 1;
+
+                    // `&` runs the pipeline in background and ends the command
+                    if kind == TokenKind::Background {
+                        break;
+                    }
+
+                    if kind == TokenKind::Pipe {
+                        // pipeline continues on the next line
+                        self.skip_separators(true);
+
+                        if self.peek().is_none() {
+                            return Err(self.error(String::from("expected command after `|`"), None));
+                        }
+                    }
+                }
+            }
+        }
+
+        pipelines.extend(pipeline);
+
+        Ok(NodeKind::Chain(pipelines))
+    }
+
+    fn function(&mut self) -> Result<NodeKind, ParseError> {
+        let name = self.tokens[self.position + 1].text.to_string();
+        let start = self.tokens[self.position + 2].end();
+
+        self.position += 3;
+        self.block(&["}"])?;
+
+        let end = self.tokens[self.position - 1].start;
+        self.end_of_command("}")?;
+
+        Ok(NodeKind::Function { name, body: Span { start, end } })
+    }
+
+    fn condition(&mut self) -> Result<NodeKind, ParseError> {
+        let mut branches = Vec::new();
+        let mut otherwise = None;
+        let mut keyword = "if";
+
+        self.position += 1;
+
+        loop {
+            let (condition, _) = self.body(keyword, &["then"])?;
+            let (body, terminator) = self.body("then", &["elif", "else", "fi"])?;
+
+            branches.push((condition, body));
+
+            match terminator {
+                "elif" => keyword = "elif",
+                "else" => {
+                    otherwise = Some(self.body("else", &["fi"])?.0);
+                    break;
+                },
+                _ => break
+            }
+        }
+
+        self.end_of_command("fi")?;
+
+        Ok(NodeKind::If { branches, otherwise })
+    }
+
+    fn while_loop(&mut self) -> Result<NodeKind, ParseError> {
+        self.position += 1;
+
+        let (condition, _) = self.body("while", &["do"])?;
+        let (body, _) = self.body("do", &["done"])?;
+
+        self.end_of_command("done")?;
+
+        Ok(NodeKind::While { condition, body })
+    }
+
+    fn for_loop(&mut self) -> Result<NodeKind, ParseError> {
+        self.position += 1;
+
+        let variable = match self.peek() {
+            Some(token) if token.kind == TokenKind::Word => token.text.to_string(),
+            token => return Err(self.error(String::from("expected variable name after `for`"), token))
+        };
+
+        self.position += 1;
+
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Word && token.text == "in" => self.position += 1,
+            token => return Err(self.error(String::from("expected `in` after variable name"), token))
+        }
+
+        let mut words = Vec::new();
+
+        while let Some(token) = self.peek().filter(|token| token.kind == TokenKind::Word) {
+            words.push(token.text.to_string());
+            self.position += 1;
+        }
+
+        match self.peek() {
+            Some(token) if token.kind != TokenKind::Separator => {
+                return Err(self.error(format!("unexpected `{}`", token.text), Some(token)));
+            },
+            _ => self.skip_separators(false)
+        }
+
+        match self.peek() {
+            Some(token) if token.kind == TokenKind::Word && token.text == "do" => self.position += 1,
+            token => return Err(self.error(String::from("expected `do`"), token))
+        }
+
+        let (body, _) = self.body("do", &["done"])?;
+
+        self.end_of_command("done")?;
+
+        Ok(NodeKind::For { variable, words, body })
+    }
+}
+
+/// Formats the list of keywords, e.g. "`elif`, `else` or `fi`"
+fn expected(keywords: &[&str]) -> String {
+    let quoted: Vec<String> = keywords.iter().map(|keyword| format!("`{keyword}`")).collect();
+
+    match quoted.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {last}", rest.join(", ")),
+        _ => quoted.concat()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tree as text, e.g. `if(echo a)then(echo b)`
+    fn describe(source: &str, nodes: &[Node]) -> String {
+        nodes.iter().map(|node| match &node.kind {
+            NodeKind::Chain(pipelines) => pipelines.iter()
+                .map(|span| &source[span.start..span.end])
+                .collect::<Vec<_>>()
+                .join(" && "),
+            NodeKind::Function { name, body } => format!("fn {name}({})", source[body.start..body.end].trim()),
+            NodeKind::If { branches, otherwise } => {
+                let mut text: String = branches.iter()
+                    .map(|(condition, body)| format!("if({})then({})", describe(source, condition), describe(source, body)))
+                    .collect();
+
+                if let Some(otherwise) = otherwise {
+                    text += &format!("else({})", describe(source, otherwise));
+                }
+
+                text
+            },
+            NodeKind::While { condition, body } => format!("while({})do({})", describe(source, condition), describe(source, body)),
+            NodeKind::For { variable, words, body } => format!("for {variable} in {}({})", words.join(","), describe(source, body))
+        }).collect::<Vec<_>>().join("; ")
+    }
+
+    fn parse(source: &str) -> String {
+        describe(source, &parse_command(source).unwrap())
+    }
+
+    fn error(source: &str) -> ParseError {
+        parse_command(source).unwrap_err()
+    }
+
+    #[test]
+    fn chains_and_separators() {
+        assert_eq!(parse("echo hello && echo world; echo a > text.txt"), "echo hello && echo world; echo a > text.txt");
+        assert_eq!(parse("a | b\n\nc &\nd # comment"), "a | b; c &; d");
+        assert_eq!(parse(""), "");
+        assert_eq!(parse(r#"echo "a; b" && c\;d"#), r#"echo "a; b" && c\;d"#);
+    }
+
+    #[test]
+    fn spans_point_to_source() {
+        let source = "  echo a;  echo b";
+        let nodes = parse_command(source).unwrap();
+
+        assert_eq!(nodes[0].span, Span { start: 2, end: 8 });
+        assert_eq!(nodes[1].span, Span { start: 11, end: 17 });
+    }
+
+    #[test]
+    fn control_flow() {
+        assert_eq!(parse("if a; then b; elif c; then d; else e; fi"), "if(a)then(b)if(c)then(d)else(e)");
+        assert_eq!(parse("while a\ndo\n  b\n  c\ndone"), "while(a)do(b; c)");
+        assert_eq!(parse("for x in 1 2 3; do echo $x; done"), "for x in 1,2,3(echo $x)");
+        assert_eq!(parse("fn greet {\n  echo hi &name\n}\ngreet a"), "fn greet(echo hi &name); greet a");
+        assert_eq!(parse("if a; then while b; do c; done; fi"), "if(a)then(while(b)do(c))");
+    }
+
+    #[test]
+    fn keywords_only_in_command_position() {
+        assert_eq!(parse("echo if then fi"), "echo if then fi");
+        assert_eq!(parse("if echo fi; then echo done; fi"), "if(echo fi)then(echo done)");
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for source in ["echo \"abc", "echo abc\\", "fn f {", "fn f {\n echo a", "if a", "if a; then b",
+            "if a; then b; else c", "while a; do b", "for x in a b; do", "a |", "a &&", "a &&\n"] {
+            let error = error(source);
+
+            assert!(error.incomplete, "{source}: {error}");
+        }
+    }
+
+    #[test]
+    fn complete_input_with_errors() {
+        for source in ["fi", "done", "then a", "if a; fi", "while a; done", "&& a", "}", "for x of a"] {
+            let error = error(source);
+
+            assert!(!error.incomplete, "{source}: {error}");
+        }
+    }
+
+    #[test]
+    fn error_position() {
+        let error = error("echo a\nfi");
 
-    for independent_command in command.split("; ").collect::<Vec<&str>>() {
-        separate_commands.push(independent_command.split(" && ").collect());
+        assert_eq!(error.position, 7);
+        assert_eq!(error.to_string(), format!("syntax error: {}", error.message));
     }
-    separate_commands
 }
@@ -0,0 +1,611 @@
+//! Full-screen fuzzy picker
+//!
+//! History picker is opened with `Ctrl-R`. Commands of the
+//! history are matched with the query as subsequences, like
+//! fzf does, and ranked by the quality of the match, how
+//! recently and how often they were used. The same picker
+//! chooses between ambiguous matches of `z`.
+//!
+//! | key | action |
+//! |:----|:----|
+//! | `Up`, `Down`, `Ctrl-P`, `Ctrl-N` | move selection |
+//! | `Enter` | insert the selected command into the line |
+//! | `Esc`, `Ctrl-C`, `Ctrl-G` | close the picker |
+//! | `Ctrl-D` | history: show only commands executed in the current directory |
+//! | `Ctrl-S` | history: show all, only succeeded or only failed commands |
+
+use std::collections::HashMap;
+use std::env::current_dir;
+use std::io::{self, Write};
+use std::mem::MaybeUninit;
+use crate::colors::*;
+use crate::editor::terminal_size;
+use crate::history::{Entry, now, with_entries};
+
+/// Items shown by the picker
+trait Source {
+    /// Items that match the query, the best first,
+    /// and the number of items that pass the filters
+    fn matches(&self, query: &str) -> (Vec<Match>, usize);
+
+    /// Handles keys that change filters, returns whether they were changed
+    fn handle(&mut self, _key: &Key) -> bool {
+        false
+    }
+
+    /// Active filters and keys that change them
+    fn header(&self) -> String {
+        String::new()
+    }
+}
+
+struct Match {
+    text: String,
+    /// Positions of the matched characters
+    positions: Vec<usize>,
+    /// Colored text on the right side of the line
+    annotations: Vec<(&'static str, String)>
+}
+
+struct Picker<S: Source> {
+    source: S,
+    query: String,
+    selected: usize,
+    /// First visible match
+    offset: usize
+}
+
+/// Opens the picker with the initial query, returns the selected command
+pub fn pick_history(query: &str) -> Option<String> {
+    let source = HistorySource {
+        directory_only: false,
+        status: StatusFilter::All,
+        directory: current_dir().unwrap_or_default().display().to_string()
+    };
+
+    Picker { source, query: query.to_string(), selected: 0, offset: 0 }.open()
+}
+
+/// Opens the picker over the items, which are ordered from the best
+pub fn pick_item(items: Vec<String>) -> Option<String> {
+    Picker { source: ListSource(items), query: String::new(), selected: 0, offset: 0 }.open()
+}
+
+impl<S: Source> Picker<S> {
+    fn open(mut self) -> Option<String> {
+        let original = enable_raw_mode()?;
+
+        // alternate screen keeps the terminal content
+        print!("\x1b[?1049h");
+        let selected = self.run();
+        print!("\x1b[?1049l");
+        let _ = io::stdout().flush();
+
+        disable_raw_mode(&original);
+
+        selected
+    }
+
+    fn run(&mut self) -> Option<String> {
+        loop {
+            let (matches, total) = self.source.matches(&self.query);
+
+            self.selected = self.selected.min(matches.len().saturating_sub(1));
+
+            if self.draw(&matches, total).is_err() {
+                return None;
+            }
+
+            match read_key()? {
+                Key::Char(char) => {
+                    self.query.push(char);
+                    self.selected = 0;
+                },
+                Key::Backspace => {
+                    self.query.pop();
+                    self.selected = 0;
+                },
+                Key::Up => self.selected = self.selected.saturating_sub(1),
+                Key::Down => self.selected += 1,
+                Key::Enter => return matches.into_iter().nth(self.selected).map(|matched| matched.text),
+                Key::Cancel => return None,
+                key => {
+                    if self.source.handle(&key) {
+                        self.selected = 0;
+                    }
+                }
+            }
+        }
+    }
+
+    fn draw(&mut self, matches: &[Match], total: usize) -> io::Result<()> {
+        let (columns, rows) = terminal_size().unwrap_or((80, 24));
+        let height = rows.saturating_sub(2).max(1);
+
+        // keep the selection visible
+        if self.selected < self.offset {
+            self.offset = self.selected;
+        } else if self.selected >= self.offset + height {
+            self.offset = self.selected + 1 - height;
+        }
+
+        let mut frame = String::from("\x1b[H\x1b[2J");
+
+        frame += &format!("{DIMMED}{}/{total} {}{RESET}\r\n", matches.len(), self.source.header());
+
+        for (index, matched) in matches.iter().enumerate().skip(self.offset).take(height) {
+            frame += &draw_match(matched, index == self.selected, columns);
+            frame += "\r\n";
+        }
+
+        frame += &format!("\x1b[{rows};1H{BOLD}>{RESET} {}", self.query);
+
+        let mut stdout = io::stdout();
+        stdout.write_all(frame.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+/// Line of the match, truncated to the width of the terminal
+fn draw_match(matched: &Match, selected: bool, columns: usize) -> String {
+    let marker = if selected { "\x1b[7m" } else { "" };
+
+    let annotations: String = matched.annotations.iter()
+        .map(|(color, text)| format!("{color}{text}{RESET}{marker}"))
+        .collect();
+
+    let annotations_width: usize = matched.annotations.iter().map(|(_, text)| text.chars().count()).sum();
+    let width = columns.saturating_sub(annotations_width + 3);
+
+    let mut line = String::new();
+    let mut length = 0;
+
+    for (index, char) in matched.text.chars().enumerate() {
+        if length == width {
+            break;
+        }
+
+        // multi-line commands are displayed in one line
+        let char = if char == '\n' { '↵' } else { char };
+
+        if matched.positions.contains(&index) {
+            line += &format!("{BOLD}{CYAN}{char}{RESET}{marker}");
+        } else {
+            line.push(char);
+        }
+
+        length += 1;
+    }
+
+    let padding = " ".repeat(width.saturating_sub(length));
+
+    format!("{marker} {line}{padding} {annotations}{RESET} ")
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum StatusFilter {
+    All,
+    Succeeded,
+    Failed
+}
+
+/// Distinct command of the history
+struct Candidate {
+    command: String,
+    /// Position of the latest entry, greater is more recent
+    last: usize,
+    count: usize,
+    start: u64,
+    status: i32
+}
+
+/// Commands of the history, ranked by the match,
+/// how recently and how often they were executed
+struct HistorySource {
+    directory_only: bool,
+    status: StatusFilter,
+    directory: String
+}
+
+impl Source for HistorySource {
+    fn matches(&self, query: &str) -> (Vec<Match>, usize) {
+        let candidates = with_entries(|entries| self.candidates(entries));
+        let total = candidates.len();
+        let newest = candidates.values().map(|candidate| candidate.last).max().unwrap_or(0) + 1;
+
+        let mut matches: Vec<(f64, Candidate, Vec<usize>)> = candidates.into_values()
+            .filter_map(|candidate| {
+                let (score, positions) = fuzzy_match(query, &candidate.command)?;
+
+                let recency = candidate.last as f64 / newest as f64;
+                let frequency = (candidate.count as f64).ln_1p();
+
+                Some((score + 4.0 * recency + 2.0 * frequency, candidate, positions))
+            })
+            .collect();
+
+        matches.sort_by(|(a, first, _), (b, second, _)| {
+            b.total_cmp(a).then(second.last.cmp(&first.last))
+        });
+
+        let matches = matches.into_iter()
+            .map(|(_, candidate, positions)| {
+                let age = match candidate.start {
+                    0 => String::new(),
+                    start => format_age(now().saturating_sub(start))
+                };
+
+                let status = if candidate.status == 0 { String::new() } else { format!("✗ {} ", candidate.status) };
+
+                Match { text: candidate.command, positions, annotations: vec![(RED, status), (DIMMED, age)] }
+            })
+            .collect();
+
+        (matches, total)
+    }
+
+    fn handle(&mut self, key: &Key) -> bool {
+        match key {
+            Key::ToggleDirectory => self.directory_only = !self.directory_only,
+            Key::ToggleStatus => {
+                self.status = match self.status {
+                    StatusFilter::All => StatusFilter::Succeeded,
+                    StatusFilter::Succeeded => StatusFilter::Failed,
+                    StatusFilter::Failed => StatusFilter::All
+                };
+            },
+            _ => return false
+        }
+
+        true
+    }
+
+    fn header(&self) -> String {
+        let mut filters = Vec::new();
+
+        if self.directory_only {
+            filters.push("directory");
+        }
+
+        match self.status {
+            StatusFilter::All => {},
+            StatusFilter::Succeeded => filters.push("succeeded"),
+            StatusFilter::Failed => filters.push("failed")
+        }
+
+        let filters = if filters.is_empty() { String::new() } else { format!("[{}] ", filters.join(", ")) };
+
+        format!("{filters}(Ctrl-D: directory, Ctrl-S: status)")
+    }
+}
+
+impl HistorySource {
+    /// Groups entries that pass the filters by command
+    fn candidates(&self, entries: &[Entry]) -> HashMap<String, Candidate> {
+        let mut candidates: HashMap<String, Candidate> = HashMap::new();
+
+        let filtered = entries.iter().enumerate().filter(|(_, entry)| {
+            let status = match self.status {
+                StatusFilter::All => true,
+                StatusFilter::Succeeded => entry.status == 0,
+                StatusFilter::Failed => entry.status != 0
+            };
+
+            status && (!self.directory_only || entry.directory == self.directory)
+        });
+
+        for (index, entry) in filtered {
+            let candidate = candidates.entry(entry.command.clone()).or_insert(Candidate {
+                command: entry.command.clone(),
+                last: index,
+                count: 0,
+                start: entry.start,
+                status: entry.status
+            });
+
+            candidate.count += 1;
+            candidate.last = index;
+            candidate.start = entry.start;
+            candidate.status = entry.status;
+        }
+
+        candidates
+    }
+}
+
+/// Items that keep their order, query only filters them
+struct ListSource(Vec<String>);
+
+impl Source for ListSource {
+    fn matches(&self, query: &str) -> (Vec<Match>, usize) {
+        let matches = self.0.iter()
+            .filter_map(|item| {
+                let (_, positions) = fuzzy_match(query, item)?;
+                Some(Match { text: item.clone(), positions, annotations: Vec::new() })
+            })
+            .collect();
+
+        (matches, self.0.len())
+    }
+}
+
+/// Matches the query as a subsequence of the command
+///
+/// Returns the score of the match and positions of the matched
+/// characters. Consecutive characters and characters at the start
+/// of words score more. Query without uppercase letters matches
+/// case-insensitively
+fn fuzzy_match(query: &str, command: &str) -> Option<(f64, Vec<usize>)> {
+    if query.is_empty() {
+        return Some((0.0, Vec::new()));
+    }
+
+    let ignore_case = !query.chars().any(char::is_uppercase);
+    let normalize = |char: char| if ignore_case { char.to_ascii_lowercase() } else { char };
+
+    let chars: Vec<char> = command.chars().collect();
+    let mut positions = Vec::new();
+    let mut score = 0.0;
+    let mut start = 0;
+
+    for query_char in query.chars().map(normalize) {
+        let position = (start..chars.len()).find(|index| normalize(chars[*index]) == query_char)?;
+
+        score += 1.0;
+
+        if positions.last().is_some_and(|last| last + 1 == position) {
+            score += 2.0;
+        }
+
+        if position == 0 || !chars[position - 1].is_alphanumeric() {
+            score += 1.5;
+        }
+
+        positions.push(position);
+        start = position + 1;
+    }
+
+    // shorter commands with the same match are better
+    score -= chars.len() as f64 * 0.01;
+
+    Some((score, positions))
+}
+
+/// Formats the time passed since the command, e.g. `5m` or `3d`
+fn format_age(seconds: u64) -> String {
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400)
+    }
+}
+
+enum Key {
+    Char(char),
+    Backspace,
+    Up,
+    Down,
+    Enter,
+    Cancel,
+    ToggleDirectory,
+    ToggleStatus,
+    Other
+}
+
+/// Reads a key from the terminal, returns `None` on the end of input
+fn read_key() -> Option<Key> {
+    let mut byte = [0u8];
+    read_exact(&mut byte)?;
+
+    let key = match byte[0] {
+        b'\r' | b'\n' => Key::Enter,
+        0x7f | 0x08 => Key::Backspace,
+        0x03 | 0x07 => Key::Cancel,
+        0x04 => Key::ToggleDirectory,
+        0x13 => Key::ToggleStatus,
+        0x10 | 0x0b => Key::Up,
+        0x0e => Key::Down,
+        0x1b => {
+            // lone `Esc` is not followed by the rest of the sequence
+            if !has_input(50) {
+                return Some(Key::Cancel);
+            }
+
+            let mut sequence = [0u8; 2];
+            read_exact(&mut sequence)?;
+
+            match sequence {
+                [b'[' | b'O', b'A'] => Key::Up,
+                [b'[' | b'O', b'B'] => Key::Down,
+                _ => Key::Other
+            }
+        },
+        byte if byte < 0x20 => Key::Other,
+        byte => {
+            // rest of UTF-8 character
+            let length = match byte {
+                0xf0.. => 4,
+                0xe0.. => 3,
+                0xc0.. => 2,
+                _ => 1
+            };
+
+            let mut buffer = vec![byte; length];
+            read_exact(&mut buffer[1..])?;
+
+            match String::from_utf8_lossy(&buffer).chars().next() {
+                Some(char) => Key::Char(char),
+                None => Key::Other
+            }
+        }
+    };
+
+    Some(key)
+}
+
+/// Reads from the terminal without buffering, so that
+/// `has_input` sees the rest of escape sequences
+fn read_exact(buffer: &mut [u8]) -> Option<()> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        let rest = &mut buffer[filled..];
+        let count = unsafe { libc::read(libc::STDIN_FILENO, rest.as_mut_ptr() as *mut libc::c_void, rest.len()) };
+
+        if count <= 0 {
+            return None;
+        }
+
+        filled += count as usize;
+    }
+
+    Some(())
+}
+
+/// Whether stdin has input within the timeout in milliseconds
+fn has_input(timeout: i32) -> bool {
+    let mut descriptor = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    unsafe { libc::poll(&mut descriptor, 1, timeout) > 0 }
+}
+
+/// Switches the terminal to raw mode, returns the original mode
+fn enable_raw_mode() -> Option<libc::termios> {
+    let mut original = MaybeUninit::<libc::termios>::zeroed();
+
+    unsafe {
+        if libc::tcgetattr(libc::STDIN_FILENO, original.as_mut_ptr()) != 0 {
+            return None;
+        }
+
+        let original = original.assume_init();
+        let mut raw = original;
+
+        libc::cfmakeraw(&mut raw);
+
+        if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+            return None;
+        }
+
+        Some(original)
+    }
+}
+
+fn disable_raw_mode(original: &libc::termios) {
+    unsafe {
+        libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, original);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// Commands that match the query, the best first
+    fn rank<'a>(query: &str, commands: &[&'a str]) -> Vec<&'a str> {
+        let mut matches: Vec<(f64, &str)> = commands.iter()
+            .filter_map(|command| Some((fuzzy_match(query, command)?.0, *command)))
+            .collect();
+
+        matches.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+        matches.into_iter().map(|(_, command)| command).collect()
+    }
+
+    fn positions(query: &str, command: &str) -> Option<Vec<usize>> {
+        fuzzy_match(query, command).map(|(_, positions)| positions)
+    }
+
+    fn entry(command: &str, status: i32, directory: &str) -> Entry {
+        Entry {
+            command: command.to_string(),
+            start: 0,
+            duration: Duration::ZERO,
+            status,
+            directory: directory.to_string()
+        }
+    }
+
+    #[test]
+    fn matches_subsequences() {
+        assert_eq!(positions("gco", "git checkout"), Some(vec![0, 4, 9]));
+        assert_eq!(positions("ls", "ls -la"), Some(vec![0, 1]));
+        assert_eq!(positions("cargo", "cargo"), Some(vec![0, 1, 2, 3, 4]));
+
+        assert_eq!(positions("xyz", "git checkout"), None);
+        assert_eq!(positions("ba", "ab"), None);
+        assert_eq!(positions("lss", "ls"), None);
+        assert_eq!(positions("a", ""), None);
+    }
+
+    #[test]
+    fn matches_case_only_for_uppercase_queries() {
+        assert_eq!(positions("make", "MAKE"), Some(vec![0, 1, 2, 3]));
+        assert_eq!(positions("Make", "make"), None);
+        assert_eq!(positions("Make", "Make"), Some(vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn empty_query_matches_everything() {
+        assert_eq!(fuzzy_match("", "git status"), Some((0.0, Vec::new())));
+        assert_eq!(fuzzy_match("", ""), Some((0.0, Vec::new())));
+        assert_eq!(rank("", &["b", "a"]).len(), 2);
+    }
+
+    #[test]
+    fn ranks_consecutive_and_word_start_matches_first() {
+        assert_eq!(rank("log", &["lxoxg", "git log"]), ["git log", "lxoxg"]);
+        assert_eq!(rank("st", &["mst", "git st"]), ["git st", "mst"]);
+        assert_eq!(rank("gs", &["gxs", "git status"]), ["git status", "gxs"]);
+        // shorter commands win ties
+        assert_eq!(rank("ls", &["ls -la", "ls"]), ["ls", "ls -la"]);
+        assert_eq!(rank("ls", &["echo", "ls"]), ["ls"]);
+    }
+
+    #[test]
+    fn positions_are_character_indices() {
+        assert_eq!(positions("é", "café"), Some(vec![3]));
+        assert_eq!(positions("ct", "échec total"), Some(vec![1, 6]));
+        assert_eq!(positions("日本", "echo 日本語"), Some(vec![5, 6]));
+
+        // highlighted characters are the matched ones
+        let matched = Match { text: String::from("echo 日本"), positions: vec![6], annotations: Vec::new() };
+        let line = draw_match(&matched, false, 40);
+
+        assert!(line.contains(&format!("日{BOLD}{CYAN}本{RESET}")));
+    }
+
+    #[test]
+    fn truncates_lines_to_the_width() {
+        let matched = Match { text: String::from("echo 日本語\nls"), positions: Vec::new(), annotations: Vec::new() };
+
+        assert_eq!(draw_match(&matched, false, 10), format!(" echo 日本 {RESET} "));
+        assert_eq!(draw_match(&matched, false, 20), format!(" echo 日本語↵ls{} {RESET} ", " ".repeat(6)));
+    }
+
+    #[test]
+    fn groups_history_by_command() {
+        let entries = [entry("make", 0, "/a"), entry("ls", 1, "/b"), entry("make", 2, "/b")];
+        let mut source = HistorySource { directory_only: false, status: StatusFilter::All, directory: String::from("/a") };
+
+        let candidates = source.candidates(&entries);
+        assert_eq!(candidates.len(), 2);
+        assert_eq!((candidates["make"].count, candidates["make"].last, candidates["make"].status), (2, 2, 2));
+
+        source.status = StatusFilter::Succeeded;
+        assert_eq!(source.candidates(&entries)["make"].count, 1);
+
+        source.status = StatusFilter::Failed;
+        source.directory_only = true;
+        assert!(source.candidates(&entries).is_empty());
+    }
+
+    #[test]
+    fn lists_keep_their_order() {
+        let source = ListSource(vec![String::from("/tmp/xbar"), String::from("/home/bar"), String::from("/usr")]);
+        let (matches, total) = source.matches("bar");
+
+        assert_eq!(total, 3);
+        assert_eq!(matches.iter().map(|matched| matched.text.as_str()).collect::<Vec<_>>(), ["/tmp/xbar", "/home/bar"]);
+    }
+}
@@ -0,0 +1,240 @@
+//! Prompt templates
+//!
+//! Prompts are generated from templates stored in
+//! `PS1`, `PS2` and `RPROMPT` variables. Template is
+//! printed as is, except for escape sequences:
+//!
+//! | escape | expands to |
+//! |:----|:----|
+//! | `\u` | user name |
+//! | `\h`, `\H` | host name up to the first `.`, full host name |
+//! | `\w` | working directory, home is abbreviated with `~` |
+//! | `\<N>w` | last `N` components of `\w` (e.g. `\2w`) |
+//! | `\W` | basename of the working directory |
+//! | `\p` | full path of the working directory |
+//! | `\?` | exit status of the last command |
+//! | `\t`, `\T`, `\d` | time as `HH:MM:SS`, as `HH:MM`, date as `YYYY-MM-DD` |
+//! | `\D` | duration of the last command if it exceeds the threshold from the config |
+//! | `\j` | number of background jobs |
+//! | `\g` | git branch and status, see `git` module |
+//! | `\L` | shell level (`$SHLVL`) |
+//! | `\$` | `#` for root, `$` otherwise |
+//! | `\n`, `\e`, `\\` | newline, escape character, backslash |
+//! | `\[color]` | color from `@format` table (e.g. `\[red]`), `\[reset]` or `\[status]` |
+//!
+//! `\[status]` is the success or failure color from the config,
+//! depending on the exit status of the last command.
+
+use std::env::var;
+use std::ffi::CStr;
+use std::mem::MaybeUninit;
+use std::path::{MAIN_SEPARATOR, MAIN_SEPARATOR_STR};
+use std::time::Duration;
+use crate::colors::*;
+use crate::config::config;
+use crate::directories::working_directory;
+use crate::git::git_segment;
+use crate::jobs::job_count;
+use crate::utils::last_exit_status;
+
+pub fn expand_prompt(template: &str) -> String {
+    let mut prompt = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(char) = chars.next() {
+        if char != '\\' {
+            prompt.push(char);
+            continue;
+        }
+
+        let mut count = String::new();
+
+        while let Some(digit) = chars.next_if(|char| char.is_ascii_digit()) {
+            count.push(digit);
+        }
+
+        match chars.next() {
+            Some('u') => prompt += &user(),
+            Some('h') => prompt += host().split('.').next().unwrap_or_default(),
+            Some('H') => prompt += &host(),
+            Some('w') => prompt += &truncate_path(&abbreviated_directory(), count.parse().unwrap_or(0)),
+            Some('W') => {
+                let directory = abbreviated_directory();
+
+                prompt += match directory.rsplit_once(MAIN_SEPARATOR) {
+                    Some(("", "")) => "/",
+                    Some((_, basename)) => basename,
+                    None => &directory
+                };
+            },
+            Some('p') => prompt += &working_directory().display().to_string(),
+            Some('?') => prompt += &last_exit_status().to_string(),
+            Some('t') => prompt += &format_time("%H:%M:%S"),
+            Some('T') => prompt += &format_time("%H:%M"),
+            Some('d') => prompt += &format_time("%Y-%m-%d"),
+            Some('D') => {
+                let duration = var("CMD_DURATION").ok()
+                    .and_then(|milliseconds| milliseconds.parse().ok())
+                    .map(Duration::from_millis)
+                    .unwrap_or_default();
+
+                if duration >= config().duration_threshold {
+                    prompt += &format_duration(duration);
+                }
+            },
+            Some('j') => prompt += &job_count().to_string(),
+            Some('g') => prompt += &git_segment(),
+            Some('L') => prompt += &var("SHLVL").unwrap_or_else(|_| String::from("1")),
+            Some('$') => prompt.push(if unsafe { libc::geteuid() } == 0 { '#' } else { '$' }),
+            Some('n') => prompt.push('\n'),
+            Some('e') => prompt.push('\x1b'),
+            Some('\\') => prompt.push('\\'),
+            Some('[') => {
+                let name: String = chars.by_ref().take_while(|char| *char != ']').collect();
+
+                match name.as_str() {
+                    "reset" => prompt += RESET,
+                    "status" if last_exit_status() == 0 => prompt += &config().success_color,
+                    "status" => prompt += &config().failure_color,
+                    name => match color_by_name(name) {
+                        Some(color) => prompt += color,
+                        None => prompt += &format!("\\[{name}]")
+                    }
+                }
+            },
+            Some(other) => {
+                prompt.push('\\');
+                prompt += &count;
+                prompt.push(other);
+            },
+            None => prompt.push('\\')
+        }
+    }
+
+    prompt
+}
+
+/// Number of columns the text takes in the terminal,
+/// escape sequences are not counted
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+
+    while let Some(char) = chars.next() {
+        if char == '\x1b' {
+            // skip control sequence up to its final byte
+            if chars.next() == Some('[') {
+                for char in chars.by_ref() {
+                    if ('@'..='~').contains(&char) {
+                        break;
+                    }
+                }
+            }
+        } else if !char.is_control() {
+            width += 1;
+        }
+    }
+
+    width
+}
+
+fn user() -> String {
+    if let Ok(user) = var("USER") {
+        return user;
+    }
+
+    unsafe {
+        let passwd = libc::getpwuid(libc::geteuid());
+
+        if passwd.is_null() {
+            return String::new();
+        }
+
+        CStr::from_ptr((*passwd).pw_name).to_string_lossy().into_owned()
+    }
+}
+
+fn host() -> String {
+    let mut buffer = [0u8; 256];
+
+    unsafe {
+        if libc::gethostname(buffer.as_mut_ptr() as *mut libc::c_char, buffer.len()) != 0 {
+            return String::new();
+        }
+    }
+
+    CStr::from_bytes_until_nul(&buffer)
+        .map(|host| host.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+/// Working directory with home directory replaced with `~`
+pub fn abbreviated_directory() -> String {
+    abbreviate_home(working_directory().display().to_string())
+}
+
+/// Replaces home directory at the start of the path with `~`
+pub fn abbreviate_home(directory: String) -> String {
+    match var("HOME") {
+        Ok(home) if !home.is_empty() && directory.starts_with(&home) => {
+            match &directory[home.len()..] {
+                "" => String::from("~"),
+                rest if rest.starts_with(MAIN_SEPARATOR) => format!("~{rest}"),
+                _ => directory
+            }
+        },
+        _ => directory
+    }
+}
+
+/// Keeps only the last `components` of the path, `0` keeps the whole path
+fn truncate_path(path: &str, components: usize) -> String {
+    let parts: Vec<&str> = path.split(MAIN_SEPARATOR).filter(|part| !part.is_empty()).collect();
+
+    if components == 0 || parts.len() <= components {
+        return path.to_string();
+    }
+
+    format!("…{MAIN_SEPARATOR}{}", parts[parts.len() - components..].join(MAIN_SEPARATOR_STR))
+}
+
+/// Formats duration as `350ms`, `2.4s`, `5m3s` or `1h20m5s`
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+
+    match seconds {
+        0 => format!("{}ms", duration.as_millis()),
+        1..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m{}s", seconds / 60, seconds % 60),
+        _ => format!("{}h{}m{}s", seconds / 3600, seconds % 3600 / 60, seconds % 60)
+    }
+}
+
+/// Formats current local time with `strftime(3)` format
+pub fn format_time(format: &str) -> String {
+    format_timestamp(format, unsafe { libc::time(std::ptr::null_mut()) })
+}
+
+/// Formats Unix time as local time with `strftime(3)` format
+pub fn format_timestamp(format: &str, time: libc::time_t) -> String {
+    let mut buffer = [0u8; 64];
+
+    unsafe {
+        let mut tm = MaybeUninit::<libc::tm>::zeroed();
+
+        if libc::localtime_r(&time, tm.as_mut_ptr()).is_null() {
+            return String::new();
+        }
+
+        let format = format!("{format}\0");
+
+        let length = libc::strftime(
+            buffer.as_mut_ptr() as *mut libc::c_char,
+            buffer.len(),
+            format.as_ptr() as *const libc::c_char,
+            tm.as_ptr()
+        );
+
+        String::from_utf8_lossy(&buffer[..length]).into_owned()
+    }
+}
@@ -0,0 +1,319 @@
+//! Front end of the `rsh` binary
+//!
+//! Parses the command line, loads the config and the profile,
+//! and runs the script, the command or the interactive loop.
+
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::process::exit;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use rustyline::{Editor, EventHandler, KeyCode, KeyEvent, Modifiers};
+use rustyline::error::ReadlineError;
+use crate::{debugger, formatter, history, hooks, jobs, lint, lsp, picker, signals};
+use crate::cli::*;
+use crate::colors::*;
+use crate::config::{self, *};
+use crate::editor::{AcceptHint, OpenPicker, RshHelper, erase_input};
+use crate::jobs::reap_jobs;
+use crate::parse_command::parse_command;
+use crate::prompt::expand_prompt;
+use crate::execute::*;
+use crate::shell::Shell;
+use crate::utils::*;
+
+/// Runs the shell with the command line arguments, `args[0]`
+/// is the name it is started with. Exits the process
+pub fn run<I>(args: I) -> !
+where I: Iterator<Item = String> {
+    let options = match parse_options(args) {
+        Ok(Action::Run(options)) => options,
+        Ok(Action::Check { inputs, json, strict, check_env }) => {
+            // output piped to `head` must not panic
+            unsafe {
+                libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+            }
+
+            exit(lint::check_scripts(inputs, json, strict, check_env))
+        },
+        Ok(Action::Format { files, check }) => {
+            unsafe {
+                libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+            }
+
+            exit(formatter::format_files(files, check))
+        },
+        Ok(Action::Lsp) => exit(lsp::run_server()),
+        Ok(Action::Help) => {
+            println!("{USAGE}\n{HELP}");
+            exit(0)
+        },
+        Ok(Action::Version) => {
+            println!("rsh {}", env!("CARGO_PKG_VERSION"));
+            exit(0)
+        },
+        Err(err) => {
+            error_log(err);
+            eprintln!("{USAGE}");
+            exit(2)
+        }
+    };
+
+    let home: String = env::var("HOME").unwrap();
+    let profile_path: &str = &format!("{home}/.rsh_profile");
+    let config_path: &str = &format!("{home}/.rshrc");
+    let rsh_internal_err = format!("{}: unexpected internal error", red("rsh"));
+
+    let mut shell = Shell::new();
+    shell.set_args(&options.name, &options.args);
+
+    let interactive = options.interactive || matches!(options.input, Input::Stdin) && unsafe {
+        libc::isatty(libc::STDIN_FILENO) == 1
+    };
+
+    signals::init_signals(interactive);
+
+    // config is loaded by the same shells as profile, so
+    // its aliases and options don't affect scripts
+    set_config(if options.rc && (interactive || options.login) {
+        load_config(config_path, &home)
+    } else {
+        Config::new(&home)
+    });
+
+    let config = config::config();
+
+    env::set_var("PS1", &config.prompt);
+    env::set_var("PS2", &config.continuation_prompt);
+    env::set_var("RPROMPT", &config.right_prompt);
+
+    let shell_level = env::var("SHLVL").ok()
+        .and_then(|level| level.parse::<u32>().ok())
+        .unwrap_or(0);
+
+    env::set_var("SHLVL", (shell_level + 1).to_string());
+
+    if options.profile && (interactive || options.login) {
+        if !Path::new(profile_path).exists() {
+            File::create(profile_path).expect(&rsh_internal_err);
+        }
+
+        match shell.run_file(profile_path) {
+            ExecutionResult::Error(err) => {
+                error_log(err);
+                println!("the above error occurred in profile: {profile_path}")
+            },
+            ExecutionResult::Exit(code) => signals::exit_with(code),
+            _ => {}
+        }
+    }
+
+    env::set_var("profile", profile_path);
+
+    if options.debug {
+        debugger::enable(match &options.input {
+            Input::File(path) => path,
+            _ => "-c"
+        });
+    }
+
+    match options.input {
+        Input::Command(command) => signals::exit_with(shell.run_script(&command, "-c").status()),
+        Input::File(path) => match shell.run_file(&path) {
+            ExecutionResult::Error(err) => {
+                error_log(Box::<dyn Error>::from(format!("{path}: {err}")));
+                exit(1)
+            },
+            result => signals::exit_with(result.status())
+        },
+        Input::Stdin if !interactive => {
+            let mut code = String::new();
+
+            if let Err(err) = io::stdin().read_to_string(&mut code) {
+                error_log(Box::new(err));
+                exit(1)
+            }
+
+            signals::exit_with(shell.run_script(&code, "stdin").status())
+        },
+        Input::Stdin => {}
+    }
+
+    let editor_config = rustyline::Config::builder()
+        .max_history_size(config.history_size)
+        // duplicates are handled by `history` module
+        .history_ignore_dups(false)
+        .edit_mode(config.edit_mode)
+        .completion_type(rustyline::CompletionType::List)
+        .build();
+
+    let mut rl = Editor::<RshHelper>::with_config(editor_config)
+        .expect(&rsh_internal_err);
+
+    rl.set_helper(Some(RshHelper::default()));
+    rl.bind_sequence(KeyEvent(KeyCode::End, Modifiers::NONE), EventHandler::Conditional(Box::new(AcceptHint)));
+
+    // line that was typed when the history picker was requested
+    let picker_request = Arc::new(Mutex::new(None));
+    rl.bind_sequence(KeyEvent::ctrl('R'), EventHandler::Conditional(Box::new(OpenPicker(picker_request.clone()))));
+
+    if let Err(err) = history::load_history() {
+        error_log(Box::<dyn Error>::from(format!("{}: {err}", config.history_path)));
+    }
+
+    reload_history(&mut rl);
+
+    // line the editor starts with, e.g. command from the picker
+    let mut initial = String::new();
+
+    loop
+    {
+        reap_jobs();
+
+        // traps of signals that arrived after the last command
+        if let ExecutionResult::Exit(code) = signals::check_signals() {
+            exit_shell(code)
+        }
+
+        signals::clear_interrupt();
+
+        if let ExecutionResult::Exit(code) = hooks::run_hooks("precmd", &[]) {
+            exit_shell(code)
+        }
+
+        let (prompt, right_prompt) = generate_prompt();
+
+        if let Some(helper) = rl.helper_mut() {
+            helper.right_prompt = right_prompt;
+        }
+
+        let line = rl.readline_with_initial(&prompt, (&initial, ""));
+        initial.clear();
+
+        let mut input = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => {
+                if let Some(line) = picker_request.lock().unwrap().take() {
+                    erase_input(&prompt, &line);
+                    initial = picker::pick_history(&line).unwrap_or(line);
+                    continue;
+                }
+
+                set_exit_status(130);
+                continue;
+            },
+            Err(ReadlineError::Eof) => {
+                break;
+            },
+            Err(_) => {
+                println!("{rsh_internal_err}");
+                break;
+            }
+        };
+
+        // editor validates the input only in terminal,
+        // otherwise the rest of the command is read here
+        while parse_command(&input).is_err_and(|err| err.incomplete) {
+            let continuation_prompt = expand_prompt(&env::var("PS2").unwrap_or_default());
+
+            match rl.readline(&continuation_prompt) {
+                Ok(line) => {
+                    input.push('\n');
+                    input += &line;
+                },
+                Err(_) => break
+            }
+        }
+
+        match history::expand_history(&input) {
+            Ok(Some(expanded)) => {
+                println!("{expanded}");
+                input = expanded;
+            },
+            Ok(None) => {},
+            Err(err) => {
+                error_log(err);
+                set_exit_status(1);
+                continue;
+            }
+        }
+
+        let record = history::should_record(&input);
+
+        if record {
+            rl.add_history_entry(input.as_str());
+        }
+
+        if !input.trim().is_empty() {
+            if let ExecutionResult::Exit(code) = hooks::run_hooks("preexec", &[input.clone()]) {
+                exit_shell(code)
+            }
+        }
+
+        let directory = env::current_dir().unwrap_or_default();
+        let started = history::now();
+        let start = Instant::now();
+        let result = shell.run_line(&input);
+        let duration = start.elapsed();
+
+        // `Ctrl-C` stops only the current command line
+        signals::clear_interrupt();
+
+        env::set_var("CMD_DURATION", duration.as_millis().to_string());
+
+        if record {
+            history::record(history::Entry {
+                command: input,
+                start: started,
+                duration,
+                status: result.status(),
+                directory: directory.display().to_string()
+            });
+        }
+
+        // entries were deleted with `history` builtin
+        if history::take_modified() {
+            reload_history(&mut rl);
+        }
+
+        if let ExecutionResult::Exit(code) = result {
+            exit_shell(code)
+        }
+    }
+
+    exit_shell(last_exit_status())
+}
+
+/// Calls `exit` hooks, sends `HUP` to background jobs
+/// and exits the interactive shell
+fn exit_shell(code: i32) -> ! {
+    let code = match hooks::run_hooks("exit", &[code.to_string()]) {
+        ExecutionResult::Exit(code) => code,
+        _ => code
+    };
+
+    jobs::signal_jobs(libc::SIGHUP);
+    signals::exit_with(code)
+}
+
+/// Replaces history of the line editor with entries of `history` module
+fn reload_history(rl: &mut Editor<RshHelper>) {
+    rl.clear_history();
+
+    history::with_entries(|entries| {
+        for entry in entries {
+            rl.add_history_entry(entry.command.as_str());
+        }
+    });
+}
+
+/// Generates the left and the right prompts from `PS1` and `RPROMPT` templates
+fn generate_prompt() -> (String, String) {
+    let template = env::var("PS1").unwrap_or_default();
+    let right_template = env::var("RPROMPT").unwrap_or_default();
+
+    (expand_prompt(&template), expand_prompt(&right_template))
+}
@@ -0,0 +1,202 @@
+//! Embeddable interpreter
+
+use std::env::{remove_var, set_var, var, vars};
+use std::error::Error;
+use std::io::{self, Write};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::sync::Arc;
+use crate::builtins::{self, CustomBuiltin};
+use crate::cli::set_positional_args;
+use crate::diagnostics::define_function;
+use crate::directories::init_working_directory;
+use crate::execute::{ExecutionResult, execute_code, execute_file, execute_script};
+use crate::instants::{self, CustomInstant};
+use crate::utils::{exec_function, is_function, last_exit_status, set_exit_status};
+
+/// Handle of the interpreter
+///
+/// State of the interpreter (variables, functions, aliases, jobs,
+/// traps, custom builtins) is global to the process, as variables
+/// are environment variables of the process. All `Shell` values
+/// share it, so they are handles rather than separate interpreters.
+///
+/// Custom standard streams are file descriptors that replace
+/// descriptors 0, 1 and 2 while the code is running, so they are
+/// used by both builtins and external commands.
+pub struct Shell {
+    /// Replacements of standard input, output and error
+    streams: [Option<OwnedFd>; 3]
+}
+
+impl Default for Shell {
+    fn default() -> Shell {
+        Shell::new()
+    }
+}
+
+impl Shell {
+    /// Sets `PWD` and `$?` like the shell does when it starts
+    pub fn new() -> Shell {
+        init_working_directory();
+        set_exit_status(0);
+
+        Shell { streams: [None, None, None] }
+    }
+
+    /// Executes the command line, like the interactive shell
+    ///
+    /// Errors are printed to the standard error. `exit`
+    /// doesn't exit the process, it returns `Exit` result
+    pub fn run_line(&self, line: &str) -> ExecutionResult {
+        self.with_streams(|| execute_code(line))
+    }
+
+    /// Executes the script, `name` is the file shown in its errors
+    pub fn run_script(&self, code: &str, name: &str) -> ExecutionResult {
+        self.with_streams(|| execute_script(code, name))
+    }
+
+    /// Executes the script file, returns `Error` if it can't be read
+    pub fn run_file<P: AsRef<Path>>(&self, path: P) -> ExecutionResult {
+        self.with_streams(|| execute_file(path))
+    }
+
+    pub fn var(&self, name: &str) -> Option<String> {
+        var(name).ok()
+    }
+
+    pub fn set_var(&mut self, name: &str, value: &str) {
+        set_var(name, value);
+    }
+
+    pub fn unset_var(&mut self, name: &str) {
+        remove_var(name);
+    }
+
+    /// Sets `$0` to the name and `$1`, `$2`, ... to the arguments
+    pub fn set_args(&mut self, name: &str, args: &[String]) {
+        set_positional_args(name, args);
+    }
+
+    /// Exit status of the last command (`$?`)
+    pub fn last_status(&self) -> i32 {
+        last_exit_status()
+    }
+
+    /// Body of the function
+    pub fn function(&self, name: &str) -> Option<String> {
+        var("__FN_".to_owned() + name).ok()
+    }
+
+    /// Names of the defined functions
+    pub fn functions(&self) -> Vec<String> {
+        let mut names: Vec<String> = vars()
+            .filter_map(|(name, _)| name.strip_prefix("__FN_").map(str::to_string))
+            .collect();
+
+        names.sort();
+        names
+    }
+
+    /// Defines the function, its arguments are the `&name`
+    /// placeholders of the body in the order they appear
+    pub fn define_function(&mut self, name: &str, body: &str) {
+        set_var("__FN_".to_owned() + name, body);
+        define_function(name, None);
+    }
+
+    pub fn call_function(&self, name: &str, args: &[String]) -> ExecutionResult {
+        if !is_function(name) {
+            return ExecutionResult::Error(Box::<dyn Error>::from(format!("{name}: function not found")));
+        }
+
+        self.with_streams(|| exec_function(name, args))
+    }
+
+    /// Replaces the standard input of the executed code
+    pub fn set_stdin(&mut self, stdin: impl Into<OwnedFd>) {
+        self.streams[0] = Some(stdin.into());
+    }
+
+    /// Replaces the standard output of the executed code
+    pub fn set_stdout(&mut self, stdout: impl Into<OwnedFd>) {
+        self.streams[1] = Some(stdout.into());
+    }
+
+    /// Replaces the standard error of the executed code
+    pub fn set_stderr(&mut self, stderr: impl Into<OwnedFd>) {
+        self.streams[2] = Some(stderr.into());
+    }
+
+    /// Registers the builtin, which is called with the expanded
+    /// arguments. Its name can't be a keyword or a built-in builtin,
+    /// and it takes precedence over functions and external commands
+    pub fn register_builtin<F>(&mut self, name: &str, builtin: F) -> Result<(), Box<dyn Error>>
+    where F: Fn(&[String]) -> ExecutionResult + Send + Sync + 'static {
+        let builtin: CustomBuiltin = Arc::new(builtin);
+
+        builtins::register_builtin(name, builtin)
+    }
+
+    /// Registers the instant, which is called with the output of
+    /// the previous command of the pipeline (if any) and the
+    /// expanded arguments. Its name must start with `@`
+    pub fn register_instant<F>(&mut self, name: &str, instant: F) -> Result<(), Box<dyn Error>>
+    where F: Fn(Option<String>, &[String]) -> ExecutionResult + Send + Sync + 'static {
+        let instant: CustomInstant = Arc::new(instant);
+
+        instants::register_instant(name, instant)
+    }
+
+    /// Runs the code with the custom standard streams
+    fn with_streams<T>(&self, run: impl FnOnce() -> T) -> T {
+        if self.streams.iter().all(Option::is_none) {
+            return run();
+        }
+
+        // output written so far goes to the previous streams
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+
+        let mut saved = [None; 3];
+
+        for (fd, stream) in self.streams.iter().enumerate() {
+            if let Some(stream) = stream {
+                let fd = fd as i32;
+
+                unsafe {
+                    let original = libc::dup(fd);
+                    saved[fd as usize] = (original >= 0).then_some(original);
+                    libc::dup2(stream.as_raw_fd(), fd);
+                }
+            }
+        }
+
+        let result = run();
+
+        let _ = io::stdout().flush();
+        let _ = io::stderr().flush();
+
+        for (fd, stream) in self.streams.iter().enumerate() {
+            if stream.is_none() {
+                continue;
+            }
+
+            // descriptor that wasn't open before is closed again
+            unsafe {
+                match saved[fd] {
+                    Some(original) => {
+                        libc::dup2(original, fd as i32);
+                        libc::close(original);
+                    },
+                    None => {
+                        libc::close(fd as i32);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+}
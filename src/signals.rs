@@ -0,0 +1,372 @@
+//! Signal handling and traps
+//!
+//! Signals are not handled when they arrive. The handler only
+//! marks the signal as pending, and the shell checks pending
+//! signals between commands, where it runs the trap code set
+//! with `trap` builtin, or the default action:
+//!
+//! | signal | interactive shell | script |
+//! |:----|:----|:----|
+//! | `INT` | interrupts the command line | exits with status 130 |
+//! | `HUP` | sends `HUP` to jobs and exits | terminates |
+//! | `TERM`, `QUIT` | ignored | terminate |
+//! | `USR1`, `USR2` | terminate | terminate |
+//!
+//! Interrupted command line stops at the next command, so
+//! `Ctrl-C` breaks loops and functions, not only the running
+//! process. Besides signals, `EXIT` trap runs when the shell
+//! exits, and `ERR` trap runs when a command fails where it
+//! would trigger errexit.
+//!
+//! Traps are not inherited by child processes. Children get
+//! the default dispositions, except signals ignored with
+//! `trap "" SIG`, and background jobs ignore `INT` and `QUIT`.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::process::exit;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use libc::{c_int, sighandler_t};
+use crate::execute::{ExecutionResult, execute_code};
+use crate::utils::{error_log, last_exit_status, set_exit_status};
+
+/// Signals that can be trapped
+const SIGNALS: [(c_int, &str); 5] = [
+    (libc::SIGHUP, "HUP"),
+    (libc::SIGINT, "INT"),
+    (libc::SIGUSR1, "USR1"),
+    (libc::SIGUSR2, "USR2"),
+    (libc::SIGTERM, "TERM")
+];
+
+/// Status of the command interrupted with `Ctrl-C`
+const INTERRUPTED_STATUS: i32 = 128 + libc::SIGINT;
+
+/// Signals that arrived and are not handled yet, by number
+static PENDING: [AtomicBool; 32] = [const { AtomicBool::new(false) }; 32];
+
+/// Command line was interrupted and must stop
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+static INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Trap code by the name of the signal, `EXIT` or `ERR`.
+/// Empty code means the signal is ignored
+static TRAPS: Mutex<BTreeMap<&'static str, String>> = Mutex::new(BTreeMap::new());
+
+/// `ERR` trap is running, so its own failures don't call it again
+static RUNNING_ERR: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn mark_pending(signal: c_int) {
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Whether the shell reads commands from the terminal
+pub fn is_interactive() -> bool {
+    INTERACTIVE.load(Ordering::Relaxed)
+}
+
+/// Sets dispositions of the signals when the shell starts
+pub fn init_signals(interactive: bool) {
+    INTERACTIVE.store(interactive, Ordering::Relaxed);
+
+    for (signal, _) in SIGNALS {
+        set_disposition(signal, default_disposition(signal));
+    }
+
+    if interactive {
+        set_disposition(libc::SIGQUIT, libc::SIG_IGN);
+    }
+}
+
+fn set_disposition(signal: c_int, disposition: sighandler_t) {
+    unsafe {
+        libc::signal(signal, disposition);
+    }
+}
+
+/// Disposition of the signal in the shell when it is not trapped
+fn default_disposition(signal: c_int) -> sighandler_t {
+    let interactive = INTERACTIVE.load(Ordering::Relaxed);
+
+    match signal {
+        libc::SIGINT => mark_pending as extern "C" fn(c_int) as sighandler_t,
+        libc::SIGHUP if interactive => mark_pending as extern "C" fn(c_int) as sighandler_t,
+        libc::SIGTERM if interactive => libc::SIG_IGN,
+        _ => libc::SIG_DFL
+    }
+}
+
+/// Dispositions that children set before `exec`
+pub fn child_dispositions(background: bool) -> Vec<(c_int, sighandler_t)> {
+    let traps = TRAPS.lock().unwrap();
+
+    let mut dispositions: Vec<(c_int, sighandler_t)> = SIGNALS.iter()
+        .map(|(signal, name)| match traps.get(name) {
+            Some(code) if code.is_empty() => (*signal, libc::SIG_IGN),
+            _ => (*signal, libc::SIG_DFL)
+        })
+        .collect();
+
+    dispositions.push((libc::SIGQUIT, libc::SIG_DFL));
+    dispositions.push((libc::SIGPIPE, libc::SIG_DFL));
+
+    // `Ctrl-C` in the terminal must not stop background jobs
+    if background {
+        dispositions.push((libc::SIGINT, libc::SIG_IGN));
+        dispositions.push((libc::SIGQUIT, libc::SIG_IGN));
+    }
+
+    dispositions
+}
+
+/// Runs traps of the pending signals
+///
+/// Returns `Failure` if the command line is interrupted and must
+/// stop, or `Exit` if the shell must exit
+pub fn check_signals() -> ExecutionResult {
+    for (signal, name) in SIGNALS {
+        if !PENDING[signal as usize].swap(false, Ordering::SeqCst) {
+            continue;
+        }
+
+        let trap = TRAPS.lock().unwrap().get(name).cloned();
+
+        match trap {
+            Some(code) => {
+                if let ExecutionResult::Exit(code) = run_trap(&code) {
+                    return ExecutionResult::Exit(code);
+                }
+            },
+            None if signal == libc::SIGINT => INTERRUPTED.store(true, Ordering::SeqCst),
+            None if signal == libc::SIGHUP => return ExecutionResult::Exit(128 + libc::SIGHUP),
+            None => {}
+        }
+    }
+
+    match INTERRUPTED.load(Ordering::SeqCst) {
+        false => ExecutionResult::Success,
+        true if INTERACTIVE.load(Ordering::Relaxed) => ExecutionResult::Failure(INTERRUPTED_STATUS),
+        true => ExecutionResult::Exit(INTERRUPTED_STATUS)
+    }
+}
+
+/// Whether the command line is interrupted
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Allows the next command line to run after the interrupted one
+pub fn clear_interrupt() {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+}
+
+/// Executes trap code, keeping `$?`
+fn run_trap(code: &str) -> ExecutionResult {
+    let status = last_exit_status();
+    let result = execute_code(code);
+
+    set_exit_status(status);
+
+    result
+}
+
+/// Runs `ERR` trap after the command failed
+pub fn run_err_trap() -> ExecutionResult {
+    let trap = TRAPS.lock().unwrap().get("ERR").cloned();
+
+    match trap {
+        Some(code) if !RUNNING_ERR.swap(true, Ordering::SeqCst) => {
+            let result = run_trap(&code);
+            RUNNING_ERR.store(false, Ordering::SeqCst);
+            result
+        },
+        _ => ExecutionResult::Success
+    }
+}
+
+/// Runs `EXIT` trap and exits with the status
+///
+/// If the trap calls `exit`, its status is used instead
+pub fn exit_with(status: i32) -> ! {
+    let trap = TRAPS.lock().unwrap().remove("EXIT");
+
+    if let Some(code) = trap {
+        clear_interrupt();
+        set_exit_status(status);
+
+        if let ExecutionResult::Exit(code) = execute_code(&code) {
+            exit(code)
+        }
+    }
+
+    exit(status)
+}
+
+/// Name of the signal, `EXIT` or `ERR` by its name or number,
+/// e.g. `INT`, `SIGINT`, `int` or `2`
+fn trap_name(word: &str) -> Option<&'static str> {
+    let upper = word.to_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+
+    match name {
+        "EXIT" | "0" => Some("EXIT"),
+        "ERR" => Some("ERR"),
+        _ => SIGNALS.iter()
+            .find(|(signal, signal_name)| *signal_name == name || signal.to_string() == name)
+            .map(|(_, signal_name)| *signal_name)
+    }
+}
+
+/// Implementation of shell built-in `trap` function
+///
+/// ```rsh
+/// trap "echo bye" EXIT    # run code when the shell exits
+/// trap "" INT             # ignore the signal
+/// trap - INT              # restore the default action
+/// trap                    # list traps
+/// trap -l                 # list names of the signals
+/// ```
+pub fn trap_builtin(args: &[String]) -> ExecutionResult {
+    let (code, names) = match args {
+        [] => return print_traps(),
+        [option] if option == "-p" => return print_traps(),
+        [option] if option == "-l" => {
+            for (signal, name) in SIGNALS {
+                println!("{signal:2}) SIG{name}");
+            }
+
+            return ExecutionResult::Success;
+        },
+        // single signal name restores its default action
+        [name] if trap_name(name).is_some() => (None, args),
+        [_] => return ExecutionResult::Error(Box::<dyn Error>::from("trap: signal name required")),
+        [code, names @ ..] if code == "-" => (None, names),
+        [code, names @ ..] => (Some(code), names)
+    };
+
+    let mut result = ExecutionResult::Success;
+
+    for word in names {
+        let Some(name) = trap_name(word) else {
+            error_log(Box::<dyn Error>::from(format!("trap: {word}: invalid signal name")));
+            result = ExecutionResult::Failure(1);
+            continue;
+        };
+
+        match code {
+            Some(code) => TRAPS.lock().unwrap().insert(name, code.clone()),
+            None => TRAPS.lock().unwrap().remove(name)
+        };
+
+        if let Some((signal, _)) = SIGNALS.iter().find(|(_, signal_name)| *signal_name == name) {
+            let disposition = match code {
+                Some(code) if code.is_empty() => libc::SIG_IGN,
+                Some(_) => mark_pending as extern "C" fn(c_int) as sighandler_t,
+                None => default_disposition(*signal)
+            };
+
+            set_disposition(*signal, disposition);
+        }
+    }
+
+    result
+}
+
+fn print_traps() -> ExecutionResult {
+    for (name, code) in TRAPS.lock().unwrap().iter() {
+        println!("trap -- \"{}\" {name}", code.replace('\\', "\\\\").replace('"', "\\\""));
+    }
+
+    ExecutionResult::Success
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::mem::MaybeUninit;
+
+    fn trap(args: &[&str]) -> ExecutionResult {
+        trap_builtin(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+
+    fn traps() -> Vec<(&'static str, String)> {
+        TRAPS.lock().unwrap().iter().map(|(name, code)| (*name, code.clone())).collect()
+    }
+
+    /// Current disposition of the signal in the process
+    fn disposition(signal: c_int) -> sighandler_t {
+        unsafe {
+            let mut action = MaybeUninit::<libc::sigaction>::zeroed();
+            libc::sigaction(signal, std::ptr::null(), action.as_mut_ptr());
+            action.assume_init().sa_sigaction
+        }
+    }
+
+    #[test]
+    fn maps_signal_names() {
+        assert_eq!(trap_name("INT"), Some("INT"));
+        assert_eq!(trap_name("SIGINT"), Some("INT"));
+        assert_eq!(trap_name("int"), Some("INT"));
+        assert_eq!(trap_name("sigterm"), Some("TERM"));
+        assert_eq!(trap_name("2"), Some("INT"));
+        assert_eq!(trap_name(&libc::SIGUSR1.to_string()), Some("USR1"));
+        assert_eq!(trap_name("EXIT"), Some("EXIT"));
+        assert_eq!(trap_name("0"), Some("EXIT"));
+        assert_eq!(trap_name("err"), Some("ERR"));
+    }
+
+    #[test]
+    fn rejects_unknown_signal_names() {
+        assert_eq!(trap_name("KILL"), None);
+        assert_eq!(trap_name("SIG"), None);
+        assert_eq!(trap_name("SIGSIGINT"), None);
+        assert_eq!(trap_name("99"), None);
+        assert_eq!(trap_name(""), None);
+    }
+
+    /// Traps are global, so a single test changes them
+    #[test]
+    fn sets_and_resets_traps() {
+        let handler = mark_pending as extern "C" fn(c_int) as sighandler_t;
+
+        assert!(matches!(trap(&["echo usr1", "USR1", "sigusr2"]), ExecutionResult::Success));
+        assert_eq!(traps(), [("USR1", String::from("echo usr1")), ("USR2", String::from("echo usr1"))]);
+        assert_eq!(disposition(libc::SIGUSR1), handler);
+        assert_eq!(disposition(libc::SIGUSR2), handler);
+
+        // empty code ignores the signal, in children too
+        trap(&["", "USR2"]);
+        assert_eq!(traps()[1], ("USR2", String::new()));
+        assert_eq!(disposition(libc::SIGUSR2), libc::SIG_IGN);
+        assert!(child_dispositions(false).contains(&(libc::SIGUSR2, libc::SIG_IGN)));
+        assert!(child_dispositions(false).contains(&(libc::SIGUSR1, libc::SIG_DFL)));
+
+        // `-` and a single name restore the default action
+        assert!(matches!(trap(&["-", "USR1"]), ExecutionResult::Success));
+        assert!(matches!(trap(&["SIGUSR2"]), ExecutionResult::Success));
+        assert!(traps().is_empty());
+        assert_eq!(disposition(libc::SIGUSR1), libc::SIG_DFL);
+        assert_eq!(disposition(libc::SIGUSR2), libc::SIG_DFL);
+        assert!(child_dispositions(false).contains(&(libc::SIGUSR2, libc::SIG_DFL)));
+
+        // invalid names fail, valid ones are still set
+        assert_eq!(trap(&["echo bye", "NOPE", "EXIT"]).status(), 1);
+        assert_eq!(traps(), [("EXIT", String::from("echo bye"))]);
+        assert!(matches!(trap(&["echo bye"]), ExecutionResult::Error(_)));
+        assert!(matches!(trap(&["-", "EXIT"]), ExecutionResult::Success));
+        assert!(traps().is_empty());
+    }
+
+    #[test]
+    fn background_children_ignore_interrupts() {
+        let dispositions = child_dispositions(true);
+
+        assert!(dispositions.contains(&(libc::SIGINT, libc::SIG_IGN)));
+        assert!(dispositions.contains(&(libc::SIGQUIT, libc::SIG_IGN)));
+        assert!(!child_dispositions(false).contains(&(libc::SIGINT, libc::SIG_IGN)));
+    }
+}
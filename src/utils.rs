@@ -1,14 +1,31 @@
 use std::error::Error;
-use std::env::var;
-use std::fs::File;
-use std::process::Stdio;
-use std::str::SplitWhitespace;
+use std::env::{set_var, var};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, Write};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
 use crate::ExecutionResult;
 use crate::colors::*;
-use crate::execute::execute_code;
+use crate::debugger;
+use crate::diagnostics::{Frame, Substitution, describe, function_origin};
+use crate::execute::execute_in_frame;
+use crate::lexer::{TokenKind, tokenize, unquote};
+use crate::options;
+use crate::parse_command::Span;
 
+/// Expands a word of the command
+///
+/// * `$name` is replaced with value of the variable
+/// * `'text` is a literal string, only `'` is removed
+/// * `~` and `~/path` are expanded to the home directory
+/// * quotes and escape characters are removed from other words
+///
+/// Returns `None` for comments
 pub fn parse_single_argument(argument: &str) -> Option<String> {
-    return match argument.chars().next().unwrap() {
+    match argument.chars().next().unwrap_or_default() {
         '$' => {
             if argument.len() > 1 {
                 let variable = argument.to_string().replace('$', "");
@@ -25,61 +42,218 @@ pub fn parse_single_argument(argument: &str) -> Option<String> {
         },
         '\'' => Some(argument.replacen('\'', "", 1)),
         '#' => None,
-        _ => Some(String::from(argument))
+        '~' if argument == "~" || argument.starts_with("~/") => {
+            Some(var("HOME").unwrap_or_default() + &unquote(&argument[1..]))
+        },
+        _ => Some(unquote(argument))
     }
 }
 
-pub fn parse_args(args: SplitWhitespace) -> Vec<String>
+/// Expands arguments of the command with `parse_single_argument`
+///
+/// Arguments with unquoted wildcards are replaced with matching
+/// paths, unless `noglob` option is set. Wildcard that matches
+/// nothing is kept as is
+pub fn parse_args<'a, I>(args: I) -> Vec<String>
+where I: IntoIterator<Item = &'a String>
 {
     let mut parsed_args: Vec<String> = Vec::new();
 
     for arg in args {
-        match parse_single_argument(arg) {
-            Some(parsed_value) => parsed_args.push(parsed_value),
+        let parsed_value = match parse_single_argument(arg) {
+            Some(parsed_value) => parsed_value,
             None => break
+        };
+
+        let paths = match glob_pattern(arg) {
+            Some(pattern) if !options::is_set("noglob") => expand_wildcard(&pattern),
+            _ => Vec::new()
+        };
+
+        if paths.is_empty() {
+            parsed_args.push(parsed_value);
+        } else {
+            parsed_args.extend(paths);
         }
     }
 
     parsed_args
 }
 
-pub fn generate_stdout(command: Option<&&str>) -> Result<(Stdio, bool), ExecutionResult> {
-    let mut write_to_file = false;
+/// Wildcard pattern of the word, if it has `*` or `?` outside of quotes
+///
+/// The word is unquoted like with `unquote`, and quoted or escaped
+/// `*`, `?` and `\` stay literal, escaped with `\` in the pattern
+fn glob_pattern(word: &str) -> Option<String> {
+    if word.starts_with(['\'', '$']) {
+        return None;
+    }
 
-    let stdio = if command.is_some() {
-        let append = command.unwrap().starts_with("&a");
-            let write = command.unwrap().starts_with("&w");
+    if let Some(path) = word.strip_prefix('~').filter(|path| path.is_empty() || path.starts_with('/')) {
+        let mut pattern = String::new();
+        var("HOME").unwrap_or_default().chars().for_each(|char| push_literal(&mut pattern, char));
 
-            let stdio = if append || write {
-                let filename_untrimmed = command.unwrap().replace("&a ", "").replace("&w ", "");
-                let filename = filename_untrimmed.trim();
+        return glob_pattern(path).map(|path| pattern + &path);
+    }
 
-                let file = match File::options()
-                    .create(!append)
-                    .append(append)
-                    .write(write)
-                    .truncate(write)
-                    .open(filename) {
-                        Ok(file) => file,
-                        Err(err) => return Err(ExecutionResult::Error(Box::new(err)))
-                    };
+    let mut pattern = String::new();
+    let mut chars = word.chars().peekable();
+    let mut quoted = false;
+    let mut wildcard = false;
 
-                write_to_file = true;
-                Stdio::from(file)
-            } else {
-                Stdio::piped()
+    while let Some(char) = chars.next() {
+        match char {
+            '"' => quoted = !quoted,
+            '\\' if quoted => match chars.next_if(|next| *next == '"' || *next == '\\') {
+                Some(escaped) => push_literal(&mut pattern, escaped),
+                None => push_literal(&mut pattern, '\\')
+            },
+            '\\' => {
+                if let Some(escaped) = chars.next().filter(|escaped| *escaped != '\n') {
+                    push_literal(&mut pattern, escaped);
+                }
+            },
+            '*' | '?' if !quoted => {
+                wildcard = true;
+                pattern.push(char);
+            },
+            char => push_literal(&mut pattern, char)
+        }
+    }
+
+    wildcard.then_some(pattern)
+}
+
+/// Adds the character to the pattern so that it matches only itself
+fn push_literal(pattern: &mut String, char: char) {
+    if matches!(char, '*' | '?' | '\\') {
+        pattern.push('\\');
+    }
+
+    pattern.push(char);
+}
+
+/// Component of the pattern without escapes, if it has no wildcards
+fn literal_component(component: &str) -> Option<String> {
+    let mut literal = String::new();
+    let mut chars = component.chars();
+
+    while let Some(char) = chars.next() {
+        match char {
+            '\\' => literal.extend(chars.next()),
+            '*' | '?' => return None,
+            char => literal.push(char)
+        }
+    }
+
+    Some(literal)
+}
+
+/// Sorted paths that match the pattern, e.g. `src/*.rs`
+/// (see `matches_pattern`)
+///
+/// Each component of the path is matched separately, so `*`
+/// doesn't match `/`. Hidden files match only if the component
+/// starts with `.`, and trailing `/` matches only directories
+fn expand_wildcard(pattern: &str) -> Vec<String> {
+    let mut paths = vec![if pattern.starts_with('/') { String::from("/") } else { String::new() }];
+    let components: Vec<&str> = pattern.split('/').filter(|component| !component.is_empty()).collect();
+
+    for component in components {
+        let mut matched = Vec::new();
+
+        for path in paths {
+            let prefix = if path.is_empty() || path.ends_with('/') { path } else { path + "/" };
+
+            if let Some(literal) = literal_component(component) {
+                matched.push(prefix + &literal);
+                continue;
+            }
+
+            let directory = if prefix.is_empty() { "." } else { &prefix };
+
+            let Ok(entries) = fs::read_dir(directory) else {
+                continue;
             };
 
-            stdio
-    } else {
-        Stdio::inherit()
+            let mut names: Vec<String> = entries
+                .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+                .filter(|name| !name.starts_with('.') || component.starts_with('.'))
+                .filter(|name| matches_pattern(component, name))
+                .collect();
+
+            names.sort();
+            matched.extend(names.into_iter().map(|name| prefix.clone() + &name));
+        }
+
+        paths = matched;
+    }
+
+    if pattern.ends_with('/') {
+        paths.retain(|path| Path::new(path).is_dir());
+        return paths.into_iter().map(|path| path + "/").collect();
+    }
+
+    paths.retain(|path| fs::symlink_metadata(path).is_ok());
+
+    paths
+}
+
+/// Checks that the variable referenced by the word is set,
+/// if `nounset` option is set
+///
+/// Only a whole word `$name` references a variable, other
+/// words with `$` (e.g. `$HOME/bin`) are not expanded
+pub fn check_variable(word: &str) -> Result<(), Box<dyn Error>> {
+    let name = match word.strip_prefix('$') {
+        Some(name) if is_variable_name(name) => name,
+        _ => return Ok(())
     };
 
-    return Ok((stdio, write_to_file));
+    if !options::is_set("nounset") {
+        return Ok(());
+    }
+
+    match var(name) {
+        Ok(_) => Ok(()),
+        Err(_) => Err(Box::<dyn Error>::from(format!("{name}: unbound variable")))
+    }
+}
+
+/// Whether the text is a name of a variable, e.g. `name`, `1` or `?`
+fn is_variable_name(text: &str) -> bool {
+    matches!(text, "?" | "#" | "@")
+        || !text.is_empty() && text.chars().all(|char| char.is_alphanumeric() || char == '_')
 }
 
+/// Prints the error, with its location if it
+/// occurred in the code of a file
 pub fn error_log(error: Box<dyn Error>) {
-    eprintln!("{}: {error}", red("rsh"));
+    eprintln!("{}: {}", red("rsh"), describe(&error.to_string()));
+}
+
+/// Converts exit status of the child process to `ExecutionResult`
+///
+/// Processes terminated by a signal are treated as failed
+/// with status `128 + signal`, as in other shells
+pub fn exit_status_result(status: ExitStatus) -> ExecutionResult {
+    match (status.code(), status.signal()) {
+        (Some(0), _) => ExecutionResult::Success,
+        (Some(code), _) => ExecutionResult::Failure(code),
+        (None, Some(signal)) => ExecutionResult::Failure(128 + signal),
+        (None, None) => ExecutionResult::Failure(1)
+    }
+}
+
+/// Stores exit status of the last command in the `$?` variable
+pub fn set_exit_status(status: i32) {
+    set_var("?", status.to_string());
+}
+
+pub fn last_exit_status() -> i32 {
+    var("?").ok()
+        .and_then(|status| status.parse().ok())
+        .unwrap_or(0)
 }
 
 pub fn get_alias(alias: &str) -> String {
@@ -93,24 +267,302 @@ pub fn get_alias(alias: &str) -> String {
     }
 }
 
+/// Replaces aliases with their values
+///
+/// Only words in command position are replaced, i.e. the first
+/// word of each command and the word after `time` keyword
+pub fn expand_aliases(line: &str) -> String {
+    let mut expanded = String::new();
+    let mut last = 0;
+    let mut command_position = true;
+
+    for token in tokenize(line) {
+        if token.kind == TokenKind::Comment {
+            continue;
+        }
+
+        if token.kind == TokenKind::Word && command_position {
+            expanded += &line[last..token.start];
+            expanded += &get_alias(token.text);
+            last = token.end();
+        }
+
+        command_position = token.is_command_separator()
+            || (command_position && token.text == "time");
+    }
+
+    expanded + &line[last..]
+}
+
 pub fn is_function(name: &str) -> bool {
     var("__FN_".to_owned() + name).is_ok()
 }
 
-pub fn exec_function(function_name: &str, mut args: SplitWhitespace) -> ExecutionResult {
-    let mut function_body = match var("__FN_".to_owned() + function_name) {
+/// Executes the function, replacing its argument
+/// placeholders (`&name`) with the arguments
+pub fn exec_function(function_name: &str, args: &[String]) -> ExecutionResult {
+    let function_body = match var("__FN_".to_owned() + function_name) {
         Ok(code) => code,
         Err(err) => return ExecutionResult::Error(Box::new(err))
     };
 
-    for token in function_body.clone().split_whitespace() {
-        if token.starts_with('&') && !token.starts_with("&&") {
-            match args.next() {
-                Some(value) => function_body = function_body.replace(token.trim(), value),
-                None => break
-            };
+    let placeholders = placeholders(&function_body);
+
+    let mut names: Vec<&str> = Vec::new();
+    let mut code = String::new();
+    let mut substitutions = Vec::new();
+    let mut last = 0;
+
+    for span in placeholders {
+        let name = &function_body[span.start..span.end];
+
+        if !names.contains(&name) {
+            names.push(name);
         }
-    };
 
-    execute_code(&function_body)
+        // placeholders without arguments are kept
+        let Some(value) = names.iter().position(|known| *known == name).and_then(|index| args.get(index)) else {
+            continue;
+        };
+
+        code += &function_body[last..span.start];
+        substitutions.push(Substitution { executed: Span { start: code.len(), end: code.len() + value.len() }, source: span });
+        code += value;
+        last = span.end;
+    }
+
+    code += &function_body[last..];
+
+    let frame = Frame::new(
+        Some(format!("function `{function_name}`")),
+        function_origin(function_name),
+        &function_body,
+        substitutions
+    );
+
+    debugger::function_called(function_name);
+
+    execute_in_frame(frame, &code)
+}
+
+/// Argument placeholders of the function body (`&name`), in
+/// order of appearance. Arguments are assigned to distinct
+/// names in order of their first appearance
+///
+/// `&&`, `&` at the end of the command and `>&` are not placeholders
+fn placeholders(body: &str) -> Vec<Span> {
+    let mut placeholders = Vec::new();
+    let mut previous = ' ';
+    let mut chars = body.char_indices().peekable();
+
+    while let Some((index, char)) = chars.next() {
+        let is_placeholder = char == '&'
+            && !matches!(previous, '&' | '>' | '<')
+            && chars.peek().is_some_and(|(_, next)| next.is_alphanumeric() || *next == '_');
+
+        previous = char;
+
+        if !is_placeholder {
+            continue;
+        }
+
+        let mut end = index + 1;
+
+        while let Some((next_index, next)) = chars.next_if(|(_, next)| next.is_alphanumeric() || *next == '_') {
+            end = next_index + next.len_utf8();
+            previous = next;
+        }
+
+        placeholders.push(Span { start: index, end });
+    }
+
+    placeholders
+}
+
+pub fn is_executable(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+/// Finds executable file of the command in `$PATH`
+///
+/// Commands that contain `/` are paths and are not searched
+pub fn find_executable(command: &str) -> Option<PathBuf> {
+    if command.contains('/') {
+        let path = PathBuf::from(command);
+        return is_executable(&path).then_some(path);
+    }
+
+    var("PATH").unwrap_or_default()
+        .split(':')
+        .filter(|directory| !directory.is_empty())
+        .map(|directory| Path::new(directory).join(command))
+        .find(|path| is_executable(path))
+}
+
+/// Whether the text matches the wildcard pattern, where `*`
+/// matches any text, `?` any character, and `\` makes the
+/// next character match only itself
+pub fn matches_pattern(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // position after the last `*` and the text position it matched up to
+    let mut backtrack = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        // number of pattern characters that match the character of the text
+        let step = match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, t));
+                continue;
+            },
+            Some('\\') if p + 1 < pattern.len() => (pattern[p + 1] == text[t]).then_some(2),
+            Some(char) => (*char == '?' || *char == text[t]).then_some(1),
+            None => None
+        };
+
+        match step {
+            Some(step) => {
+                p += step;
+                t += 1;
+            },
+            None => match backtrack {
+                Some((star, matched)) => {
+                    p = star;
+                    t = matched + 1;
+                    backtrack = Some((star, matched + 1));
+                },
+                None => return false
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|char| *char == '*')
+}
+
+/// Opens the file for reading and appending, creating it if
+/// needed, and locks it until the file is closed
+pub fn open_locked(path: &str) -> io::Result<File> {
+    let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(file)
+}
+
+/// Replaces content of the locked file
+pub fn rewrite_file(file: &mut File, content: &str) -> io::Result<()> {
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(content.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches_pattern("*.rs", "main.rs"));
+        assert!(matches_pattern("*.rs", ".rs"));
+        assert!(matches_pattern("m??n.rs", "main.rs"));
+        assert!(matches_pattern("*", ""));
+        assert!(matches_pattern("a*b*c", "aXbYbZc"));
+        assert!(matches_pattern("a**", "a"));
+        assert!(matches_pattern("*ab", "aab"));
+        assert!(matches_pattern("é?*", "éü"));
+        assert!(matches_pattern("", ""));
+
+        assert!(!matches_pattern("*.rs", "main.rsx"));
+        assert!(!matches_pattern("?", ""));
+        assert!(!matches_pattern("a*b*c", "aXbY"));
+        assert!(!matches_pattern("", "a"));
+        assert!(!matches_pattern("abc", "ab"));
+    }
+
+    #[test]
+    fn matches_escaped_wildcards() {
+        assert!(matches_pattern("\\*x*", "*xy"));
+        assert!(matches_pattern("a\\?", "a?"));
+        assert!(matches_pattern("a\\\\*", "a\\b"));
+        assert!(matches_pattern("a\\", "a\\"));
+
+        assert!(!matches_pattern("\\*x*", "axy"));
+        assert!(!matches_pattern("a\\?", "ab"));
+    }
+
+    #[test]
+    fn builds_patterns_of_unquoted_wildcards() {
+        assert_eq!(glob_pattern("*.rs").as_deref(), Some("*.rs"));
+        assert_eq!(glob_pattern("src/?").as_deref(), Some("src/?"));
+        assert_eq!(glob_pattern("\"a b\"*").as_deref(), Some("a b*"));
+
+        // quoted and escaped wildcards stay literal
+        assert_eq!(glob_pattern("\"*\"x*").as_deref(), Some("\\*x*"));
+        assert_eq!(glob_pattern("\\?a*").as_deref(), Some("\\?a*"));
+        assert_eq!(glob_pattern("\"a\\\\\"*").as_deref(), Some("a\\\\*"));
+
+        assert_eq!(glob_pattern("\"*.rs\""), None);
+        assert_eq!(glob_pattern("\\*"), None);
+        assert_eq!(glob_pattern("'*"), None);
+        assert_eq!(glob_pattern("$x*"), None);
+        assert_eq!(glob_pattern("plain"), None);
+        assert_eq!(glob_pattern("~"), None);
+    }
+
+    #[test]
+    fn checks_only_whole_word_variables() {
+        assert!(is_variable_name("HOME"));
+        assert!(is_variable_name("1"));
+        assert!(is_variable_name("?"));
+        assert!(is_variable_name("my_var2"));
+
+        assert!(!is_variable_name(""));
+        assert!(!is_variable_name("HOME/bin"));
+        assert!(!is_variable_name("a$b"));
+        assert!(!is_variable_name("x-y"));
+    }
+
+    #[test]
+    fn expands_paths_by_components() {
+        let root = std::env::temp_dir().join(format!("rsh-glob-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        for directory in ["src/a", "src/b", "docs"] {
+            fs::create_dir_all(root.join(directory)).unwrap();
+        }
+
+        for file in ["src/main.rs", "src/lib.rs", "src/a/x.rs", "src/.hidden.rs", "docs/readme.md", "*x1", "ax1"] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let root_text = root.display().to_string();
+        let expand = |pattern: &str| -> Vec<String> {
+            expand_wildcard(&format!("{root_text}/{pattern}"))
+                .into_iter()
+                .map(|path| path[root_text.len() + 1..].to_string())
+                .collect()
+        };
+
+        assert_eq!(expand("src/*.rs"), ["src/lib.rs", "src/main.rs"]);
+        assert_eq!(expand("src/.*.rs"), ["src/.hidden.rs"]);
+        assert_eq!(expand("*/*.rs"), ["src/lib.rs", "src/main.rs"]);
+        assert_eq!(expand("src/*/"), ["src/a/", "src/b/"]);
+        assert_eq!(expand("src/*/x.rs"), ["src/a/x.rs"]);
+        assert_eq!(expand("d?cs/*"), ["docs/readme.md"]);
+        assert!(expand("*.txt").is_empty());
+
+        // `"*"x*` matches only names that start with `*`
+        let pattern = glob_pattern(&format!("{root_text}/\"*\"x*")).unwrap();
+        assert_eq!(expand_wildcard(&pattern), [format!("{root_text}/*x1")]);
+        assert_eq!(parse_args(&[format!("{root_text}/\"*\"x*")]), [format!("{root_text}/*x1")]);
+        assert_eq!(expand("\\*x?"), ["*x1"]);
+        assert_eq!(expand("?x1"), ["*x1", "ax1"]);
+
+        fs::remove_dir_all(root).unwrap();
+    }
 }
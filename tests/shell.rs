@@ -0,0 +1,146 @@
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use rsh::{ExecutionResult, Shell};
+
+/// State of the interpreter is global to the process,
+/// so the tests don't run at the same time
+static LOCK: Mutex<()> = Mutex::new(());
+
+fn lock() -> MutexGuard<'static, ()> {
+    LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn temp_file(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("rsh-shell-{}-{name}", std::process::id()))
+}
+
+/// Runs the line with the standard output written to a file, returns the output
+fn output(line: &str) -> (ExecutionResult, String) {
+    let path = temp_file(line.split_whitespace().next().unwrap_or("empty"));
+    let mut shell = Shell::new();
+
+    shell.set_stdout(File::create(&path).unwrap());
+
+    let result = shell.run_line(line);
+    let output = fs::read_to_string(&path).unwrap();
+
+    let _ = fs::remove_file(&path);
+    (result, output)
+}
+
+#[test]
+fn run_line_statuses() {
+    let _lock = lock();
+    let shell = Shell::new();
+
+    assert!(matches!(shell.run_line("true"), ExecutionResult::Success));
+    assert_eq!(shell.run_line("false").status(), 1);
+    assert_eq!(shell.last_status(), 1);
+    assert_eq!(shell.run_line("true && false").status(), 1);
+    assert_eq!(shell.run_line("false; true").status(), 0);
+    assert_eq!(shell.run_line("rsh-test-no-such-command").status(), 127);
+    assert!(matches!(shell.run_line("exit 3"), ExecutionResult::Exit(3)));
+}
+
+#[test]
+fn run_script_statuses() {
+    let _lock = lock();
+    let shell = Shell::new();
+
+    assert_eq!(shell.run_script("true\nfalse\n", "script.rsh").status(), 1);
+    assert_eq!(shell.run_script("if false; then\nexit 4\nelse\ntrue\nfi\n", "script.rsh").status(), 0);
+    assert!(matches!(shell.run_script("true\nexit 5\nfalse\n", "script.rsh"), ExecutionResult::Exit(5)));
+    assert_eq!(shell.run_script("if true; then", "script.rsh").status(), 2);
+    assert!(matches!(shell.run_file(temp_file("missing.rsh")), ExecutionResult::Error(_)));
+}
+
+#[test]
+fn variables() {
+    let _lock = lock();
+    let mut shell = Shell::new();
+
+    shell.set_var("RSH_TEST_NAME", "world");
+    assert_eq!(shell.var("RSH_TEST_NAME").as_deref(), Some("world"));
+
+    let (_, text) = output("echo hello $RSH_TEST_NAME");
+    assert_eq!(text, "hello world\n");
+
+    shell.run_line("set RSH_TEST_SET=value");
+    assert_eq!(shell.var("RSH_TEST_SET").as_deref(), Some("value"));
+
+    shell.unset_var("RSH_TEST_NAME");
+    shell.unset_var("RSH_TEST_SET");
+    assert_eq!(shell.var("RSH_TEST_NAME"), None);
+}
+
+#[test]
+fn functions() {
+    let _lock = lock();
+    let mut shell = Shell::new();
+
+    shell.define_function("rsh_test_greet", "echo hi &who");
+    assert!(shell.functions().contains(&String::from("rsh_test_greet")));
+    assert_eq!(shell.function("rsh_test_greet").as_deref(), Some("echo hi &who"));
+
+    let (result, text) = output("rsh_test_greet you");
+    assert_eq!(result.status(), 0);
+    assert_eq!(text, "hi you\n");
+
+    assert!(matches!(shell.call_function("rsh_test_greet", &[String::from("me")]), ExecutionResult::Success));
+    assert!(matches!(shell.call_function("rsh_test_missing", &[]), ExecutionResult::Error(_)));
+
+    shell.run_script("fn rsh_test_fail {\nfalse\n}\n", "functions.rsh");
+    assert_eq!(shell.call_function("rsh_test_fail", &[]).status(), 1);
+}
+
+#[test]
+fn custom_builtins() {
+    let _lock = lock();
+    let mut shell = Shell::new();
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let recorded = calls.clone();
+
+    shell.register_builtin("rsh_test_builtin", move |args| {
+        recorded.lock().unwrap().push(args.to_vec());
+        ExecutionResult::Failure(args.len() as i32)
+    }).unwrap();
+
+    shell.set_var("RSH_TEST_ARG", "b");
+    assert_eq!(shell.run_line("rsh_test_builtin a $RSH_TEST_ARG").status(), 2);
+    assert_eq!(*calls.lock().unwrap(), [vec![String::from("a"), String::from("b")]]);
+
+    assert!(shell.register_builtin("cd", |_| ExecutionResult::Success).is_err());
+    assert!(shell.register_builtin("if", |_| ExecutionResult::Success).is_err());
+    shell.unset_var("RSH_TEST_ARG");
+}
+
+#[test]
+fn redirected_streams() {
+    let _lock = lock();
+    let input = temp_file("input");
+    let stdout = temp_file("stdout");
+    let stderr = temp_file("stderr");
+
+    fs::write(&input, "line\n").unwrap();
+
+    let mut shell = Shell::new();
+    shell.set_stdin(File::open(&input).unwrap());
+    shell.set_stdout(File::create(&stdout).unwrap());
+    shell.set_stderr(File::create(&stderr).unwrap());
+
+    assert_eq!(shell.run_line("cat | tr a-z A-Z").status(), 0);
+    assert_ne!(shell.run_line("ls /rsh-test-no-such-file").status(), 0);
+
+    assert_eq!(fs::read_to_string(&stdout).unwrap(), "LINE\n");
+    assert!(fs::read_to_string(&stderr).unwrap().contains("/rsh-test-no-such-file"));
+
+    // streams of the other shells are not redirected
+    let (_, text) = output("echo other");
+    assert_eq!(text, "other\n");
+    assert_eq!(fs::read_to_string(&stdout).unwrap(), "LINE\n");
+
+    for path in [input, stdout, stderr] {
+        let _ = fs::remove_file(path);
+    }
+}